@@ -1,6 +1,7 @@
 #![allow(non_snake_case, non_upper_case_globals)]
 
 use nannou::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
 use rust_robotics_algo as rb;
 use rust_robotics_algo::pf::*;
 use rust_robotics_algo::prelude::*;
@@ -89,6 +90,7 @@ struct ParticleFilter {
     h_x_true: Vec<rb::Vector4>,
     h_x_dr: Vec<rb::Vector4>,
     view: ViewState,
+    rng: StdRng,
 }
 
 impl ParticleFilter {
@@ -98,12 +100,13 @@ impl ParticleFilter {
             x_true: zeros!(4, 1),
             x_dr: zeros!(4, 1),
             p_est: zeros!(3, 3),
-            pw: ones!(1, NP) * (1. / NP as f32),
-            px: zeros!(4, NP),
+            pw: PW::from_element(NP, 1. / NP as f32),
+            px: PX::zeros(NP),
             h_x_est: vec![zeros!(4, 1)],
             h_x_true: vec![zeros!(4, 1)],
             h_x_dr: vec![zeros!(4, 1)],
             view: ViewState::new(),
+            rng: StdRng::seed_from_u64(0),
         }
     }
 
@@ -121,8 +124,23 @@ impl ParticleFilter {
 
     pub fn step(&mut self, dt: f32) {
         let u = calc_input();
-        let (z, ud) = observation(&mut self.x_true, &mut self.x_dr, u, &rf_id, dt);
-        self.p_est = pf_localization(&mut self.x_est, &mut self.px, &mut self.pw, z, ud, dt);
+        let (z, ud) = observation(
+            &mut self.rng,
+            &mut self.x_true,
+            &mut self.x_dr,
+            u,
+            &rf_id,
+            dt,
+        );
+        self.p_est = pf_localization(
+            &mut self.rng,
+            &mut self.x_est,
+            &mut self.px,
+            &mut self.pw,
+            z,
+            ud,
+            dt,
+        );
 
         self.update_history();
 
@@ -0,0 +1,184 @@
+pub mod lqr;
+
+pub use crate::control::inverted_pendulum::g;
+pub use crate::control::{StateSpace, LQR};
+use crate::prelude::*;
+
+/// Number of states
+pub const NX: usize = 6;
+/// Number of control input
+pub const NU: usize = 1;
+
+/// Convenience type for denoting state vector
+///
+/// [cart position, cart velocity, rod 1 angle, rod 1 angular velocity,
+/// rod 2 angle, rod 2 angular velocity]
+pub type State = Vector<NX>;
+/// Convenience type for denoting system matrix A
+pub type AMat = Mat<NX, NX>;
+/// Convenience type for denoting input matrix B
+pub type BMat = Mat<NX, NU>;
+/// Convenience type for denoting Q matrix
+pub type QMat = AMat;
+/// Convenience type for denoting R matrix
+pub type RMat = Mat<NU, NU>;
+
+/// Define model parameters and LQR-related parameters of a double inverted
+/// pendulum on a cart.
+///
+/// Each rod is massless with a ball at its end. Rod angles are absolute and
+/// measured from upright, with the same sign convention as
+/// [`inverted_pendulum::Model`](crate::inverted_pendulum::Model).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Model {
+    /// Length of lower bar [m]
+    pub l_bar1: f32,
+    /// Length of upper bar [m]
+    pub l_bar2: f32,
+    /// Mass of cart [kg]
+    pub m_cart: f32,
+    /// Mass of ball at the end of lower bar [kg]
+    pub m_ball1: f32,
+    /// Mass of ball at the end of upper bar [kg]
+    pub m_ball2: f32,
+    /// Q matrix
+    pub Q: QMat,
+    /// R matrix
+    pub R: RMat,
+    /// Tolerance for computing matrix pseudo-inverse
+    pub eps: f32,
+    /// Maximum number of iteration for solving
+    /// Discrete Algebraic Ricatti Equation
+    pub max_iter: u32,
+}
+
+impl Default for Model {
+    fn default() -> Self {
+        Self {
+            l_bar1: 1.0,
+            l_bar2: 1.0,
+            m_cart: 1.0,
+            m_ball1: 0.5,
+            m_ball2: 0.5,
+            eps: 0.01,
+            max_iter: 1000,
+            Q: diag![1., 1., 10., 1., 10., 1.],
+            R: diag![0.01],
+        }
+    }
+}
+
+impl StateSpace<NX, NU> for Model {
+    fn model(&self, dt: f32) -> (AMat, BMat) {
+        let Self {
+            l_bar1: l1,
+            l_bar2: l2,
+            m_ball1: m1,
+            m_ball2: m2,
+            ..
+        } = *self;
+
+        // Linearised equations of motion about upright: M0 * q_acc = G * x + F * u
+        let M0_inv = self
+            .mass_matrix(0.0, 0.0)
+            .try_inverse()
+            .expect("Mass matrix of double inverted pendulum is singular");
+
+        let mut G = zeros!(3, NX);
+        G[(1, 2)] = (m1 + m2) * g * l1;
+        G[(2, 4)] = m2 * g * l2;
+        let F = vector![1., 0., 0.];
+
+        let q_acc = M0_inv * G;
+        let q_acc_u = M0_inv * F;
+
+        let mut A = AMat::zeros();
+        let mut B = BMat::zeros();
+        for i in 0..3 {
+            A[(2 * i, 2 * i + 1)] = 1.0;
+            A.set_row(2 * i + 1, &q_acc.row(i));
+            B[2 * i + 1] = q_acc_u[i];
+        }
+
+        (eye!(NX) + A * dt, B * dt)
+    }
+}
+
+impl Model {
+    /// Mass matrix of the generalised coordinates [x, th1, th2]
+    pub fn mass_matrix(&self, th1: f32, th2: f32) -> Matrix3 {
+        let Self {
+            l_bar1: l1,
+            l_bar2: l2,
+            m_cart: m0,
+            m_ball1: m1,
+            m_ball2: m2,
+            ..
+        } = *self;
+        let (c1, c2, c12) = (cos(th1), cos(th2), cos(th1 - th2));
+
+        matrix![m0 + m1 + m2,         -(m1 + m2) * l1 * c1, -m2 * l2 * c2;
+                -(m1 + m2) * l1 * c1, (m1 + m2) * l1 * l1,  m2 * l1 * l2 * c12;
+                -m2 * l2 * c2,        m2 * l1 * l2 * c12,   m2 * l2 * l2]
+    }
+
+    /// Continuous-time nonlinear dynamics, `dx/dt = f(x, u)`
+    ///
+    /// Derived from the Lagrangian of the cart and the two point masses.
+    pub fn dynamics(&self, x: State, u: f32) -> State {
+        let Self {
+            l_bar1: l1,
+            l_bar2: l2,
+            m_ball1: m1,
+            m_ball2: m2,
+            ..
+        } = *self;
+        let (v, th1, w1, th2, w2) = (x[1], x[2], x[3], x[4], x[5]);
+        let (s1, s2, s12) = (sin(th1), sin(th2), sin(th1 - th2));
+
+        let rhs = vector![
+            u - (m1 + m2) * l1 * s1 * w1 * w1 - m2 * l2 * s2 * w2 * w2,
+            -m2 * l1 * l2 * s12 * w2 * w2 + (m1 + m2) * g * l1 * s1,
+            m2 * l1 * l2 * s12 * w1 * w1 + m2 * g * l2 * s2
+        ];
+        let q_acc = self
+            .mass_matrix(th1, th2)
+            .try_inverse()
+            .expect("Mass matrix of double inverted pendulum is singular")
+            * rhs;
+
+        vector![v, q_acc[0], w1, q_acc[1], w2, q_acc[2]]
+    }
+
+    /// Integrate [`dynamics`](Self::dynamics) over one time step with 4th-order Runge-Kutta
+    pub fn step(&self, x: State, u: f32, dt: f32) -> State {
+        let k1 = self.dynamics(x, u);
+        let k2 = self.dynamics(x + k1 * (dt / 2.), u);
+        let k3 = self.dynamics(x + k2 * (dt / 2.), u);
+        let k4 = self.dynamics(x + k3 * dt, u);
+        x + (k1 + k2 * 2. + k3 * 2. + k4) * (dt / 6.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lqr_stabilizes_nonlinear_plant() {
+        let dt = 0.01;
+        let model = Model::default();
+        let (A, B) = model.model(dt);
+        let K = model.dlqr(A, B);
+        let mut x = vector![0., 0., 0.1, 0., -0.05, 0.];
+
+        for _ in 0..1000 {
+            let u = (-K * x)[0];
+            x = model.step(x, u, dt);
+        }
+        assert!(
+            x[2].abs() < 1e-2 && x[4].abs() < 1e-2,
+            "Rods did not settle: {x}"
+        );
+    }
+}
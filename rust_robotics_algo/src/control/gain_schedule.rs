@@ -0,0 +1,99 @@
+use crate::prelude::*;
+
+/// State-feedback gains computed at a grid of operating points
+///
+/// The scheduling variable can be anything the linearisation depends on, such
+/// as a rod angle or a model parameter. Gains are linearly interpolated between
+/// operating points, and held constant outside of the grid.
+///
+/// Use [`LQR::gain_schedule`](super::LQR::gain_schedule) to compute the gains.
+#[derive(Debug, PartialEq, Clone)]
+pub struct GainSchedule<const N: usize, const M: usize> {
+    /// Scheduling variable at each operating point, in ascending order
+    points: Vec<f32>,
+    /// Gain at each operating point
+    gains: Vec<Mat<M, N>>,
+}
+
+impl<const N: usize, const M: usize> GainSchedule<N, M> {
+    /// Instantiate from gains precomputed at the given operating points.
+    ///
+    /// The operating points do not need to be sorted.
+    pub fn from_gains(points: Vec<f32>, gains: Vec<Mat<M, N>>) -> Self {
+        assert!(
+            !points.is_empty(),
+            "Gain schedule needs at least one operating point"
+        );
+        assert_eq!(
+            points.len(),
+            gains.len(),
+            "Number of operating points and gains are different!"
+        );
+
+        let mut pairs: Vec<(f32, Mat<M, N>)> = points.into_iter().zip(gains).collect();
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let (points, gains) = pairs.into_iter().unzip();
+
+        Self { points, gains }
+    }
+
+    /// Scheduling variable at each operating point
+    pub fn points(&self) -> &[f32] {
+        &self.points
+    }
+
+    /// Gain at each operating point
+    pub fn gains(&self) -> &[Mat<M, N>] {
+        &self.gains
+    }
+
+    /// Gain interpolated at the given value of the scheduling variable
+    pub fn gain(&self, sigma: f32) -> Mat<M, N> {
+        // Number of operating points at or below `sigma`. A NaN, e.g. from a
+        // diverged state, compares false with all of them and gets the first gain.
+        let i = self.points.partition_point(|&p| p <= sigma);
+        if i == 0 {
+            return self.gains[0];
+        }
+        if i == self.points.len() {
+            return self.gains[i - 1];
+        }
+
+        let (p0, p1) = (self.points[i - 1], self.points[i]);
+        let t = (sigma - p0) / (p1 - p0);
+        self.gains[i - 1] * (1.0 - t) + self.gains[i] * t
+    }
+
+    /// State-feedback control `u = -K(sigma) * x`
+    pub fn control(&self, sigma: f32, x: Vector<N>) -> Vector<M> {
+        -self.gain(sigma) * x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::{StateSpace, LQR};
+    use crate::inverted_pendulum::{Model, NU, NX};
+
+    #[test]
+    fn interpolates_between_operating_points() {
+        let dt = 0.01;
+        let model = Model::default();
+        let angles = [0.2, -0.2, 0.0];
+        let schedule: GainSchedule<NX, NU> = model.gain_schedule(&angles, |th| {
+            model.linearize(vector![0., 0., th, 0.], 0.0, dt)
+        });
+
+        assert_eq!(schedule.points(), &[-0.2, 0.0, 0.2]);
+
+        let (A, B) = model.model(dt);
+        let K0 = model.dlqr(A, B);
+        assert!((schedule.gain(0.0) - K0).abs().amax() < 0.05 * K0.abs().amax());
+
+        let K_mid = (schedule.gains()[1] + schedule.gains()[2]) * 0.5;
+        assert!((schedule.gain(0.1) - K_mid).abs().amax() < 1e-4);
+        assert_eq!(schedule.gain(1.0), schedule.gains()[2]);
+        assert_eq!(schedule.gain(f32::NAN), schedule.gains()[0]);
+    }
+}
@@ -0,0 +1,123 @@
+use super::*;
+
+/// Initial covariance of the mass estimates
+const P0: f32 = 10.0;
+/// Regressors with smaller norm than this carry too little excitation to adapt on
+const MIN_EXCITATION: f32 = 1e-2;
+
+/// Adaptive LQR for the inverted pendulum with unknown cart and ball mass
+///
+/// This is an indirect adaptive controller. The masses are estimated online by
+/// recursive least squares on the dynamics of [`Model`], which are linear in
+/// `m_cart` and `m_ball`:
+///
+/// m_cart * a_x = m_ball * g * th + u
+/// m_cart * (l_bar * a_th - g * th) - m_ball * g * th = u
+///
+/// The LQR gain is then recomputed from the latest estimates at every sample
+/// time (certainty equivalence).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AdaptiveLQR {
+    /// Model used for control. `m_cart` and `m_ball` hold the running estimates.
+    pub model: Model,
+    /// Initial guess of [m_cart, m_ball]
+    pub initial: Vector2,
+    /// Forgetting factor of the recursive least squares, in (0, 1]
+    pub forgetting: f32,
+    /// Covariance of the mass estimates
+    P: Matrix2,
+    /// State and control input from the previous sample time
+    prev: Option<(Vector4, f32)>,
+}
+
+impl Default for AdaptiveLQR {
+    fn default() -> Self {
+        Self::new(Model::default())
+    }
+}
+
+impl AdaptiveLQR {
+    /// Instantiate with the masses of `model` as the initial guess
+    pub fn new(model: Model) -> Self {
+        Self {
+            model,
+            initial: vector![model.m_cart, model.m_ball],
+            forgetting: 0.995,
+            P: Matrix2::identity() * P0,
+            prev: None,
+        }
+    }
+
+    /// Current estimate of [m_cart, m_ball]
+    pub fn estimates(&self) -> Vector2 {
+        vector![self.model.m_cart, self.model.m_ball]
+    }
+
+    /// Update the mass estimates from the latest state, then compute control input
+    pub fn control(&mut self, x: Vector4, dt: f32) -> f32 {
+        if let Some((x_prev, u_prev)) = self.prev {
+            self.adapt(x_prev, u_prev, x, dt);
+        }
+        let u = self.model.control(x, dt)[0];
+        self.prev = Some((x, u));
+        u
+    }
+
+    /// Reset the estimates to the initial guess
+    pub fn reset_state(&mut self) {
+        self.model.m_cart = self.initial[0];
+        self.model.m_ball = self.initial[1];
+        self.P = Matrix2::identity() * P0;
+        self.prev = None;
+    }
+
+    fn adapt(&mut self, x0: Vector4, u: f32, x1: Vector4, dt: f32) {
+        let th = x0[2];
+        let a_x = (x1[1] - x0[1]) / dt;
+        let a_th = (x1[3] - x0[3]) / dt;
+        let l = self.model.l_bar;
+
+        let regressors = [vector![a_x, -g * th], vector![l * a_th - g * th, -g * th]];
+
+        let mut theta = self.estimates();
+        for phi in regressors.iter() {
+            if phi.norm() < MIN_EXCITATION {
+                continue;
+            }
+            let P_phi = self.P * phi;
+            let k = P_phi / (self.forgetting + phi.dot(&P_phi));
+            theta += k * (u - phi.dot(&theta));
+            self.P = (self.P - k * P_phi.transpose()) / self.forgetting;
+        }
+
+        self.model.m_cart = theta[0].clamp(0.1, 10.0);
+        self.model.m_ball = theta[1].clamp(0.1, 10.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_converge_to_plant_masses() {
+        let dt = 0.01;
+        let plant = Model {
+            m_cart: 1.5,
+            m_ball: 3.0,
+            ..Default::default()
+        };
+        let (A, B) = plant.model(dt);
+        let mut controller = AdaptiveLQR::default();
+        let mut x = vector![0., 0., 0.3, 0.];
+
+        for _ in 0..500 {
+            let u = controller.control(x, dt);
+            x = A * x + B * u;
+        }
+        let est = controller.estimates();
+        assert!((est[0] - 1.5).abs() < 0.15, "m_cart estimate: {}", est[0]);
+        assert!((est[1] - 3.0).abs() < 0.3, "m_ball estimate: {}", est[1]);
+        assert!(x[2].abs() < 0.05);
+    }
+}
@@ -0,0 +1,92 @@
+use super::*;
+use crate::control::KalmanFilter;
+
+/// Number of measured outputs (cart position and rod angle)
+pub const NY: usize = 2;
+
+/// Convenience type for denoting measurement matrix C
+pub type CMat = Mat<NY, NX>;
+
+/// Linear-Quadratic-Gaussian controller for the inverted pendulum
+///
+/// The full state is estimated by a [`KalmanFilter`] from noisy measurements
+/// of cart position and rod angle, and the estimate is fed back through the
+/// [`LQR`] gain of [`Model`].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LQG {
+    /// Model and LQR-related parameters
+    pub model: Model,
+    /// State estimator
+    pub kf: KalmanFilter<NX, NU, NY>,
+    /// Control input applied at the previous sample time
+    u: Vector<NU>,
+}
+
+impl Default for LQG {
+    fn default() -> Self {
+        Self::new(Model::default())
+    }
+}
+
+impl LQG {
+    pub fn new(model: Model) -> Self {
+        let C: CMat = matrix![1., 0., 0., 0.;
+                              0., 0., 1., 0.];
+        let Q = diag![1e-4, 1e-3, 1e-4, 1e-3];
+        let R = diag![0.05_f32.powi(2), 0.02_f32.powi(2)];
+
+        Self {
+            model,
+            kf: KalmanFilter::new(C, Q, R),
+            u: zeros!(NU, 1),
+        }
+    }
+
+    /// Compute control input from measured cart position and rod angle
+    pub fn control(&mut self, y: Vector<NY>, dt: f32) -> f32 {
+        self.kf.predict(&self.model, self.u, dt);
+        self.kf.update(y);
+
+        self.u = self.model.control(self.kf.x, dt);
+        self.u[0]
+    }
+
+    /// Current estimate of the full state
+    pub fn estimate(&self) -> Vector4 {
+        self.kf.x
+    }
+
+    /// Reset the estimator and the memory of the last control input
+    pub fn reset_state(&mut self) {
+        self.kf.reset_state();
+        self.u = zeros!(NU, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localization::particle_filter::rand_with;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn lqg_stabilizes_from_noisy_measurements() {
+        let dt = 0.01;
+        let mut lqg = LQG::default();
+        let model = lqg.model;
+        let (A, B) = model.model(dt);
+        let mut x = vector![0., 0., 0.2, 0.];
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..1000 {
+            let y = vector![
+                x[0] + 0.05 * rand_with(&mut rng),
+                x[2] + 0.02 * rand_with(&mut rng)
+            ];
+            let u = lqg.control(y, dt);
+            x = A * x + B * u;
+        }
+        assert!(x[2].abs() < 0.05, "Rod angle did not settle: {}", x[2]);
+        assert!((lqg.estimate() - x).abs().amax() < 0.2);
+    }
+}
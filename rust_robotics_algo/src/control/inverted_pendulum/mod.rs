@@ -1,9 +1,13 @@
+pub mod adaptive;
+pub mod lqg;
 pub mod lqr;
 pub mod pid;
 
 #[cfg(not(feature = "libm"))]
 pub mod mpc;
 
+pub use adaptive::*;
+pub use lqg::*;
 pub use lqr::*;
 pub use pid::*;
 
@@ -83,3 +87,42 @@ impl StateSpace<NX, NU> for Model {
         (eye!(NX) + A * dt, B * dt)
     }
 }
+
+impl Model {
+    /// Continuous-time nonlinear dynamics of the cart-pole, `dx/dt = f(x, u)`
+    ///
+    /// [`StateSpace::model`] is the linearisation of these dynamics about the
+    /// upright equilibrium, and shares its sign convention.
+    pub fn dynamics(&self, x: Vector4, u: f32) -> Vector4 {
+        let Self {
+            l_bar,
+            m_cart: m_c,
+            m_ball: m_b,
+            ..
+        } = *self;
+        let (v, th, w) = (x[1], x[2], x[3]);
+        let (s, c) = (sin(th), cos(th));
+
+        let den = m_c + m_b * s * s;
+        let x_acc = (u - m_b * l_bar * w * w * s + m_b * g * s * c) / den;
+        let th_acc = (g * (m_c + m_b) * s + u * c - m_b * l_bar * w * w * s * c) / (l_bar * den);
+
+        vector![v, x_acc, w, th_acc]
+    }
+
+    /// Discrete-time linearisation of [`dynamics`](Self::dynamics) about an
+    /// arbitrary operating point `(x0, u0)`, using central differences.
+    pub fn linearize(&self, x0: Vector4, u0: f32, dt: f32) -> (AMat, BMat) {
+        let h = 1e-3;
+        let mut A = AMat::zeros();
+        for i in 0..NX {
+            let mut dx = Vector4::zeros();
+            dx[i] = h;
+            let col = (self.dynamics(x0 + dx, u0) - self.dynamics(x0 - dx, u0)) / (2. * h);
+            A.set_column(i, &col);
+        }
+        let B = (self.dynamics(x0, u0 + h) - self.dynamics(x0, u0 - h)) / (2. * h);
+
+        (eye!(NX) + A * dt, B * dt)
+    }
+}
@@ -0,0 +1,134 @@
+use super::{solve_dare, StateSpace};
+use crate::prelude::*;
+
+use nalgebra::{allocator::Allocator, Const, DefaultAllocator, DimMin, DimSub, ToTypenum};
+
+/// Discrete-time linear Kalman filter for a [`StateSpace`] model
+///
+/// x[k+1] = A x[k] + B u[k] + w[k],  w ~ N(0, Q)
+/// y[k]   = C x[k] + v[k],           v ~ N(0, R)
+///
+/// `N` is the number of states, `M` the number of control inputs and `L` the
+/// number of measurements.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct KalmanFilter<const N: usize, const M: usize, const L: usize> {
+    /// State estimate
+    pub x: Vector<N>,
+    /// Covariance of the state estimate
+    pub P: Mat<N, N>,
+    /// Process noise covariance
+    pub Q: Mat<N, N>,
+    /// Measurement noise covariance
+    pub R: Mat<L, L>,
+    /// Measurement matrix
+    pub C: Mat<L, N>,
+    /// Fixed gain used by [`update`](Self::update) instead of the time-varying gain
+    K: Option<Mat<N, L>>,
+}
+
+impl<const N: usize, const M: usize, const L: usize> KalmanFilter<N, M, L> {
+    /// Instantiate a new filter with zero initial state and identity covariance
+    pub fn new(C: Mat<L, N>, Q: Mat<N, N>, R: Mat<L, L>) -> Self {
+        Self {
+            x: Vector::<N>::zeros(),
+            P: Mat::<N, N>::identity(),
+            Q,
+            R,
+            C,
+            K: None,
+        }
+    }
+
+    /// Set the initial state estimate and its covariance
+    pub fn with_state(mut self, x: Vector<N>, P: Mat<N, N>) -> Self {
+        self.x = x;
+        self.P = P;
+        self
+    }
+
+    /// Reset the state estimate to zero and the covariance to identity.
+    ///
+    /// A steady-state gain, if any, is retained.
+    pub fn reset_state(&mut self) {
+        self.x = Vector::<N>::zeros();
+        if self.K.is_none() {
+            self.P = Mat::<N, N>::identity();
+        }
+    }
+
+    /// Gain used by [`update`](Self::update), if it is fixed to the steady-state value
+    pub fn steady_state_gain(&self) -> Option<Mat<N, L>> {
+        self.K
+    }
+
+    /// Go back to computing the gain at every [`update`](Self::update)
+    pub fn clear_steady_state_gain(&mut self) {
+        self.K = None;
+    }
+
+    /// Propagate the estimate through the model with control input `u`
+    pub fn predict(&mut self, model: &impl StateSpace<N, M>, u: Vector<M>, dt: f32) {
+        let (A, B) = model.model(dt);
+        self.x = A * self.x + B * u;
+
+        // The covariance stays at its steady-state value when the gain is fixed
+        if self.K.is_none() {
+            self.P = A * self.P * A.transpose() + self.Q;
+        }
+    }
+
+    /// Correct the estimate with measurement `y`
+    pub fn update(&mut self, y: Vector<L>) {
+        let C = self.C;
+        let K = match self.K {
+            Some(K) => K,
+            None => {
+                let S = C * self.P * C.transpose() + self.R;
+                let K = self.P
+                    * C.transpose()
+                    * S.try_inverse()
+                        .expect("Innovation covariance of Kalman filter is singular");
+                self.P = (Mat::<N, N>::identity() - K * C) * self.P;
+                K
+            }
+        };
+        self.x += K * (y - C * self.x);
+    }
+}
+
+impl<const N: usize, const M: usize, const L: usize> KalmanFilter<N, M, L>
+where
+    Const<L>: DimMin<Const<L>>,
+    Const<L>: ToTypenum,
+    <Const<L> as DimMin<Const<L>>>::Output: DimSub<Const<1_usize>>,
+    DefaultAllocator:
+        Allocator<f32, <<Const<L> as DimMin<Const<L>>>::Output as DimSub<Const<1_usize>>>::Output>,
+    DefaultAllocator: Allocator<f32, <Const<L> as DimMin<Const<L>>>::Output, Const<L>>,
+    DefaultAllocator: Allocator<f32, Const<L>, <Const<L> as DimMin<Const<L>>>::Output>,
+    DefaultAllocator: Allocator<f32, <Const<L> as DimMin<Const<L>>>::Output>,
+{
+    /// Fix the gain to its steady-state value for the given model.
+    ///
+    /// The a priori covariance is obtained by solving the DARE of the dual
+    /// problem (A.T, C.T), the same solver used for [`LQR`](super::LQR).
+    pub fn with_steady_state_gain(
+        mut self,
+        model: &impl StateSpace<N, M>,
+        dt: f32,
+        eps: f32,
+        max_iter: u32,
+    ) -> Self {
+        let (A, _) = model.model(dt);
+        let C = self.C;
+        let P = solve_dare(A.transpose(), C.transpose(), self.Q, self.R, eps, max_iter);
+
+        let inv = (C * P * C.transpose() + self.R)
+            .pseudo_inverse(eps)
+            .expect("Matrix inverse failed for steady-state Kalman gain");
+        let K = P * C.transpose() * inv;
+
+        self.P = (Mat::<N, N>::identity() - K * C) * P;
+        self.K = Some(K);
+        self
+    }
+}
@@ -1,4 +1,10 @@
+pub mod double_inverted_pendulum;
+pub mod gain_schedule;
 pub mod inverted_pendulum;
+pub mod kalman;
+
+pub use gain_schedule::*;
+pub use kalman::*;
 
 use crate::prelude::*;
 
@@ -52,6 +58,26 @@ where
         K
     }
 
+    /// Compute LQR gains at each of the operating points in `points`.
+    ///
+    /// `linearize` returns the discrete-time model (A, B) for a given value of
+    /// the scheduling variable, e.g. a rod angle or a model parameter. The
+    /// weights of `self` are used at every operating point.
+    fn gain_schedule(
+        &self,
+        points: &[f32],
+        linearize: impl Fn(f32) -> (Mat<N, N>, Mat<N, M>),
+    ) -> GainSchedule<N, M> {
+        let gains = points
+            .iter()
+            .map(|&p| {
+                let (A, B) = linearize(p);
+                self.dlqr(A, B)
+            })
+            .collect();
+        GainSchedule::from_gains(points.to_vec(), gains)
+    }
+
     /// Solve the discrete time LQR controller.
     ///
     /// x[k+1] = A x[k] + B u[k]
@@ -59,26 +85,49 @@ where
     ///
     /// # ref Bertsekas, p.151
     fn solve_DARE(&self, A: Mat<N, N>, B: Mat<N, M>) -> Mat<N, N> {
-        let max_iter = self.max_iter();
-        let eps = self.epsilon();
-        let Q = self.Q();
-        let R = self.R();
-        let mut P = self.Q();
-        let AT = A.transpose();
-        let BT = B.transpose();
+        solve_dare(A, B, self.Q(), self.R(), self.epsilon(), self.max_iter())
+    }
+}
 
-        for _ in 0..max_iter {
-            let inv = (R + BT * P * B)
-                .pseudo_inverse(eps)
-                .expect("Matrix inverse failed for DARE");
+/// Solve the Discrete Algebraic Ricatti Equation by fixed-point iteration.
+///
+/// P = A.T*P*A - A.T*P*B * (R + B.T*P*B)^-1 * B.T*P*A + Q
+///
+/// Iteration starts from `P = Q` and stops when the element-wise change of `P`
+/// drops below `eps`, or after `max_iter` iterations.
+pub fn solve_dare<const N: usize, const M: usize>(
+    A: Mat<N, N>,
+    B: Mat<N, M>,
+    Q: Mat<N, N>,
+    R: Mat<M, M>,
+    eps: f32,
+    max_iter: u32,
+) -> Mat<N, N>
+where
+    Const<M>: DimMin<Const<M>>,
+    Const<M>: ToTypenum,
+    <Const<M> as DimMin<Const<M>>>::Output: DimSub<Const<1_usize>>,
+    DefaultAllocator:
+        Allocator<f32, <<Const<M> as DimMin<Const<M>>>::Output as DimSub<Const<1_usize>>>::Output>,
+    DefaultAllocator: Allocator<f32, <Const<M> as DimMin<Const<M>>>::Output, Const<M>>,
+    DefaultAllocator: Allocator<f32, Const<M>, <Const<M> as DimMin<Const<M>>>::Output>,
+    DefaultAllocator: Allocator<f32, <Const<M> as DimMin<Const<M>>>::Output>,
+{
+    let mut P = Q;
+    let AT = A.transpose();
+    let BT = B.transpose();
 
-            let Pn = (AT * P * A) - (AT * P * B) * inv * (BT * P * A) + Q;
-            if (Pn - P).abs().amax() < eps {
-                return Pn;
-            }
+    for _ in 0..max_iter {
+        let inv = (R + BT * P * B)
+            .pseudo_inverse(eps)
+            .expect("Matrix inverse failed for DARE");
 
-            P = Pn;
+        let Pn = (AT * P * A) - (AT * P * B) * inv * (BT * P * A) + Q;
+        if (Pn - P).abs().amax() < eps {
+            return Pn;
         }
-        return P;
+
+        P = Pn;
     }
+    P
 }
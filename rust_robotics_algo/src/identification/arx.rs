@@ -0,0 +1,154 @@
+use super::*;
+
+/// Multi-input multi-output ARX model
+///
+/// y[k] = A_1 y[k-1] + ... + A_na y[k-na] + B_1 u[k-1] + ... + B_nb u[k-nb] + e[k]
+#[derive(Debug, PartialEq, Clone)]
+pub struct Arx {
+    /// Output coefficients `A_1..A_na` (ny x ny each)
+    pub A: Vec<DMatrix<f32>>,
+    /// Input coefficients `B_1..B_nb` (ny x nu each)
+    pub B: Vec<DMatrix<f32>>,
+    /// Fit of the one-step-ahead prediction on the identification data
+    pub fit: Fit,
+}
+
+/// Identify an ARX model with `na` output lags and `nb` input lags by least squares.
+///
+/// One-step-ahead prediction is used for [`Fit`], since free-run simulation
+/// of an unstable plant (e.g. the inverted pendulum) diverges.
+///
+/// With the full state as outputs, `arx(data, 1, 1)` identifies
+/// `x[k+1] = A x[k] + B u[k]` directly.
+pub fn arx(data: &IoData, na: usize, nb: usize) -> Arx {
+    assert!(na + nb > 0, "ARX model needs at least one lag");
+    let (nu, ny) = (data.nu(), data.ny());
+    let lag = na.max(nb);
+    assert!(data.len() > lag, "Not enough samples for ARX model");
+
+    // Regressor of each sample time as a column: [y[k-1]; ..; y[k-na]; u[k-1]; ..; u[k-nb]]
+    let n_samples = data.len() - lag;
+    let n_reg = na * ny + nb * nu;
+    let mut phi = DMatrix::zeros(n_reg, n_samples);
+    for (col, k) in (lag..data.len()).enumerate() {
+        for i in 0..na {
+            phi.slice_mut((i * ny, col), (ny, 1))
+                .copy_from(&data.y.column(k - i - 1));
+        }
+        for j in 0..nb {
+            phi.slice_mut((na * ny + j * nu, col), (nu, 1))
+                .copy_from(&data.u.column(k - j - 1));
+        }
+    }
+    let y = data.y.columns(lag, n_samples).into_owned();
+
+    let theta = lstsq(&phi, &y);
+    let fit = Fit::new(&y, &(&theta * &phi));
+
+    let A = (0..na)
+        .map(|i| theta.columns(i * ny, ny).into_owned())
+        .collect();
+    let B = (0..nb)
+        .map(|j| theta.columns(na * ny + j * nu, nu).into_owned())
+        .collect();
+
+    Arx { A, B, fit }
+}
+
+impl Arx {
+    /// Number of output lags
+    pub fn na(&self) -> usize {
+        self.A.len()
+    }
+
+    /// Number of input lags
+    pub fn nb(&self) -> usize {
+        self.B.len()
+    }
+
+    /// Realise as a [`DiscreteModel`]
+    ///
+    /// The state is `x[k] = [y[k]; ..; y[k-na+1]; u[k-1]; ..; u[k-nb+1]]`, so
+    /// that `C` selects the most recent output and `D` is zero.
+    pub fn to_state_space(&self) -> DiscreteModel {
+        let ny = self.A.first().map_or(self.B[0].nrows(), |A| A.nrows());
+        let nu = self.B.first().map_or(0, |B| B.ncols());
+        let (na, nb) = (self.na().max(1), self.nb());
+        let n_y = na * ny;
+        let n = n_y + nb.saturating_sub(1) * nu;
+
+        let mut A = DMatrix::zeros(n, n);
+        let mut B = DMatrix::zeros(n, nu);
+
+        // Newest output from the ARX equation
+        for (i, A_i) in self.A.iter().enumerate() {
+            A.slice_mut((0, i * ny), (ny, ny)).copy_from(A_i);
+        }
+        if let Some(B_1) = self.B.first() {
+            B.slice_mut((0, 0), (ny, nu)).copy_from(B_1);
+        }
+        for (j, B_j) in self.B.iter().enumerate().skip(1) {
+            A.slice_mut((0, n_y + (j - 1) * nu), (ny, nu))
+                .copy_from(B_j);
+        }
+
+        // Shift past outputs and inputs by one sample
+        for i in 1..na {
+            A.slice_mut((i * ny, (i - 1) * ny), (ny, ny))
+                .fill_with_identity();
+        }
+        if nb > 1 {
+            B.slice_mut((n_y, 0), (nu, nu)).fill_with_identity();
+            for j in 1..nb - 1 {
+                A.slice_mut((n_y + j * nu, n_y + (j - 1) * nu), (nu, nu))
+                    .fill_with_identity();
+            }
+        }
+
+        let mut C = DMatrix::zeros(ny, n);
+        C.slice_mut((0, 0), (ny, ny)).fill_with_identity();
+        let D = DMatrix::zeros(ny, nu);
+
+        DiscreteModel { A, B, C, D }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inverted_pendulum::{Model, NU, NX};
+    use crate::localization::particle_filter::rand_with;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn identify_pendulum_from_closed_loop_data() {
+        let dt = 0.01;
+        let model = Model::default();
+        let (A, B) = model.model(dt);
+        let K = model.dlqr(A, B);
+
+        // Stabilised plant with dithered input, so that `u` is not a linear
+        // function of the state
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut x = vector![0., 0., 0.2, 0.];
+        let (mut u_log, mut x_log) = (Vec::new(), Vec::new());
+        for _ in 0..500 {
+            let u = (-K * x)[0] + rand_with(&mut rng);
+            u_log.push(u);
+            x_log.push(x);
+            x = A * x + B * u;
+        }
+        let u = DMatrix::from_row_slice(1, u_log.len(), &u_log);
+        let y = DMatrix::from_fn(NX, x_log.len(), |i, k| x_log[k][i]);
+
+        let arx = arx(&IoData::new(u, y), 1, 1);
+        assert!(arx.fit.nrmse.iter().all(|&fit| fit > 99.0));
+
+        let identified = arx
+            .to_state_space()
+            .to_linear_model::<NX, NU>()
+            .expect("Identified model has wrong dimensions");
+        assert!((identified.A - A).abs().amax() < 1e-3);
+        assert!((identified.B - B).abs().amax() < 1e-3);
+    }
+}
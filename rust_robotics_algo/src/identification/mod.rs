@@ -0,0 +1,236 @@
+//! System identification from recorded input/output data
+//!
+//! - [`arx`]: least-squares fit of an ARX model
+//! - [`n4sid`]: subspace identification of a state-space model
+
+use crate::control::{StateSpace, LQR};
+use crate::prelude::*;
+
+use nalgebra::{allocator::Allocator, Const, DefaultAllocator, DimMin, DimSub, ToTypenum};
+use nalgebra::{DMatrix, DVector};
+
+pub mod arx;
+pub mod n4sid;
+
+pub use arx::*;
+pub use n4sid::*;
+
+/// Relative tolerance for singular values when solving least squares problems
+const RCOND: f32 = 1e-6;
+
+/// Input/output samples of an experiment, with one column per sample time
+#[derive(Debug, PartialEq, Clone)]
+pub struct IoData {
+    /// Inputs (nu x T)
+    pub u: DMatrix<f32>,
+    /// Outputs (ny x T)
+    pub y: DMatrix<f32>,
+}
+
+impl IoData {
+    pub fn new(u: DMatrix<f32>, y: DMatrix<f32>) -> Self {
+        assert_eq!(
+            u.ncols(),
+            y.ncols(),
+            "Number of input and output samples are different!"
+        );
+        Self { u, y }
+    }
+
+    /// Instantiate from one slice per input and per output signal
+    pub fn from_columns(inputs: &[&[f32]], outputs: &[&[f32]]) -> Self {
+        Self::new(rows_to_matrix(inputs), rows_to_matrix(outputs))
+    }
+
+    /// Pair each output with the input `samples` later.
+    ///
+    /// Useful for logs where each row holds the state *after* applying the
+    /// input of that row, so that `arx(data, 1, 1)` fits `x[k+1] = A x[k] + B u[k]`.
+    pub fn advance_inputs(self, samples: usize) -> Self {
+        let n = self.len().saturating_sub(samples);
+        Self {
+            u: self.u.columns(self.len() - n, n).into_owned(),
+            y: self.y.columns(0, n).into_owned(),
+        }
+    }
+
+    /// Number of samples
+    pub fn len(&self) -> usize {
+        self.y.ncols()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of inputs
+    pub fn nu(&self) -> usize {
+        self.u.nrows()
+    }
+
+    /// Number of outputs
+    pub fn ny(&self) -> usize {
+        self.y.nrows()
+    }
+}
+
+/// Discrete-time state-space model with dimensions known at run time
+///
+/// x[k+1] = A x[k] + B u[k]
+/// y[k]   = C x[k] + D u[k]
+#[derive(Debug, PartialEq, Clone)]
+pub struct DiscreteModel {
+    pub A: DMatrix<f32>,
+    pub B: DMatrix<f32>,
+    pub C: DMatrix<f32>,
+    pub D: DMatrix<f32>,
+}
+
+impl DiscreteModel {
+    /// Number of states
+    pub fn order(&self) -> usize {
+        self.A.nrows()
+    }
+
+    /// Simulate the output for inputs `u` (nu x T) from initial state `x0`
+    pub fn simulate(&self, u: &DMatrix<f32>, x0: &DVector<f32>) -> DMatrix<f32> {
+        let mut x = x0.clone();
+        let mut y = DMatrix::zeros(self.C.nrows(), u.ncols());
+        for k in 0..u.ncols() {
+            let uk = u.column(k);
+            y.set_column(k, &(&self.C * &x + &self.D * uk));
+            x = &self.A * &x + &self.B * uk;
+        }
+        y
+    }
+
+    /// Convert into a [`LinearModel`] with static dimensions, e.g. to design an
+    /// [`LQR`] controller on the identified model.
+    ///
+    /// Returns `None` if the dimensions don't match `N` and `M`.
+    pub fn to_linear_model<const N: usize, const M: usize>(&self) -> Option<LinearModel<N, M>> {
+        if self.A.shape() != (N, N) || self.B.shape() != (N, M) {
+            return None;
+        }
+        Some(LinearModel::new(
+            Mat::<N, N>::from_iterator(self.A.iter().copied()),
+            Mat::<N, M>::from_iterator(self.B.iter().copied()),
+        ))
+    }
+}
+
+/// Discrete-time linear model with static dimensions and LQR-related parameters
+///
+/// Unlike physical models, the matrices are already discretised at the sample
+/// time of the data they were identified from, so `dt` passed to
+/// [`StateSpace::model`] is ignored.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LinearModel<const N: usize, const M: usize> {
+    /// System matrix
+    pub A: Mat<N, N>,
+    /// Input matrix
+    pub B: Mat<N, M>,
+    /// Q matrix
+    pub Q: Mat<N, N>,
+    /// R matrix
+    pub R: Mat<M, M>,
+    /// Tolerance for computing matrix pseudo-inverse
+    pub eps: f32,
+    /// Maximum number of iteration for solving
+    /// Discrete Algebraic Ricatti Equation
+    pub max_iter: u32,
+}
+
+impl<const N: usize, const M: usize> LinearModel<N, M> {
+    /// Instantiate with identity weights
+    pub fn new(A: Mat<N, N>, B: Mat<N, M>) -> Self {
+        Self {
+            A,
+            B,
+            Q: Mat::<N, N>::identity(),
+            R: Mat::<M, M>::identity(),
+            eps: 0.01,
+            max_iter: 150,
+        }
+    }
+}
+
+impl<const N: usize, const M: usize> StateSpace<N, M> for LinearModel<N, M> {
+    fn model(&self, _dt: f32) -> (Mat<N, N>, Mat<N, M>) {
+        (self.A, self.B)
+    }
+}
+
+impl<const N: usize, const M: usize> LQR<N, M> for LinearModel<N, M>
+where
+    Const<N>: DimSub<Const<1_usize>>,
+    Const<N>: ToTypenum,
+    DefaultAllocator: Allocator<f32, Const<N>, <Const<N> as DimSub<Const<1_usize>>>::Output>,
+    DefaultAllocator: Allocator<f32, <Const<N> as DimSub<Const<1_usize>>>::Output>,
+    Const<M>: DimMin<Const<M>>,
+    Const<M>: ToTypenum,
+    <Const<M> as DimMin<Const<M>>>::Output: DimSub<Const<1_usize>>,
+    DefaultAllocator:
+        Allocator<f32, <<Const<M> as DimMin<Const<M>>>::Output as DimSub<Const<1_usize>>>::Output>,
+    DefaultAllocator: Allocator<f32, <Const<M> as DimMin<Const<M>>>::Output, Const<M>>,
+    DefaultAllocator: Allocator<f32, Const<M>, <Const<M> as DimMin<Const<M>>>::Output>,
+    DefaultAllocator: Allocator<f32, <Const<M> as DimMin<Const<M>>>::Output>,
+{
+    fn Q(&self) -> Mat<N, N> {
+        self.Q
+    }
+    fn R(&self) -> Mat<M, M> {
+        self.R
+    }
+    fn epsilon(&self) -> f32 {
+        self.eps
+    }
+    fn max_iter(&self) -> u32 {
+        self.max_iter
+    }
+}
+
+/// Goodness of fit of an identified model, per output
+#[derive(Debug, PartialEq, Clone)]
+pub struct Fit {
+    /// Normalised root mean squared error fit in percent,
+    /// `100 * (1 - |y - y_hat| / |y - mean(y)|)`. 100 is a perfect fit.
+    pub nrmse: Vec<f32>,
+    /// Mean squared error
+    pub mse: Vec<f32>,
+}
+
+impl Fit {
+    /// Compare measured outputs `y` with predicted outputs `y_hat` (ny x T)
+    pub fn new(y: &DMatrix<f32>, y_hat: &DMatrix<f32>) -> Self {
+        let (nrmse, mse) = y
+            .row_iter()
+            .zip(y_hat.row_iter())
+            .map(|(y, y_hat)| {
+                let err = (y - y_hat).norm();
+                let dev = y.add_scalar(-y.mean()).norm();
+                let nrmse = if dev > 0.0 {
+                    100.0 * (1.0 - err / dev)
+                } else {
+                    0.0
+                };
+                (nrmse, err * err / y.len().max(1) as f32)
+            })
+            .unzip();
+        Self { nrmse, mse }
+    }
+}
+
+/// Least squares solution `Theta` of `Theta * phi = y`
+fn lstsq(phi: &DMatrix<f32>, y: &DMatrix<f32>) -> DMatrix<f32> {
+    let svd = phi.transpose().svd(true, true);
+    let eps = svd.singular_values.max() * RCOND;
+    svd.solve(&y.transpose(), eps)
+        .expect("SVD failed for least squares")
+        .transpose()
+}
+
+fn rows_to_matrix(rows: &[&[f32]]) -> DMatrix<f32> {
+    let ncols = rows.iter().map(|row| row.len()).min().unwrap_or(0);
+    DMatrix::from_fn(rows.len(), ncols, |i, j| rows[i][j])
+}
@@ -0,0 +1,121 @@
+use super::*;
+
+/// State-space model identified by [`n4sid`]
+#[derive(Debug, PartialEq, Clone)]
+pub struct Subspace {
+    /// Identified model, in the state basis chosen by the algorithm
+    pub model: DiscreteModel,
+    /// Singular values of the oblique projection, useful for choosing the order
+    pub singular_values: DVector<f32>,
+    /// Fit of the outputs reconstructed from the estimated state sequence
+    pub fit: Fit,
+}
+
+/// Identify a state-space model of the given `order` with an N4SID-style
+/// subspace method.
+///
+/// `horizon` is the number of block rows of the past and future Hankel
+/// matrices, and must be larger than `order`.
+///
+/// 1. The future outputs are projected obliquely onto past inputs/outputs,
+///    along the future inputs.
+/// 2. An SVD of the projection gives the extended observability matrix and
+///    the state sequence.
+/// 3. `A`, `B`, `C` and `D` are fitted to the state sequence by least squares.
+pub fn n4sid(data: &IoData, order: usize, horizon: usize) -> Subspace {
+    let i = horizon;
+    let (nu, ny) = (data.nu(), data.ny());
+    assert!(order > 0 && order <= i * ny, "Invalid model order");
+    assert!(
+        data.len() > 2 * i,
+        "Not enough samples for the given horizon"
+    );
+    let j = data.len() - 2 * i + 1;
+
+    let U_p = hankel(&data.u, 0, i, j);
+    let U_f = hankel(&data.u, i, i, j);
+    let Y_p = hankel(&data.y, 0, i, j);
+    let Y_f = hankel(&data.y, i, i, j);
+
+    // Oblique projection of Y_f onto W_p along U_f
+    let W_p = stack(&U_p, &Y_p);
+    let L = lstsq(&stack(&W_p, &U_f), &Y_f);
+    let O = L.columns(0, W_p.nrows()) * &W_p;
+
+    let svd = O.svd(true, true);
+    let sqrt_s = DMatrix::from_diagonal(&svd.singular_values.rows(0, order).map(|s| s.sqrt()));
+    let V_t = svd.v_t.as_ref().expect("SVD failed for oblique projection");
+    let X = sqrt_s * V_t.rows(0, order);
+
+    // [x[k+1]; y[k]] = [A B; C D] [x[k]; u[k]]
+    let X_k = X.columns(0, j - 1).into_owned();
+    let X_next = X.columns(1, j - 1).into_owned();
+    let U_k = U_f.rows(0, nu).columns(0, j - 1).into_owned();
+    let Y_k = Y_f.rows(0, ny).columns(0, j - 1).into_owned();
+
+    let regressor = stack(&X_k, &U_k);
+    let theta = lstsq(&regressor, &stack(&X_next, &Y_k));
+    let fit = Fit::new(&Y_k, &(theta.rows(order, ny) * &regressor));
+
+    let model = DiscreteModel {
+        A: theta.slice((0, 0), (order, order)).into_owned(),
+        B: theta.slice((0, order), (order, nu)).into_owned(),
+        C: theta.slice((order, 0), (ny, order)).into_owned(),
+        D: theta.slice((order, order), (ny, nu)).into_owned(),
+    };
+
+    Subspace {
+        model,
+        singular_values: svd.singular_values,
+        fit,
+    }
+}
+
+/// Block Hankel matrix with `rows` block rows and `cols` columns, starting at sample `start`
+fn hankel(w: &DMatrix<f32>, start: usize, rows: usize, cols: usize) -> DMatrix<f32> {
+    let n = w.nrows();
+    DMatrix::from_fn(rows * n, cols, |r, c| w[(r % n, start + r / n + c)])
+}
+
+fn stack(top: &DMatrix<f32>, bottom: &DMatrix<f32>) -> DMatrix<f32> {
+    let mut out = DMatrix::zeros(top.nrows() + bottom.nrows(), top.ncols());
+    out.rows_mut(0, top.nrows()).copy_from(top);
+    out.rows_mut(top.nrows(), bottom.nrows()).copy_from(bottom);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localization::particle_filter::rand_with;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn identify_second_order_system() {
+        // Lightly damped oscillator, observed through its position only
+        let A = matrix![0.9, 0.2; -0.2, 0.9];
+        let B = vector![0., 0.1];
+        let C = matrix![1., 0.];
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut x = Vector2::zeros();
+        let (mut u_log, mut y_log) = (Vec::new(), Vec::new());
+        for _ in 0..400 {
+            let u = rand_with(&mut rng);
+            u_log.push(u);
+            y_log.push((C * x)[0]);
+            x = A * x + B * u;
+        }
+        let data = IoData::from_columns(&[&u_log], &[&y_log]);
+        let id = n4sid(&data, 2, 8);
+
+        assert!(id.fit.nrmse[0] > 95.0, "Fit: {}", id.fit.nrmse[0]);
+
+        // Eigenvalues don't depend on the state basis
+        let eig = id.model.A.complex_eigenvalues();
+        eig.iter().for_each(|e| {
+            assert!((e.re - 0.9).abs() < 0.02, "Eigenvalue: {e}");
+            assert!((e.im.abs() - 0.2).abs() < 0.02, "Eigenvalue: {e}");
+        });
+    }
+}
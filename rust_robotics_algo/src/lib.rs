@@ -3,15 +3,22 @@
 #![allow(non_snake_case)]
 
 pub mod control;
+pub mod identification;
 pub mod localization;
+pub mod mapping;
+pub mod pose;
+pub mod sensor;
+pub mod slam;
 pub mod util;
 pub mod prelude {
     pub use crate::util::*;
     pub use crate::*;
+    pub use control::double_inverted_pendulum;
     pub use control::inverted_pendulum;
     pub use localization::particle_filter as pf;
     pub use nalgebra;
     pub use nalgebra::{matrix, vector};
+    pub use pose::{Pose2, VehicleState};
 
     #[cfg(feature = "osqp")]
     pub use osqp::{CscMatrix, Problem, Settings};
@@ -0,0 +1,311 @@
+//! Data association of range-bearing observations with known landmarks
+//!
+//! - [`nearest_neighbor`]: greedy pairing by Mahalanobis distance, with gating
+//! - [`jcbb`]: Joint Compatibility Branch and Bound
+
+use super::*;
+use crate::pose::normalize_angle;
+use nalgebra::{DMatrix, DVector};
+
+/// Predicted observations of landmarks from an estimate with covariance `P`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Predictions {
+    /// Predicted range-bearing of each landmark
+    pub z: Vec<Vector2>,
+    /// Jacobian of each prediction with respect to the estimated state (2 x n)
+    pub H: Vec<DMatrix<f32>>,
+    /// Covariance of the estimated state (n x n)
+    pub P: DMatrix<f32>,
+    /// Covariance of range-bearing measurement
+    pub Q: Matrix2,
+}
+
+impl Predictions {
+    /// Number of landmarks
+    pub fn len(&self) -> usize {
+        self.z.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.z.is_empty()
+    }
+
+    /// Innovation of observation `z` against landmark `j`, with wrapped bearing
+    pub fn innovation(&self, z: &Vector2, j: usize) -> Vector2 {
+        let dz = z - self.z[j];
+        vector![dz[0], normalize_angle(dz[1])]
+    }
+
+    /// Cross-covariance of the innovations of landmarks `i` and `j`
+    pub fn S(&self, i: usize, j: usize) -> Matrix2 {
+        let S = &self.H[i] * &self.P * self.H[j].transpose();
+        let S = Matrix2::from_iterator(S.iter().copied());
+        if i == j {
+            S + self.Q
+        } else {
+            S
+        }
+    }
+
+    /// Squared Mahalanobis distance of observation `z` to landmark `j`
+    pub fn mahalanobis(&self, z: &Vector2, j: usize) -> f32 {
+        let nu = self.innovation(z, j);
+        match self.S(j, j).try_inverse() {
+            Some(S_inv) => (nu.transpose() * S_inv * nu)[0],
+            None => f32::INFINITY,
+        }
+    }
+
+    /// Normalised innovation squared of the joint `pairing` of observations
+    /// with landmarks
+    pub fn joint_nis(&self, z: &[Vector2], pairing: &[Option<usize>]) -> f32 {
+        let pairs: Vec<(usize, usize)> = pairing
+            .iter()
+            .enumerate()
+            .filter_map(|(i, j)| j.map(|j| (i, j)))
+            .collect();
+        let k = pairs.len();
+        if k == 0 {
+            return 0.0;
+        }
+
+        let mut nu = DVector::zeros(2 * k);
+        let mut S = DMatrix::zeros(2 * k, 2 * k);
+        for (a, &(i, j)) in pairs.iter().enumerate() {
+            nu.rows_mut(2 * a, 2).copy_from(&self.innovation(&z[i], j));
+            for (b, &(_, l)) in pairs.iter().enumerate() {
+                S.slice_mut((2 * a, 2 * b), (2, 2)).copy_from(&self.S(j, l));
+            }
+        }
+        match S.try_inverse() {
+            Some(S_inv) => (nu.transpose() * S_inv * nu)[0],
+            None => f32::INFINITY,
+        }
+    }
+}
+
+/// Algorithm for pairing observations with landmarks
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Association {
+    /// [`nearest_neighbor`] with the given gate confidence
+    NearestNeighbor(f32),
+    /// [`jcbb`] with the given gate confidence
+    JCBB(f32),
+}
+
+impl Default for Association {
+    fn default() -> Self {
+        Self::NearestNeighbor(0.99)
+    }
+}
+
+impl Association {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::NearestNeighbor(_) => "Nearest Neighbor",
+            Self::JCBB(_) => "JCBB",
+        }
+    }
+
+    /// Landmark paired with each observation, if any
+    pub fn associate(&self, z: &[Vector2], predictions: &Predictions) -> Vec<Option<usize>> {
+        match *self {
+            Self::NearestNeighbor(confidence) => nearest_neighbor(z, predictions, confidence),
+            Self::JCBB(confidence) => jcbb(z, predictions, confidence),
+        }
+    }
+}
+
+/// Pair observations with landmarks in increasing order of Mahalanobis
+/// distance, using each landmark at most once.
+///
+/// Pairs outside the chi-square gate of the given `confidence` are rejected.
+pub fn nearest_neighbor(
+    z: &[Vector2],
+    predictions: &Predictions,
+    confidence: f32,
+) -> Vec<Option<usize>> {
+    let gate = chi2_inv(confidence, 2);
+    let mut candidates: Vec<(f32, usize, usize)> = z
+        .iter()
+        .enumerate()
+        .flat_map(|(i, zi)| (0..predictions.len()).map(move |j| (i, j, zi)))
+        .map(|(i, j, zi)| (predictions.mahalanobis(zi, j), i, j))
+        .filter(|(d, _, _)| *d < gate)
+        .collect();
+    candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut pairing = vec![None; z.len()];
+    let mut used = vec![false; predictions.len()];
+    for (_, i, j) in candidates {
+        if pairing[i].is_none() && !used[j] {
+            pairing[i] = Some(j);
+            used[j] = true;
+        }
+    }
+    pairing
+}
+
+/// Joint Compatibility Branch and Bound
+///
+/// Searches for the pairing with the most paired observations whose joint
+/// innovation passes the chi-square gate of the given `confidence`, accounting
+/// for the correlation between predictions through the shared state estimate.
+/// Ties are broken by the lower joint NIS.
+pub fn jcbb(z: &[Vector2], predictions: &Predictions, confidence: f32) -> Vec<Option<usize>> {
+    let gate = chi2_inv(confidence, 2);
+    let compatible: Vec<Vec<usize>> = z
+        .iter()
+        .map(|zi| {
+            (0..predictions.len())
+                .filter(|&j| predictions.mahalanobis(zi, j) < gate)
+                .collect()
+        })
+        .collect();
+
+    let mut search = Jcbb {
+        z,
+        predictions,
+        confidence,
+        compatible,
+        best: vec![None; z.len()],
+        best_count: 0,
+        best_nis: f32::INFINITY,
+    };
+    let mut pairing = vec![None; z.len()];
+    search.branch(0, &mut pairing, 0);
+    search.best
+}
+
+struct Jcbb<'a> {
+    z: &'a [Vector2],
+    predictions: &'a Predictions,
+    confidence: f32,
+    /// Individually compatible landmarks of each observation
+    compatible: Vec<Vec<usize>>,
+    best: Vec<Option<usize>>,
+    best_count: usize,
+    best_nis: f32,
+}
+
+impl Jcbb<'_> {
+    fn branch(&mut self, i: usize, pairing: &mut Vec<Option<usize>>, count: usize) {
+        if i == self.z.len() {
+            let nis = self.predictions.joint_nis(self.z, pairing);
+            if count > self.best_count || (count == self.best_count && nis < self.best_nis) {
+                self.best.clone_from(pairing);
+                self.best_count = count;
+                self.best_nis = nis;
+            }
+            return;
+        }
+
+        for j in self.compatible[i].clone() {
+            if pairing.contains(&Some(j)) {
+                continue;
+            }
+            pairing[i] = Some(j);
+            let nis = self.predictions.joint_nis(self.z, pairing);
+            if nis < chi2_inv(self.confidence, 2 * (count + 1)) {
+                self.branch(i + 1, pairing, count + 1);
+            }
+            pairing[i] = None;
+        }
+
+        // Leave observation `i` unpaired, if that can still beat the best pairing
+        if count + (self.z.len() - i - 1) >= self.best_count {
+            self.branch(i + 1, pairing, count);
+        }
+    }
+}
+
+/// Approximate inverse of the chi-square CDF at `p` with `dof` degrees of
+/// freedom (Wilson-Hilferty)
+pub fn chi2_inv(p: f32, dof: usize) -> f32 {
+    let k = dof as f32;
+    let h = 2. / (9. * k);
+    (k * (1. - h + norm_inv(p) * sqrt(h)).powi(3)).max(0.)
+}
+
+/// Approximate inverse of the standard normal CDF (Abramowitz and Stegun 26.2.23)
+fn norm_inv(p: f32) -> f32 {
+    let q = if p < 0.5 { p } else { 1. - p };
+    let t = sqrt(-2. * q.max(f32::MIN_POSITIVE).ln());
+    let x = t
+        - (2.515517 + 0.802853 * t + 0.010328 * t * t)
+            / (1. + 1.432788 * t + 0.189269 * t * t + 0.001308 * t * t * t);
+    if p < 0.5 {
+        -x
+    } else {
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localization::particle_filter::randn_with;
+    use crate::localization::range_bearing::{jacob_range_bearing, range_bearing};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn chi2_inv_matches_table() {
+        assert!((chi2_inv(0.99, 2) - 9.21).abs() < 0.2);
+        assert!((chi2_inv(0.95, 4) - 9.49).abs() < 0.2);
+    }
+
+    #[test]
+    fn associate_shuffled_observations() {
+        let landmarks = [
+            vector![10.0_f32, 0.0_f32],
+            vector![10.0, 10.0],
+            vector![0.0, 15.0],
+            vector![-5.0, 20.0],
+            vector![11.0, 1.0],
+        ];
+        let x_true = vector![2., 3., 0.4, 1.];
+        // Estimate with a small error in position and heading
+        let x_est = x_true + vector![0.3, -0.2, 0.02, 0.];
+        let P = DMatrix::from_diagonal(&DVector::from_vec(vec![0.1, 0.1, 0.001, 0.1]));
+        let Q = diag![0.1_f32.powi(2), (1_f32).to_radians().powi(2)];
+
+        let predictions = Predictions {
+            z: landmarks.iter().map(|l| range_bearing(&x_est, l)).collect(),
+            H: landmarks
+                .iter()
+                .map(|l| {
+                    let (H, _) = jacob_range_bearing(&x_est, l);
+                    let mut H4 = DMatrix::zeros(2, 4);
+                    H4.columns_mut(0, 3).copy_from(&H);
+                    H4
+                })
+                .collect(),
+            P,
+            Q,
+        };
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let truth = [3, 0, 2, 4];
+        let z: Vec<Vector2> = truth
+            .iter()
+            .map(|&j| {
+                range_bearing(&x_true, &landmarks[j])
+                    + vector![
+                        randn_with(&mut rng) * 0.1,
+                        randn_with(&mut rng) * (1_f32).to_radians()
+                    ]
+            })
+            .collect();
+
+        let expected: Vec<Option<usize>> = truth.iter().map(|&j| Some(j)).collect();
+        assert_eq!(jcbb(&z, &predictions, 0.99), expected);
+        assert_eq!(
+            Association::JCBB(0.99).associate(&z, &predictions),
+            expected
+        );
+
+        let nn = nearest_neighbor(&z, &predictions, 0.99);
+        assert_eq!(nn.len(), z.len());
+        assert_eq!(nn[2], Some(2));
+    }
+}
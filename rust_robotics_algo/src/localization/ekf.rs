@@ -0,0 +1,237 @@
+use super::association::{Association, Predictions};
+use super::evaluation::Nis;
+use super::particle_filter::Observation;
+use super::range_bearing::{jacob_range_bearing, range_bearing};
+use super::*;
+use nalgebra::DMatrix;
+
+/// Jacobian of [`VehicleState::predict`] with respect to the state
+pub fn jacob_f(x: &VehicleState, u: &Vector2, dt: f32) -> Matrix4 {
+    let v = u[0];
+    matrix![1., 0., -dt * v * sin(x.phi), 0.;
+            0., 1.,  dt * v * cos(x.phi), 0.;
+            0., 0., 1., 0.;
+            0., 0., 0., 0.]
+}
+
+/// Jacobian of [`VehicleState::predict`] with respect to the input
+pub fn jacob_g(x: &VehicleState, dt: f32) -> Mat<4, 2> {
+    matrix![cos(x.phi) * dt, 0.;
+            sin(x.phi) * dt, 0.;
+            0., dt;
+            1., 0.]
+}
+
+/// Range from state `x` to `landmark`, along with its Jacobian with respect to the state
+pub fn range_observation(x: &VehicleState, landmark: &Vector2) -> (f32, RowVector4) {
+    let dx = x.x - landmark.x;
+    let dy = x.y - landmark.y;
+    let d = hypot(dx, dy).max(f32::EPSILON);
+    (d, RowVector4::new(dx / d, dy / d, 0., 0.))
+}
+
+/// Extended Kalman filter localization with range observations to known landmarks
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct EKF {
+    /// Estimated state
+    pub x_est: VehicleState,
+    /// Estimated covariance
+    pub P: Matrix4,
+    /// Variance of range measurement [m^2]
+    pub Q: Matrix1,
+    /// Covariance of input [velocity, yaw rate]
+    pub R: Matrix2,
+    /// NIS of the latest correction
+    pub nis: Nis,
+}
+
+impl Default for EKF {
+    fn default() -> Self {
+        Self {
+            x_est: VehicleState::default(),
+            P: Matrix4::identity() * 1e-3,
+            Q: diag![0.2],
+            R: diag![1.0, (30_f32).to_radians()],
+            nis: Nis::default(),
+        }
+    }
+}
+
+impl EKF {
+    pub fn with_state(mut self, x: VehicleState, P: Matrix4) -> Self {
+        self.x_est = x;
+        self.P = P;
+        self
+    }
+
+    pub fn with_noise(mut self, Q: Matrix1, R: Matrix2) -> Self {
+        self.Q = Q;
+        self.R = R;
+        self
+    }
+
+    /// Estimated covariance of [x, y, yaw]
+    pub fn p_est(&self) -> Matrix3 {
+        self.P.fixed_slice::<3, 3>(0, 0).into_owned()
+    }
+
+    /// Propagate the estimate through [`VehicleState::predict`] with input `u`
+    pub fn predict(&mut self, u: Vector2, dt: f32) {
+        let F = jacob_f(&self.x_est, &u, dt);
+        let G = jacob_g(&self.x_est, dt);
+        self.x_est = self.x_est.predict(u, dt);
+        self.P = F * self.P * F.transpose() + G * self.R * G.transpose();
+    }
+
+    /// Correct the estimate with range observations `[d, x, y]`, one at a time
+    pub fn correct(&mut self, z: &[Vector3]) {
+        self.nis = Nis::default();
+        for zi in z.iter() {
+            let (d, H) = range_observation(&self.x_est, &vector![zi.x(), zi.y()]);
+            let S = (H * self.P * H.transpose())[0] + self.Q[0];
+            let K = self.P * H.transpose() / S;
+            self.nis.add(&vector![zi.d() - d], &diag![1. / S]);
+            self.x_est += K * (zi.d() - d);
+            self.P = (Matrix4::identity() - K * H) * self.P;
+        }
+    }
+
+    /// Correct the estimate with range-bearing observations `z` with covariance
+    /// `Q`, whose landmarks among `landmarks` are unknown and paired by `association`.
+    ///
+    /// Returns the landmark paired with each observation, if any.
+    pub fn correct_range_bearing(
+        &mut self,
+        z: &[Vector2],
+        landmarks: &[Vector2],
+        Q: Matrix2,
+        association: Association,
+    ) -> Vec<Option<usize>> {
+        let predictions = Predictions {
+            z: landmarks
+                .iter()
+                .map(|l| range_bearing(&self.x_est, l))
+                .collect(),
+            H: landmarks
+                .iter()
+                .map(|l| DMatrix::from_iterator(2, 4, jacob_h_rb(&self.x_est, l).iter().copied()))
+                .collect(),
+            P: DMatrix::from_iterator(4, 4, self.P.iter().copied()),
+            Q,
+        };
+        let pairing = association.associate(z, &predictions);
+
+        self.nis = Nis::default();
+        for (zi, j) in z.iter().zip(pairing.iter()) {
+            if let Some(j) = *j {
+                let H = jacob_h_rb(&self.x_est, &landmarks[j]);
+                let z_hat = range_bearing(&self.x_est, &landmarks[j]);
+                let nu = vector![zi[0] - z_hat[0], angle_diff(zi[1], z_hat[1])];
+                let S = H * self.P * H.transpose() + Q;
+                if let Some(S_inv) = S.try_inverse() {
+                    let K = self.P * H.transpose() * S_inv;
+                    self.nis.add(&nu, &S_inv);
+                    self.x_est += K * nu;
+                    self.P = (Matrix4::identity() - K * H) * self.P;
+                }
+            }
+        }
+        pairing
+    }
+
+    /// Predict with input `u` and correct with observations `z`.
+    ///
+    /// Returns the estimated covariance of [x, y, yaw].
+    pub fn update(&mut self, z: &[Vector3], u: Vector2, dt: f32) -> Matrix3 {
+        self.predict(u, dt);
+        self.correct(z);
+        self.p_est()
+    }
+}
+
+/// Jacobian of [`range_bearing`] with respect to the state
+fn jacob_h_rb(x: &VehicleState, landmark: &Vector2) -> Mat<2, 4> {
+    let (H_pose, _) = jacob_range_bearing(x, landmark);
+    let mut H = Mat::<2, 4>::zeros();
+    H.fixed_columns_mut::<3>(0).copy_from(&H_pose);
+    H
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localization::evaluation::Scenario;
+    use crate::localization::particle_filter::calc_input;
+    use crate::localization::range_bearing::RangeBearingSensor;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn jacobians_match_finite_differences() {
+        let (x, u, dt) = (VehicleState::new(1., 2., 0.3, 0.5), vector![1.2, 0.1], 0.1);
+        let h = 1e-3;
+        let perturbed = |i: usize, h: f32| {
+            let mut x = x;
+            x += Vector4::from_fn(|j, _| if i == j { h } else { 0. });
+            x
+        };
+
+        let J = jacob_f(&x, &u, dt);
+        for i in 0..4 {
+            let (x_p, x_m) = (perturbed(i, h), perturbed(i, -h));
+            let col = state_residual(&x_p.predict(u, dt), &x_m.predict(u, dt)) / (2. * h);
+            assert!((col - J.column(i)).amax() < 1e-3);
+        }
+
+        let landmark = vector![10., 0.];
+        let (_, H) = range_observation(&x, &landmark);
+        for i in 0..2 {
+            let (d_p, _) = range_observation(&perturbed(i, h), &landmark);
+            let (d_m, _) = range_observation(&perturbed(i, -h), &landmark);
+            assert!(((d_p - d_m) / (2. * h) - H[i]).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn ekf_tracks_vehicle() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut ekf = EKF::default();
+        let scenario = Scenario::default();
+        let (x_true, x_dr) = scenario.run(&mut rng, |_, step| {
+            ekf.update(step.z, step.u, scenario.dt);
+        });
+        let err = hypot(ekf.x_est.x() - x_true.x(), ekf.x_est.y() - x_true.y());
+        let err_dr = hypot(x_dr.x() - x_true.x(), x_dr.y() - x_true.y());
+        assert!(err < 1.0, "Position error: {err}");
+        assert!(
+            err < err_dr,
+            "EKF error {err} larger than DR error {err_dr}"
+        );
+    }
+
+    #[test]
+    fn ekf_tracks_with_unknown_correspondences() {
+        let landmarks = Scenario::default().landmarks;
+        let mut rng = StdRng::seed_from_u64(0);
+        let sensor = RangeBearingSensor::default();
+        let mut ekf = EKF::default();
+
+        let (mut x_true, mut x_dr) = (Vector4::zeros(), Vector4::zeros());
+        let dt = 0.1;
+        for _ in 0..300 {
+            let (z, ud) = sensor.observe(
+                &mut rng,
+                &mut x_true,
+                &mut x_dr,
+                calc_input(),
+                &landmarks,
+                dt,
+            );
+            ekf.predict(ud, dt);
+            let pairing =
+                ekf.correct_range_bearing(&z, &landmarks, sensor.Q, Association::JCBB(0.99));
+            assert_eq!(pairing.len(), z.len());
+        }
+        let err = hypot(ekf.x_est.x() - x_true.x(), ekf.x_est.y() - x_true.y());
+        assert!(err < 0.5, "Position error: {err}");
+    }
+}
@@ -0,0 +1,189 @@
+use super::association::chi2_inv;
+use super::evaluation::Nis;
+use super::*;
+
+/// Jacobian of the position observed by GNSS with respect to the error state
+const H: Mat<2, 4> = matrix![1., 0., 0., 0.; 0., 1., 0., 0.];
+
+/// Error-state extended Kalman filter, fusing GNSS position fixes with the
+/// odometry input `[velocity, yaw rate]`
+///
+/// The nominal state is driven through [`VehicleState::predict`] by the input, less the
+/// estimated bias of the yaw rate. The filter estimates the error of the
+/// nominal `[x, y, yaw, bias]`, which stays small, so the linearization holds
+/// even after long stretches of dead reckoning. Each correction is folded
+/// into the nominal state, resetting the error to zero.
+///
+/// Fixes whose NIS lies beyond the chi-square `gate` are rejected as outliers.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ESEKF {
+    /// Estimated state
+    pub x_est: VehicleState,
+    /// Estimated bias of the yaw rate input [rad/s]
+    pub bias: f32,
+    /// Covariance of the error state [x, y, yaw, bias]
+    pub P: Matrix4,
+    /// Covariance of GNSS position [m^2]
+    pub Q: Matrix2,
+    /// Covariance of input [velocity, yaw rate]
+    pub R: Matrix2,
+    /// Density of the random walk of the bias [rad/s²/√Hz]
+    pub bias_random_walk: f32,
+    /// Probability within which the NIS of a fix is accepted, or `None` to
+    /// accept every fix
+    pub gate: Option<f32>,
+    /// NIS of the fix since the latest prediction, if any
+    pub nis: Nis,
+    /// Whether the fix since the latest prediction was rejected by the gate
+    pub rejected: bool,
+}
+
+impl Default for ESEKF {
+    fn default() -> Self {
+        Self {
+            x_est: VehicleState::default(),
+            bias: 0.0,
+            P: diag![1e-3, 1e-3, 1e-3, 1e-4],
+            Q: diag![1.0, 1.0],
+            R: diag![0.01, 1e-3],
+            bias_random_walk: 1e-4,
+            gate: Some(0.999),
+            nis: Nis::default(),
+            rejected: false,
+        }
+    }
+}
+
+impl ESEKF {
+    pub fn with_state(mut self, x: VehicleState, P: Matrix4) -> Self {
+        self.x_est = x;
+        self.P = P;
+        self
+    }
+
+    pub fn with_noise(mut self, Q: Matrix2, R: Matrix2) -> Self {
+        self.Q = Q;
+        self.R = R;
+        self
+    }
+
+    pub fn with_gate(mut self, gate: Option<f32>) -> Self {
+        self.gate = gate;
+        self
+    }
+
+    /// Estimated covariance of [x, y, yaw]
+    pub fn p_est(&self) -> Matrix3 {
+        self.P.fixed_slice::<3, 3>(0, 0).into_owned()
+    }
+
+    /// Propagate the nominal state through [`VehicleState::predict`] with input `u`,
+    /// and the covariance of its error
+    pub fn predict(&mut self, u: Vector2, dt: f32) {
+        // No fix since this prediction yet
+        self.nis = Nis::default();
+        self.rejected = false;
+        let u = vector![u[0], u[1] - self.bias];
+        let (s, c) = (sin(self.x_est.phi), cos(self.x_est.phi));
+        let F = matrix![1., 0., -dt * u[0] * s, 0.;
+                        0., 1.,  dt * u[0] * c, 0.;
+                        0., 0., 1., -dt;
+                        0., 0., 0., 1.];
+        let G = matrix![c * dt, 0.;
+                        s * dt, 0.;
+                        0., dt;
+                        0., 0.];
+        let walk = diag![0., 0., 0., self.bias_random_walk.powi(2) * dt];
+        self.x_est = self.x_est.predict(u, dt);
+        self.P = F * self.P * F.transpose() + G * self.R * G.transpose() + walk;
+    }
+
+    /// Correct the estimate with a GNSS position fix `z`, unless the gate
+    /// rejects it as an outlier.
+    ///
+    /// Returns whether the fix was accepted.
+    pub fn correct(&mut self, z: &Vector2) -> bool {
+        self.nis = Nis::default();
+        self.rejected = false;
+        let nu = z - self.x_est.position();
+        let S = H * self.P * H.transpose() + self.Q;
+        let S_inv = match S.try_inverse() {
+            Some(S_inv) => S_inv,
+            None => return false,
+        };
+        self.nis.add(&nu, &S_inv);
+        self.rejected = self
+            .gate
+            .is_some_and(|gate| self.nis.value > chi2_inv(gate, 2));
+        if self.rejected {
+            return false;
+        }
+
+        let K = self.P * H.transpose() * S_inv;
+        let dx = K * nu;
+        self.x_est += vector![dx[0], dx[1], dx[2], 0.];
+        self.bias += dx[3];
+        // Joseph form, which keeps the covariance symmetric and positive
+        let I_KH = Matrix4::identity() - K * H;
+        self.P = I_KH * self.P * I_KH.transpose() + K * self.Q * K.transpose();
+        true
+    }
+
+    /// Predict with input `u` and correct with the fix `z`, if any.
+    ///
+    /// Returns the estimated covariance of [x, y, yaw].
+    pub fn update(&mut self, z: Option<Vector2>, u: Vector2, dt: f32) -> Matrix3 {
+        self.predict(u, dt);
+        if let Some(z) = z {
+            self.correct(&z);
+        }
+        self.p_est()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localization::particle_filter::{calc_input, motion_model};
+    use crate::sensor::{gnss::Gnss, odometry::Odometry};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn fusion_beats_gnss_and_dead_reckoning() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (u, dt) = (calc_input(), 0.01);
+        let mut odometry = Odometry::default();
+        let mut gnss = Gnss::default();
+        let mut esekf = ESEKF::default();
+
+        let (mut x_true, mut x_dr) = (Vector4::zeros(), Vector4::zeros());
+        let mut fix = Vector2::zeros();
+        let (mut se, mut se_gnss, mut rejected, n) = (0.0, 0.0, 0, 6000);
+        for _ in 0..n {
+            let ud = odometry.measure(&mut rng, u, dt);
+            x_true = motion_model(x_true, u, dt);
+            x_dr = motion_model(x_dr, ud, dt);
+            let z = gnss.measure(&mut rng, &x_true.xy(), dt);
+            fix = z.unwrap_or(fix);
+            esekf.update(z, ud, dt);
+            rejected += esekf.rejected as usize;
+            // Nothing is left over from the last fix during an outage
+            if z.is_none() {
+                assert!(!esekf.rejected && esekf.nis == Nis::default());
+            }
+
+            se += (esekf.x_est.position() - x_true.xy()).norm_squared();
+            se_gnss += (fix - x_true.xy()).norm_squared();
+        }
+        let rmse = sqrt(se / n as f32);
+        let rmse_gnss = sqrt(se_gnss / n as f32);
+        let error_dr = (x_dr.xy() - x_true.xy()).norm();
+        assert!(rmse < 0.5 * rmse_gnss, "{rmse} vs GNSS {rmse_gnss}");
+        assert!(rmse < error_dr, "{rmse} vs DR {error_dr}");
+        assert!(rejected > 0);
+
+        // The bias absorbs the error of the gyroscope, including its scale factor
+        let bias = odometry.gyro.current_bias() + odometry.gyro.scale_factor * u[1];
+        assert!((esekf.bias - bias).abs() < 2e-3, "{} vs {bias}", esekf.bias);
+    }
+}
@@ -0,0 +1,510 @@
+//! Monte Carlo evaluation of localization filters
+//!
+//! [`MonteCarlo::run`] simulates seeded trials of a [`Scenario`] and reports
+//! how a [`Localizer`] performs over them:
+//!
+//! - RMSE of position and heading, next to that of dead reckoning
+//! - NEES (normalised estimation error squared) of [x, y, yaw]
+//! - NIS (normalised innovation squared), for filters that predict their observations
+//! - Runtime of the filter per step
+//!
+//! A filter is consistent when its errors match its covariance, in which case
+//! the NEES and NIS averaged over trials fall within their chi-square bounds.
+
+use super::association::chi2_inv;
+use super::ekf::EKF;
+use super::histogram_filter::HistogramFilter;
+use super::particle_filter::{calc_input, ParticleFilter, Sensor};
+use super::ukf::UKF;
+use super::*;
+use nalgebra::DMatrix;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Normalised innovation squared of a correction, summed over its observations
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Nis {
+    pub value: f32,
+    /// Degrees of freedom, i.e. the number of observed scalars
+    pub dof: usize,
+}
+
+impl Nis {
+    /// Add the innovation `nu` whose covariance has the inverse `S_inv`
+    pub fn add<const D: usize>(&mut self, nu: &Vector<D>, S_inv: &Mat<D, D>) {
+        self.value += (nu.transpose() * S_inv * nu)[0];
+        self.dof += D;
+    }
+}
+
+/// Localization filter that can be evaluated by [`MonteCarlo`]
+pub trait Localizer {
+    /// Update with range observations `z`, noisy input `u` and the heading `yaw`
+    /// of a compass, which only filters that take the heading as known use
+    fn update(&mut self, rng: &mut StdRng, z: &[Vector3], u: Vector2, yaw: f32, dt: f32);
+    /// Estimated state
+    fn estimate(&self) -> VehicleState;
+    /// Estimated covariance of [x, y, yaw]
+    fn covariance(&self) -> Matrix3;
+    /// NIS of the latest update, if the filter predicts its observations
+    fn nis(&self) -> Option<Nis> {
+        None
+    }
+}
+
+impl Localizer for ParticleFilter {
+    fn update(&mut self, rng: &mut StdRng, z: &[Vector3], u: Vector2, _yaw: f32, dt: f32) {
+        ParticleFilter::update(self, rng, z, u, dt);
+    }
+    fn estimate(&self) -> VehicleState {
+        self.x_est
+    }
+    fn covariance(&self) -> Matrix3 {
+        self.p_est
+    }
+}
+
+impl Localizer for EKF {
+    fn update(&mut self, _rng: &mut StdRng, z: &[Vector3], u: Vector2, _yaw: f32, dt: f32) {
+        EKF::update(self, z, u, dt);
+    }
+    fn estimate(&self) -> VehicleState {
+        self.x_est
+    }
+    fn covariance(&self) -> Matrix3 {
+        self.p_est()
+    }
+    fn nis(&self) -> Option<Nis> {
+        Some(self.nis)
+    }
+}
+
+impl Localizer for UKF {
+    fn update(&mut self, _rng: &mut StdRng, z: &[Vector3], u: Vector2, _yaw: f32, dt: f32) {
+        UKF::update(self, z, u, dt);
+    }
+    fn estimate(&self) -> VehicleState {
+        self.x_est
+    }
+    fn covariance(&self) -> Matrix3 {
+        self.p_est()
+    }
+    fn nis(&self) -> Option<Nis> {
+        Some(self.nis)
+    }
+}
+
+impl Localizer for HistogramFilter {
+    fn update(&mut self, _rng: &mut StdRng, z: &[Vector3], u: Vector2, yaw: f32, dt: f32) {
+        HistogramFilter::update(self, z, u, yaw, dt);
+    }
+    fn estimate(&self) -> VehicleState {
+        self.x_est
+    }
+    fn covariance(&self) -> Matrix3 {
+        self.p_est
+    }
+}
+
+/// Vehicle driven by `input` among `landmarks`, observed by `sensor`
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    /// True positions of landmarks
+    pub landmarks: Vec<Vector2>,
+    pub sensor: Sensor,
+    /// Initial state of the vehicle, where the filters should start
+    pub x0: Vector4,
+    /// Input [velocity, yaw rate] at each step
+    pub input: fn() -> Vector2,
+    pub dt: f32,
+    pub steps: usize,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            landmarks: vec![
+                vector![10.0, 0.0],
+                vector![10.0, 10.0],
+                vector![0.0, 15.0],
+                vector![-5.0, 20.0],
+            ],
+            sensor: Sensor::default(),
+            x0: Vector4::zeros(),
+            input: calc_input,
+            dt: 0.1,
+            steps: 300,
+        }
+    }
+}
+
+impl Scenario {
+    pub fn with_landmarks(mut self, landmarks: Vec<Vector2>) -> Self {
+        self.landmarks = landmarks;
+        self
+    }
+
+    pub fn with_sensor(mut self, sensor: Sensor) -> Self {
+        self.sensor = sensor;
+        self
+    }
+
+    pub fn with_steps(mut self, steps: usize, dt: f32) -> Self {
+        self.steps = steps;
+        self.dt = dt;
+        self
+    }
+
+    /// Drive the vehicle from `x0` with noise drawn from `rng`, handing each
+    /// [`Step`] to `update` along with `rng`, e.g. for a particle filter.
+    ///
+    /// Returns the true and dead-reckoning states at the end.
+    pub fn run<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        mut update: impl FnMut(&mut R, Step),
+    ) -> (Vector4, Vector4) {
+        let (mut x_true, mut x_dr) = (self.x0, self.x0);
+        for k in 0..self.steps {
+            let (z, u) = self.sensor.observe(
+                rng,
+                &mut x_true,
+                &mut x_dr,
+                (self.input)(),
+                &self.landmarks,
+                self.dt,
+            );
+            let step = Step {
+                k,
+                z: &z,
+                u,
+                x_true,
+                x_dr,
+            };
+            update(rng, step);
+        }
+        (x_true, x_dr)
+    }
+}
+
+/// Step `k` of a [`Scenario`], with its observations and noisy input, and the
+/// true and dead-reckoning states after it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Step<'a> {
+    pub k: usize,
+    pub z: &'a [Vector3],
+    pub u: Vector2,
+    pub x_true: Vector4,
+    pub x_dr: Vector4,
+}
+
+/// Runner of seeded trials of a [`Scenario`]
+#[derive(Debug, Clone)]
+pub struct MonteCarlo {
+    pub scenario: Scenario,
+    pub trials: usize,
+    /// Seed of the first trial, with the following ones using the next seeds
+    pub seed: u64,
+    /// Probability of the two-sided chi-square bounds of NEES and NIS
+    pub confidence: f32,
+}
+
+impl Default for MonteCarlo {
+    fn default() -> Self {
+        Self::new(Scenario::default())
+    }
+}
+
+impl MonteCarlo {
+    pub fn new(scenario: Scenario) -> Self {
+        Self {
+            scenario,
+            trials: 20,
+            seed: 0,
+            confidence: 0.95,
+        }
+    }
+
+    pub fn with_trials(mut self, trials: usize) -> Self {
+        self.trials = trials;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_confidence(mut self, confidence: f32) -> Self {
+        self.confidence = confidence;
+        self
+    }
+
+    /// Run every trial with a filter from `new_filter`.
+    ///
+    /// The noise of the scenario only depends on the seed of the trial, so
+    /// different filters are evaluated on the same trajectories and observations.
+    pub fn run<L: Localizer>(&self, mut new_filter: impl FnMut() -> L) -> Report {
+        let (steps, dt) = (self.scenario.steps, self.scenario.dt);
+        let mut trials = Vec::with_capacity(self.trials);
+        let mut nees = ChiSquare::new(steps);
+        let mut nis = ChiSquare::new(steps);
+        for trial in 0..self.trials {
+            let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(trial as u64));
+            let mut filter_rng = StdRng::seed_from_u64(rng.gen());
+            let mut filter = new_filter();
+
+            let mut errors = Errors::default();
+            let mut elapsed = Duration::ZERO;
+            self.scenario.run(&mut rng, |_, step| {
+                let start = Instant::now();
+                filter.update(&mut filter_rng, step.z, step.u, step.x_true.phi(), dt);
+                elapsed += start.elapsed();
+
+                let e = state_residual(&filter.estimate(), &step.x_true).xyz();
+                errors.add(&e, &(step.x_dr - step.x_true));
+                if let Some((value, dof)) = nees_of(&e, &filter.covariance()) {
+                    nees.add(step.k, value, dof);
+                }
+                if let Some(Nis { value, dof }) = filter.nis() {
+                    nis.add(step.k, value, dof);
+                }
+            });
+
+            let n = steps.max(1) as f32;
+            trials.push(Trial {
+                position_rmse: sqrt(errors.position / n),
+                heading_rmse: sqrt(errors.heading / n),
+                dr_position_rmse: sqrt(errors.dr_position / n),
+                time_per_step: elapsed / steps.max(1) as u32,
+            });
+        }
+
+        let n = trials.len().max(1) as f32;
+        let rms = |f: fn(&Trial) -> f32| sqrt(trials.iter().map(|t| f(t).powi(2)).sum::<f32>() / n);
+        Report {
+            position_rmse: rms(|t| t.position_rmse),
+            heading_rmse: rms(|t| t.heading_rmse),
+            dr_position_rmse: rms(|t| t.dr_position_rmse),
+            nees: nees.consistency(self.confidence),
+            nis: nis.consistency(self.confidence),
+            time_per_step: trials.iter().map(|t| t.time_per_step).sum::<Duration>()
+                / trials.len().max(1) as u32,
+            trials,
+        }
+    }
+}
+
+/// Outcome of a single trial
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Trial {
+    /// Root mean square error of position [m]
+    pub position_rmse: f32,
+    /// Root mean square error of heading [rad]
+    pub heading_rmse: f32,
+    /// Root mean square error of the dead-reckoning position [m]
+    pub dr_position_rmse: f32,
+    /// Average runtime of the filter update
+    pub time_per_step: Duration,
+}
+
+/// Chi-square statistic averaged over trials, compared with its bounds
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Consistency {
+    /// Average over all trials and steps
+    pub mean: f32,
+    /// Average degrees of freedom, which `mean` should be close to
+    pub dof: f32,
+    /// Fraction of steps where the average over trials is within the bounds
+    pub in_bounds: f32,
+}
+
+/// Outcome of [`MonteCarlo::run`], with errors aggregated over all trials
+#[derive(Debug, PartialEq, Clone)]
+pub struct Report {
+    pub trials: Vec<Trial>,
+    /// Root mean square error of position [m]
+    pub position_rmse: f32,
+    /// Root mean square error of heading [rad]
+    pub heading_rmse: f32,
+    /// Root mean square error of the dead-reckoning position [m]
+    pub dr_position_rmse: f32,
+    /// NEES of [x, y, yaw], over the components with non-zero variance
+    pub nees: Option<Consistency>,
+    /// NIS of the observations, if the filter reports it
+    pub nis: Option<Consistency>,
+    /// Average runtime of the filter update
+    pub time_per_step: Duration,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "trials:            {}", self.trials.len())?;
+        writeln!(
+            f,
+            "position RMSE:     {:.3} m (DR {:.3} m)",
+            self.position_rmse, self.dr_position_rmse
+        )?;
+        writeln!(
+            f,
+            "heading RMSE:      {:.2} deg",
+            self.heading_rmse.to_degrees()
+        )?;
+        for (name, stat) in [("NEES", self.nees), ("NIS", self.nis)] {
+            match stat {
+                Some(c) => writeln!(
+                    f,
+                    "{name:<5}              {:.2} (dof {:.2}, {:.0}% of steps in bounds)",
+                    c.mean,
+                    c.dof,
+                    100. * c.in_bounds
+                )?,
+                None => writeln!(f, "{name:<5}              -")?,
+            }
+        }
+        write!(f, "time per step:     {:?}", self.time_per_step)
+    }
+}
+
+/// Sums of squared errors over the steps of a trial
+#[derive(Default)]
+struct Errors {
+    position: f32,
+    heading: f32,
+    dr_position: f32,
+}
+
+impl Errors {
+    fn add(&mut self, e: &Vector3, e_dr: &Vector4) {
+        self.position += e[0].powi(2) + e[1].powi(2);
+        self.heading += e[2].powi(2);
+        self.dr_position += e_dr[0].powi(2) + e_dr[1].powi(2);
+    }
+}
+
+/// Sums of a chi-square statistic and its degrees of freedom over trials, per step
+struct ChiSquare {
+    value: Vec<f32>,
+    dof: Vec<usize>,
+    count: Vec<usize>,
+}
+
+impl ChiSquare {
+    fn new(steps: usize) -> Self {
+        Self {
+            value: vec![0.; steps],
+            dof: vec![0; steps],
+            count: vec![0; steps],
+        }
+    }
+
+    fn add(&mut self, k: usize, value: f32, dof: usize) {
+        self.value[k] += value;
+        self.dof[k] += dof;
+        self.count[k] += 1;
+    }
+
+    /// Averages, and the fraction of steps whose sum over trials lies within
+    /// the two-sided `confidence` bounds of its chi-square distribution
+    fn consistency(&self, confidence: f32) -> Option<Consistency> {
+        let count: usize = self.count.iter().sum();
+        if count == 0 {
+            return None;
+        }
+        let (lower, upper) = ((1. - confidence) / 2., (1. + confidence) / 2.);
+        let steps: Vec<bool> = self
+            .value
+            .iter()
+            .zip(self.dof.iter())
+            .filter(|(_, &dof)| dof > 0)
+            .map(|(&value, &dof)| (chi2_inv(lower, dof)..=chi2_inv(upper, dof)).contains(&value))
+            .collect();
+        Some(Consistency {
+            mean: self.value.iter().sum::<f32>() / count as f32,
+            dof: self.dof.iter().sum::<usize>() as f32 / count as f32,
+            in_bounds: steps.iter().filter(|&&b| b).count() as f32 / steps.len().max(1) as f32,
+        })
+    }
+}
+
+/// NEES of error `e` with covariance `P`, over the components with non-zero
+/// variance, along with their number
+fn nees_of(e: &Vector3, P: &Matrix3) -> Option<(f32, usize)> {
+    let ind: Vec<usize> = (0..3).filter(|&i| P[(i, i)] > f32::EPSILON).collect();
+    if ind.is_empty() {
+        return None;
+    }
+    let P = DMatrix::from_fn(ind.len(), ind.len(), |r, c| P[(ind[r], ind[c])]);
+    let e = DMatrix::from_fn(ind.len(), 1, |r, _| e[ind[r]]);
+    let value = P.cholesky()?.solve(&e).dot(&e);
+    value.is_finite().then_some((value, ind.len()))
+}
+
+/// Dead reckoning, which only integrates the inputs, as a baseline [`Localizer`]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DeadReckoning {
+    pub x_est: VehicleState,
+}
+
+impl Localizer for DeadReckoning {
+    fn update(&mut self, _rng: &mut StdRng, _z: &[Vector3], u: Vector2, _yaw: f32, dt: f32) {
+        self.x_est = self.x_est.predict(u, dt);
+    }
+    fn estimate(&self) -> VehicleState {
+        self.x_est
+    }
+    fn covariance(&self) -> Matrix3 {
+        Matrix3::zeros()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_filters_on_same_trials() {
+        let mc = MonteCarlo::new(Scenario::default().with_steps(200, 0.1)).with_trials(5);
+
+        let ekf = mc.run(EKF::default);
+        assert!(ekf.position_rmse < ekf.dr_position_rmse);
+        let (nees, nis) = (ekf.nees.unwrap(), ekf.nis.unwrap());
+        assert!((nees.dof - 3.0).abs() < 0.1, "{ekf}");
+        // One range per landmark in sight
+        assert!(nis.dof > 0. && nis.dof <= 4., "{ekf}");
+        // The default noise of the EKF is larger than that of the sensor, so
+        // it is consistent on the conservative side
+        assert!(nees.mean > 0.2 * nees.dof && nees.mean < nees.dof, "{ekf}");
+        assert!(nis.mean > 0.2 * nis.dof && nis.mean < nis.dof, "{ekf}");
+
+        let pf = mc.run(|| ParticleFilter::default().with_particles(200));
+        assert!(pf.position_rmse < pf.dr_position_rmse);
+        let nees = pf.nees.unwrap();
+        assert!((nees.mean - nees.dof).abs() < 0.5 * nees.dof, "{pf}");
+        assert!(nees.in_bounds > 0.5, "{pf}");
+        assert!(pf.nis.is_none());
+        // Same trajectories and observations for every filter
+        assert_eq!(pf.dr_position_rmse, ekf.dr_position_rmse);
+
+        let dr = mc.run(|| DeadReckoning {
+            x_est: VehicleState::default(),
+        });
+        assert!((dr.position_rmse - dr.dr_position_rmse).abs() < 1e-4);
+        assert!(dr.nees.is_none());
+
+        // Same seed gives the same statistics
+        let again = mc.run(EKF::default);
+        assert_eq!(again.position_rmse, ekf.position_rmse);
+        assert_eq!(again.nees, ekf.nees);
+    }
+
+    #[test]
+    fn nees_skips_known_components() {
+        let P = diag![0.25, 1.0, 0.0];
+        let (value, dof) = nees_of(&vector![0.5, 1.0, 0.0], &P).unwrap();
+        assert_eq!(dof, 2);
+        assert!((value - 2.0).abs() < 1e-5);
+        assert!(nees_of(&Vector3::zeros(), &Matrix3::zeros()).is_none());
+    }
+}
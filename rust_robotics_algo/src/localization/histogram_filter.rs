@@ -0,0 +1,266 @@
+use super::particle_filter::{gauss_likelihood, Observation};
+use super::*;
+use nalgebra::DMatrix;
+
+/// Lower left corner of the default grid, around the landmarks of the vehicle scenario [m]
+pub const GRID_MIN: [f32; 2] = [-15.0, -5.0];
+/// Upper right corner of the default grid [m]
+pub const GRID_MAX: [f32; 2] = [15.0, 25.0];
+/// Default side length of a cell [m]
+pub const GRID_RESOLUTION: f32 = 0.5;
+
+/// Largest variance [cells^2] spread by a single pass of the diffusion kernel
+const MAX_PASS_VARIANCE: f32 = 0.5;
+
+/// Histogram (discrete Bayes) filter localization over a grid of positions,
+/// with range observations to known landmarks.
+///
+/// The grid only spans x and y, so the heading is assumed known, e.g. from a compass.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HistogramFilter {
+    /// Probability of the vehicle being in each cell, with rows along x and
+    /// columns along y
+    pub belief: DMatrix<f32>,
+    /// Lower left corner of the grid [m]
+    pub min: Vector2,
+    /// Side length of a cell [m]
+    pub resolution: f32,
+    /// Estimated state, with the heading and velocity taken from the inputs
+    pub x_est: VehicleState,
+    /// Estimated covariance of [x, y, yaw], where yaw is known
+    pub p_est: Matrix3,
+    /// Variance of range measurement [m^2]
+    pub Q: Matrix1,
+    /// Covariance of input [velocity, yaw rate], of which the velocity spreads the belief
+    pub R: Matrix2,
+    /// Motion of less than a cell, carried over to the next prediction [m]
+    offset: Vector2,
+}
+
+impl Default for HistogramFilter {
+    fn default() -> Self {
+        Self::new(GRID_MIN.into(), GRID_MAX.into(), GRID_RESOLUTION)
+    }
+}
+
+impl HistogramFilter {
+    /// Instantiate with a uniform belief over the cells of side `resolution`
+    /// covering `min` to `max`
+    pub fn new(min: Vector2, max: Vector2, resolution: f32) -> Self {
+        let size = (max - min) / resolution;
+        let nx = (size.x.ceil() as usize).max(1);
+        let ny = (size.y.ceil() as usize).max(1);
+        let mut filter = Self {
+            belief: DMatrix::zeros(nx, ny),
+            min,
+            resolution,
+            x_est: VehicleState::default(),
+            p_est: Matrix3::zeros(),
+            Q: diag![0.2],
+            R: diag![1.0, (30_f32).to_radians()],
+            offset: Vector2::zeros(),
+        };
+        filter.reset();
+        filter
+    }
+
+    pub fn with_noise(mut self, Q: Matrix1, R: Matrix2) -> Self {
+        self.Q = Q;
+        self.R = R;
+        self
+    }
+
+    /// Start from a Gaussian belief around the position of `x`, with standard
+    /// deviation `std` [m]
+    pub fn with_state(mut self, x: VehicleState, std: f32) -> Self {
+        let sigma = std.max(self.resolution / 2.0);
+        self.belief = DMatrix::from_fn(self.belief.nrows(), self.belief.ncols(), |i, j| {
+            let c = self.cell_center(i, j);
+            gauss_likelihood(hypot(c.x - x.x, c.y - x.y), sigma)
+        });
+        self.x_est = x;
+        self.offset = Vector2::zeros();
+        self.normalize();
+        self
+    }
+
+    /// Spread the belief uniformly over the grid
+    pub fn reset(&mut self) {
+        self.belief.fill(1.0);
+        self.offset = Vector2::zeros();
+        self.normalize();
+    }
+
+    /// Position of the center of cell (`i`, `j`)
+    pub fn cell_center(&self, i: usize, j: usize) -> Vector2 {
+        self.min + vector![i as f32 + 0.5, j as f32 + 0.5] * self.resolution
+    }
+
+    /// Move the belief with input `u` along the known heading `yaw`, spreading
+    /// it by the velocity noise
+    pub fn predict(&mut self, u: Vector2, yaw: f32, dt: f32) {
+        self.offset += vector![cos(yaw), sin(yaw)] * u[0] * dt;
+        let shift = (self.offset / self.resolution).map(|s| s.round());
+        self.offset -= shift * self.resolution;
+        self.shift(shift.x as isize, shift.y as isize);
+        self.diffuse(self.R[(0, 0)] * dt * dt / self.resolution.powi(2));
+
+        self.x_est.phi = yaw;
+        self.x_est.v = u[0];
+        self.estimate();
+    }
+
+    /// Weight the belief by the likelihood of range observations `[d, x, y]`.
+    ///
+    /// If no cell with any belief explains the observations, the belief is
+    /// taken from the observations alone.
+    pub fn correct(&mut self, z: &[Vector3]) {
+        if z.is_empty() {
+            return;
+        }
+        let sigma = sqrt(self.Q[0]);
+        let likelihood = DMatrix::from_fn(self.belief.nrows(), self.belief.ncols(), |i, j| {
+            let c = self.cell_center(i, j);
+            z.iter()
+                .map(|zi| gauss_likelihood(hypot(c.x - zi.x(), c.y - zi.y()) - zi.d(), sigma))
+                .product::<f32>()
+        });
+
+        let posterior = self.belief.component_mul(&likelihood);
+        self.belief = if posterior.sum() > 0.0 {
+            posterior
+        } else {
+            likelihood
+        };
+        self.normalize();
+        self.estimate();
+    }
+
+    /// Predict with input `u` and heading `yaw`, then correct with observations `z`.
+    ///
+    /// Returns the estimated covariance of [x, y, yaw].
+    pub fn update(&mut self, z: &[Vector3], u: Vector2, yaw: f32, dt: f32) -> Matrix3 {
+        self.predict(u, yaw, dt);
+        self.correct(z);
+        self.p_est
+    }
+
+    /// Move the belief by whole cells, dropping what leaves the grid
+    fn shift(&mut self, di: isize, dj: isize) {
+        if di == 0 && dj == 0 {
+            return;
+        }
+        let (nx, ny) = self.belief.shape();
+        let old = std::mem::replace(&mut self.belief, DMatrix::zeros(nx, ny));
+        for ((i, j), p) in old.iter().enumerate().map(|(k, p)| ((k % nx, k / nx), p)) {
+            let (ti, tj) = (i as isize + di, j as isize + dj);
+            if (0..nx as isize).contains(&ti) && (0..ny as isize).contains(&tj) {
+                self.belief[(ti as usize, tj as usize)] = *p;
+            }
+        }
+        self.normalize();
+    }
+
+    /// Convolve the belief with a kernel of the given `variance` [cells^2] along
+    /// each axis, as repeated passes of `[a, 1 - 2a, a]`
+    fn diffuse(&mut self, variance: f32) {
+        if variance <= 0.0 {
+            return;
+        }
+        let passes = (variance / MAX_PASS_VARIANCE).ceil() as usize;
+        let a = variance / (2.0 * passes as f32);
+        let (nx, ny) = self.belief.shape();
+        for _ in 0..passes {
+            let b = &self.belief;
+            let at = |i: isize, j: isize| {
+                if (0..nx as isize).contains(&i) && (0..ny as isize).contains(&j) {
+                    b[(i as usize, j as usize)]
+                } else {
+                    0.0
+                }
+            };
+            let along_x = DMatrix::from_fn(nx, ny, |i, j| {
+                let (i, j) = (i as isize, j as isize);
+                a * at(i - 1, j) + (1.0 - 2.0 * a) * at(i, j) + a * at(i + 1, j)
+            });
+            self.belief = DMatrix::from_fn(nx, ny, |i, j| {
+                let value = |j: isize| {
+                    if (0..ny as isize).contains(&j) {
+                        along_x[(i, j as usize)]
+                    } else {
+                        0.0
+                    }
+                };
+                let j = j as isize;
+                a * value(j - 1) + (1.0 - 2.0 * a) * value(j) + a * value(j + 1)
+            });
+        }
+        self.normalize();
+    }
+
+    /// Scale the belief to sum to one, or spread it uniformly if it vanished
+    fn normalize(&mut self) {
+        let sum = self.belief.sum();
+        if sum > 0.0 && sum.is_finite() {
+            self.belief /= sum;
+        } else {
+            let n = self.belief.len() as f32;
+            self.belief.fill(1.0 / n);
+        }
+    }
+
+    /// Mean and covariance of the position over the belief
+    fn estimate(&mut self) {
+        let (nx, ny) = self.belief.shape();
+        let mut mean = Vector2::zeros();
+        let mut second = Matrix2::zeros();
+        for i in 0..nx {
+            for j in 0..ny {
+                let p = self.belief[(i, j)];
+                let c = self.cell_center(i, j);
+                mean += p * c;
+                second += p * c * c.transpose();
+            }
+        }
+        // Variance of the position within a cell
+        let cell = self.resolution.powi(2) / 12.0;
+        let cov = second - mean * mean.transpose() + Matrix2::identity() * cell;
+
+        self.x_est.x = mean.x;
+        self.x_est.y = mean.y;
+        self.p_est = Matrix3::zeros();
+        self.p_est.fixed_slice_mut::<2, 2>(0, 0).copy_from(&cov);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localization::evaluation::Scenario;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn motion_moves_and_spreads_belief() {
+        let mut filter = HistogramFilter::default().with_state(VehicleState::default(), 0.0);
+        let p0 = filter.p_est;
+        for _ in 0..20 {
+            filter.predict(vector![1.0, 0.0], 0.0, 0.1);
+        }
+        assert!((filter.x_est.x() - 2.0).abs() < filter.resolution / 2.0);
+        assert!(filter.x_est.y().abs() < 1e-3);
+        assert!(filter.p_est[(0, 0)] > p0[(0, 0)]);
+        assert!((filter.belief.sum() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn tracks_vehicle_from_uniform_belief() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut filter = HistogramFilter::default();
+        let scenario = Scenario::default();
+        let (x_true, _) = scenario.run(&mut rng, |_, step| {
+            filter.update(step.z, step.u, step.x_true.phi(), scenario.dt);
+        });
+        let err = hypot(filter.x_est.x() - x_true.x(), filter.x_est.y() - x_true.y());
+        assert!(err < 1.0, "Position error: {err}");
+    }
+}
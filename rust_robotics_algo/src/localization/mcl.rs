@@ -0,0 +1,338 @@
+//! Monte Carlo localization against an occupancy grid
+//!
+//! A [`ParticleFilter`] is weighted by lidar scans through either measurement
+//! model of Probabilistic Robotics (Thrun et al., ch. 6):
+//!
+//! - [`BeamModel`]: ray casts each beam in the map, mixing a Gaussian around
+//!   the expected range with short readings, max-range readings and random noise
+//! - [`LikelihoodField`]: looks up the distance from the end of each beam to the
+//!   nearest obstacle, which is much cheaper as it needs no ray casting
+
+use super::particle_filter::{MeasurementModel, ParticleFilter};
+use super::*;
+use crate::mapping::{GridMap, OccupancyGrid, RayCast};
+use crate::sensor::lidar::Scan;
+use rand::Rng;
+
+/// Default number of beams of a scan used to weight the particles
+pub const MAX_BEAMS: usize = 30;
+
+/// Bearing and range of every `n`th beam of `scan`, so that at most
+/// `max_beams` are used
+fn beams(scan: &Scan, max_beams: usize) -> impl Iterator<Item = (f32, Option<f32>)> + '_ {
+    let step = scan.len().div_ceil(max_beams.max(1));
+    scan.angles
+        .iter()
+        .copied()
+        .zip(scan.ranges.iter().copied())
+        .step_by(step.max(1))
+}
+
+/// Beam measurement model, ray casting each beam in the map
+#[derive(Debug, PartialEq, Clone)]
+pub struct BeamModel {
+    pub map: OccupancyGrid,
+    /// Weight of measurements around the expected range
+    pub z_hit: f32,
+    /// Weight of measurements short of the expected range, e.g. from people
+    pub z_short: f32,
+    /// Weight of max-range measurements, i.e. missed obstacles
+    pub z_max: f32,
+    /// Weight of uniformly random measurements
+    pub z_rand: f32,
+    /// Standard deviation of measurements around the expected range [m]
+    pub sigma_hit: f32,
+    /// Rate of the exponential distribution of short measurements [1/m]
+    pub lambda_short: f32,
+    /// Number of beams of a scan used
+    pub max_beams: usize,
+}
+
+impl BeamModel {
+    pub fn new(map: OccupancyGrid) -> Self {
+        Self {
+            map,
+            z_hit: 0.8,
+            z_short: 0.1,
+            z_max: 0.05,
+            z_rand: 0.05,
+            sigma_hit: 0.2,
+            lambda_short: 0.5,
+            max_beams: MAX_BEAMS,
+        }
+    }
+
+    pub fn with_weights(mut self, z_hit: f32, z_short: f32, z_max: f32, z_rand: f32) -> Self {
+        self.z_hit = z_hit;
+        self.z_short = z_short;
+        self.z_max = z_max;
+        self.z_rand = z_rand;
+        self
+    }
+
+    pub fn with_max_beams(mut self, max_beams: usize) -> Self {
+        self.max_beams = max_beams;
+        self
+    }
+
+    /// Likelihood of range `z` where `expected` is the range to the map,
+    /// either of which may be the maximum range `z_max`
+    fn beam_likelihood(&self, z: f32, expected: f32, max_range: f32) -> f32 {
+        let hit = if z < max_range {
+            let dz = z - expected;
+            exp(-dz * dz / (2.0 * self.sigma_hit.powi(2))) / sqrt(TAU * self.sigma_hit.powi(2))
+        } else {
+            0.0
+        };
+        let short = if z <= expected {
+            let l = self.lambda_short;
+            l * exp(-l * z) / (1.0 - exp(-l * expected)).max(f32::EPSILON)
+        } else {
+            0.0
+        };
+        let max = if z >= max_range { 1.0 } else { 0.0 };
+        self.z_hit * hit + self.z_short * short + self.z_max * max + self.z_rand / max_range
+    }
+}
+
+impl MeasurementModel<Scan> for BeamModel {
+    fn log_likelihood(&self, x: &VehicleState, scan: &Scan) -> f32 {
+        let pose = x.pose();
+        let origin = pose.translation();
+        beams(scan, self.max_beams)
+            .map(|(angle, range)| {
+                let max = scan.max_range;
+                let expected = self
+                    .map
+                    .cast(&origin, pose.theta + angle, max)
+                    .unwrap_or(max);
+                self.beam_likelihood(range.unwrap_or(max), expected, max)
+                    .ln()
+            })
+            .sum()
+    }
+}
+
+/// Likelihood field measurement model, scoring the end of each beam by its
+/// distance to the nearest obstacle in the map
+#[derive(Debug, PartialEq, Clone)]
+pub struct LikelihoodField {
+    /// Distance from each cell to the nearest occupied cell of the map [m]
+    pub distance: GridMap<f32>,
+    /// Weight of measurements near obstacles
+    pub z_hit: f32,
+    /// Weight of uniformly random measurements
+    pub z_rand: f32,
+    /// Standard deviation of the distance to the nearest obstacle [m]
+    pub sigma_hit: f32,
+    /// Number of beams of a scan used
+    pub max_beams: usize,
+}
+
+impl LikelihoodField {
+    pub fn new(map: &OccupancyGrid) -> Self {
+        Self {
+            distance: map.occupied().distance_transform(),
+            z_hit: 0.9,
+            z_rand: 0.1,
+            sigma_hit: 0.2,
+            max_beams: MAX_BEAMS,
+        }
+    }
+
+    pub fn with_weights(mut self, z_hit: f32, z_rand: f32) -> Self {
+        self.z_hit = z_hit;
+        self.z_rand = z_rand;
+        self
+    }
+
+    pub fn with_max_beams(mut self, max_beams: usize) -> Self {
+        self.max_beams = max_beams;
+        self
+    }
+}
+
+impl MeasurementModel<Scan> for LikelihoodField {
+    fn log_likelihood(&self, x: &VehicleState, scan: &Scan) -> f32 {
+        let pose = x.pose();
+        let sigma = self.sigma_hit;
+        // Max-range readings carry no information in this model
+        beams(scan, self.max_beams)
+            .filter_map(|(angle, range)| range.map(|range| (angle, range)))
+            .map(|(angle, range)| {
+                let end = pose.transform_point(&vector![range * cos(angle), range * sin(angle)]);
+                let hit = match self.distance.get(&end) {
+                    Some(d) if d.is_finite() => {
+                        exp(-d * d / (2.0 * sigma * sigma)) / sqrt(TAU * sigma * sigma)
+                    }
+                    _ => 0.0,
+                };
+                (self.z_hit * hit + self.z_rand / scan.max_range).ln()
+            })
+            .sum()
+    }
+}
+
+/// Measurement model of lidar scans against an occupancy grid
+#[derive(Debug, PartialEq, Clone)]
+pub enum ScanModel {
+    Beam(BeamModel),
+    LikelihoodField(LikelihoodField),
+}
+
+impl ScanModel {
+    /// Model of `kind` against `map`, with default parameters
+    pub fn new(kind: ScanModelKind, map: &OccupancyGrid) -> Self {
+        match kind {
+            ScanModelKind::Beam => Self::Beam(BeamModel::new(map.clone())),
+            ScanModelKind::LikelihoodField => Self::LikelihoodField(LikelihoodField::new(map)),
+        }
+    }
+
+    pub fn kind(&self) -> ScanModelKind {
+        match self {
+            Self::Beam(_) => ScanModelKind::Beam,
+            Self::LikelihoodField(_) => ScanModelKind::LikelihoodField,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.kind().name()
+    }
+}
+
+/// Kind of [`ScanModel`], to pick one without building it
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ScanModelKind {
+    Beam,
+    LikelihoodField,
+}
+
+impl ScanModelKind {
+    pub const ALL: [Self; 2] = [Self::LikelihoodField, Self::Beam];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Beam => "Beam",
+            Self::LikelihoodField => "Likelihood Field",
+        }
+    }
+}
+
+impl MeasurementModel<Scan> for ScanModel {
+    fn log_likelihood(&self, x: &VehicleState, scan: &Scan) -> f32 {
+        match self {
+            Self::Beam(model) => model.log_likelihood(x, scan),
+            Self::LikelihoodField(model) => model.log_likelihood(x, scan),
+        }
+    }
+}
+
+impl ParticleFilter {
+    /// Spread the particles uniformly over the free cells of `map`, with
+    /// uniformly random headings, for global localization
+    pub fn spread_over<R: Rng + ?Sized>(&mut self, rng: &mut R, map: &OccupancyGrid) {
+        let (nx, ny) = map.log_odds.shape();
+        let free: Vec<_> = (0..nx)
+            .flat_map(|i| (0..ny).map(move |j| (i, j)))
+            .filter(|(i, j)| map.is_free(*i, *j))
+            .collect();
+        if free.is_empty() {
+            return;
+        }
+        let half = map.resolution() / 2.0;
+        for x in self.px.iter_mut() {
+            let (i, j) = free[rng.gen_range(0..free.len())];
+            let c = map.log_odds.cell_center(i, j);
+            x.x = c.x + rng.gen_range(-half..half);
+            x.y = c.y + rng.gen_range(-half..half);
+            x.phi = rng.gen_range(-PI..PI);
+            x.v = 0.0;
+        }
+        self.pw.fill(1.0 / self.num_particles() as f32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localization::particle_filter::calc_input;
+    use crate::mapping::{Polygon, World};
+    use crate::pose::angle_diff;
+    use crate::sensor::lidar::Lidar;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Map of a room with an obstacle in it, built from scans at known poses
+    fn mapped_room(rng: &mut StdRng) -> (World, OccupancyGrid) {
+        let world = World::default()
+            .with_polygon(&Polygon::rectangle(vector![-12., -2.], vector![12., 22.]))
+            .with_polygon(&Polygon::rectangle(vector![-3., 7.], vector![1., 11.]))
+            .with_polygon(&Polygon::new(vec![
+                vector![3., 13.],
+                vector![6., 13.],
+                vector![4.5, 16.],
+            ]));
+        let lidar = Lidar::default().with_max_range(30.0);
+        let mut map = OccupancyGrid::new(vector![-13., -3.], vector![13., 23.], 0.25);
+        for x in [-8.0, 0.0, 8.0] {
+            for y in [2.0, 10.0, 18.0] {
+                let pose = Pose2::new(x, y, 0.0);
+                map.update(&pose, &lidar.scan(rng, &pose, &world));
+            }
+        }
+        (world, map)
+    }
+
+    #[test]
+    fn both_models_track_vehicle_in_map() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (world, map) = mapped_room(&mut rng);
+        let lidar = Lidar::default();
+        let models = [
+            ScanModel::Beam(BeamModel::new(map.clone())),
+            ScanModel::LikelihoodField(LikelihoodField::new(&map)),
+        ];
+
+        for model in models {
+            let mut rng = StdRng::seed_from_u64(1);
+            let x0 = VehicleState::default();
+            let mut pf = ParticleFilter::new(200)
+                .with_noise(diag![0.2], diag![0.1, 0.05])
+                .with_state(x0);
+            let (mut x_true, dt) = (x0, 0.1);
+            for _ in 0..100 {
+                x_true = x_true.predict(calc_input(), dt);
+                let scan = lidar.scan(&mut rng, &x_true.pose(), &world);
+                pf.update_with(&mut rng, &model, &scan, calc_input(), dt);
+            }
+            let err = hypot(pf.x_est.x() - x_true.x(), pf.x_est.y() - x_true.y());
+            assert!(err < 0.5, "{}: position error {err}", model.name());
+            let yaw_err = angle_diff(pf.x_est.phi(), x_true.phi()).abs();
+            assert!(yaw_err < 0.1, "{}: heading error {yaw_err}", model.name());
+        }
+    }
+
+    #[test]
+    fn models_prefer_true_pose() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let (world, map) = mapped_room(&mut rng);
+        let x_true = VehicleState::new(-6.0, 4.0, 1.0, 0.0);
+        let scan = Lidar::default().scan(&mut rng, &x_true.pose(), &world);
+
+        let beam = BeamModel::new(map.clone());
+        let field = LikelihoodField::new(&map);
+        for offset in [vector![0.5, 0.0, 0.0, 0.0], vector![0.0, 0.0, 0.2, 0.0]] {
+            let mut x = x_true;
+            x += offset;
+            assert!(beam.log_likelihood(&x_true, &scan) > beam.log_likelihood(&x, &scan));
+            assert!(field.log_likelihood(&x_true, &scan) > field.log_likelihood(&x, &scan));
+        }
+
+        let mut pf = ParticleFilter::new(500);
+        pf.spread_over(&mut rng, &map);
+        for x in pf.px.iter() {
+            let (i, j) = map.log_odds.cell_index(&x.position()).unwrap();
+            assert!(map.is_free(i, j));
+        }
+    }
+}
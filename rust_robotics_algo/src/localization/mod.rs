@@ -1,8 +1,18 @@
+use crate::pose::{angle_diff, circular_mean};
 use crate::prelude::*;
 
+pub mod association;
+pub mod ekf;
+pub mod es_ekf;
+pub mod evaluation;
+pub mod histogram_filter;
+pub mod mcl;
 pub mod particle_filter;
+pub mod range_bearing;
+pub mod resampling;
+pub mod ukf;
 
-/// Getter methods for state vector
+/// Getter methods for state vector, see [`VehicleState`] for the typed state
 pub trait StateVector {
     /// X Position [m]
     fn x(&self) -> f32;
@@ -16,15 +26,64 @@ pub trait StateVector {
 
 impl StateVector for Vector4 {
     fn x(&self) -> f32 {
-        *self.get(0).expect("Cannot get 1st element of Vector4")
+        self[0]
     }
     fn y(&self) -> f32 {
-        *self.get(1).expect("Cannot get 2nd element of Vector4")
+        self[1]
     }
     fn phi(&self) -> f32 {
-        *self.get(2).expect("Cannot get 3rd element of Vector4")
+        self[2]
     }
     fn v(&self) -> f32 {
-        *self.get(3).expect("Cannot get 4th element of Vector4")
+        self[3]
     }
 }
+
+impl StateVector for VehicleState {
+    fn x(&self) -> f32 {
+        self.x
+    }
+    fn y(&self) -> f32 {
+        self.y
+    }
+    fn phi(&self) -> f32 {
+        self.phi
+    }
+    fn v(&self) -> f32 {
+        self.v
+    }
+}
+
+/// Weighted mean of states, with weights summing to one.
+///
+/// Headings are averaged on the circle, relative to the heading of the first
+/// state so that the mean stays as continuous as the states themselves.
+pub fn mean_state<X: StateVector>(
+    states: impl IntoIterator<Item = X>,
+    weights: &[f32],
+) -> VehicleState {
+    let mut mean = VehicleState::default();
+    let mut reference = None;
+    let mut headings = Vec::with_capacity(weights.len());
+    for (x, w) in states.into_iter().zip(weights.iter().copied()) {
+        mean.x += w * x.x();
+        mean.y += w * x.y();
+        mean.v += w * x.v();
+        let reference = *reference.get_or_insert(x.phi());
+        headings.push((x.phi() - reference, w));
+    }
+    if let Some(reference) = reference {
+        mean.phi = reference + circular_mean(headings);
+    }
+    mean
+}
+
+/// Difference `a - b` of two states, with the heading difference wrapped to [-PI, PI)
+pub fn state_residual(a: &impl StateVector, b: &impl StateVector) -> Vector4 {
+    vector![
+        a.x() - b.x(),
+        a.y() - b.y(),
+        angle_diff(a.phi(), b.phi()),
+        a.v() - b.v()
+    ]
+}
@@ -1,24 +1,45 @@
+pub use super::resampling::*;
 use super::*;
-use nalgebra::{ArrayStorage, Matrix, U1, U100, U4};
+use nalgebra::{Matrix4xX, RowDVector};
+use rand::Rng;
 
-/// Maximum observation range
+/// Default maximum observation range
 pub const MAX_RANGE: f32 = 20.0;
 
+/// Default number of particles
 pub const NP: usize = 100;
+/// Default threshold on the effective number of particles for resampling
 pub const NTh: f32 = NP as f32 / 2.0;
 
-pub type PX = Matrix<f32, U4, U100, ArrayStorage<f32, 4, 100>>;
-pub type PW = Matrix<f32, U1, U100, ArrayStorage<f32, 1, 100>>;
+/// Particle states (4 x number of particles), as taken by [`pf_localization`]
+pub type PX = Matrix4xX<f32>;
+/// Particle weights (1 x number of particles)
+pub type PW = RowDVector<f32>;
 
 /// Generate random float between [-1.0, 1.0]
 pub fn rand() -> f32 {
-    2.0 * (rand::random::<f32>() - 0.5)
+    rand_with(&mut rand::thread_rng())
+}
+
+/// Generate random float between [-1.0, 1.0] from the given `rng`
+pub fn rand_with<R: Rng + ?Sized>(rng: &mut R) -> f32 {
+    2.0 * (rng.gen::<f32>() - 0.5)
+}
+
+/// Sample from the standard normal distribution with the given `rng`
+pub fn randn_with<R: Rng + ?Sized>(rng: &mut R) -> f32 {
+    // Box-Muller transform
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.gen();
+    sqrt(-2.0 * u1.ln()) * cos(TAU * u2)
 }
 
 pub fn rand_unifrom(low: f32, high: f32) -> f32 {
-    use rand::Rng;
+    rand_unifrom_with(&mut rand::thread_rng(), low, high)
+}
+
+pub fn rand_unifrom_with<R: Rng + ?Sized>(rng: &mut R, low: f32, high: f32) -> f32 {
     let lim = rand::distributions::Uniform::new(low, high);
-    let mut rng = rand::thread_rng();
     rng.sample(lim)
 }
 
@@ -28,65 +49,373 @@ pub fn calc_input() -> Vector2 {
     vector![v, yaw_rate]
 }
 
-pub fn observation(
+/// Simulated range sensor to known landmarks, along with noisy odometry input
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Sensor {
+    /// Variance of range measurement [m^2]
+    pub Q: Matrix1,
+    /// Covariance of input [velocity, yaw rate]
+    pub R: Matrix2,
+    /// Maximum observation range [m]
+    pub max_range: f32,
+}
+
+impl Default for Sensor {
+    fn default() -> Self {
+        Self {
+            Q: diag![0.2],
+            R: diag![1.0, (30_f32).to_radians()],
+            max_range: MAX_RANGE,
+        }
+    }
+}
+
+impl Sensor {
+    pub fn with_noise(mut self, Q: Matrix1, R: Matrix2) -> Self {
+        self.Q = Q;
+        self.R = R;
+        self
+    }
+
+    pub fn with_max_range(mut self, max_range: f32) -> Self {
+        self.max_range = max_range;
+        self
+    }
+
+    /// Whether `landmark` is within range of the vehicle at state `x`
+    pub fn is_detected(&self, x: &Vector4, landmark: &Vector2) -> bool {
+        hypot(x.x() - landmark.x, x.y() - landmark.y) <= self.max_range
+    }
+
+    /// Move the true and dead-reckoning states with input `u`, and return
+    /// the range observations `[d, x, y]` of landmarks within range along
+    /// with the noisy input.
+    pub fn observe<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        x_true: &mut Vector4,
+        xd: &mut Vector4,
+        u: Vector2,
+        rf_id: &[Vector2],
+        dt: f32,
+    ) -> (Vec<Vector3>, Vector2) {
+        *x_true = motion_model(*x_true, u, dt);
+
+        let z = self.ranges(rng, x_true, rf_id);
+        let ud1 = u[0] + rand_with(rng) * sqrt(self.R[(0, 0)]);
+        let ud2 = u[1] + rand_with(rng) * sqrt(self.R[(1, 1)]);
+        let ud = Vector2::new(ud1, ud2);
+
+        *xd = motion_model(*xd, ud, dt);
+
+        (z, ud)
+    }
+
+    /// Noisy range observations `[d, x, y]` from `x` of the landmarks within
+    /// range, e.g. with the input measured by [`Odometry`](crate::sensor::odometry::Odometry)
+    /// rather than perturbed as in [`observe`](Self::observe)
+    pub fn ranges<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        x: &Vector4,
+        rf_id: &[Vector2],
+    ) -> Vec<Vector3> {
+        rf_id
+            .iter()
+            .filter(|rf_id| self.is_detected(x, rf_id))
+            .map(|rf_id| {
+                let d = hypot(x.x() - rf_id.x, x.y() - rf_id.y);
+                let dn = d + rand_with(rng) * sqrt(self.Q[0]);
+                Vector3::new(dn, rf_id.x, rf_id.y)
+            })
+            .collect()
+    }
+}
+
+/// Thin wrapper around [`Sensor::observe`] with the default sensor
+pub fn observation<R: Rng + ?Sized>(
+    rng: &mut R,
     x_true: &mut Vector4,
     xd: &mut Vector4,
     u: Vector2,
     rf_id: &[Vector2],
     dt: f32,
 ) -> (Vec<Vector3>, Vector2) {
-    *x_true = motion_model(*x_true, u, dt);
-
-    let Q_sim = diag![0.2];
-    let R_sim = diag![1.0, (30_f32).to_radians()];
-    let mut z = Vec::new();
-
-    for rf_id in rf_id.iter() {
-        let dx = x_true.x() - rf_id.x();
-        let dy = x_true.y() - rf_id.y();
-        let d = hypot(dx, dy);
-        if d <= MAX_RANGE {
-            let dn = d + rand() * sqrt(Q_sim[0]);
-            let zi = Vector3::new(dn, rf_id.x(), rf_id.y());
-            z.push(zi);
-        }
-    }
-    let ud1 = u.x() + rand() * sqrt(R_sim.get_diagonal(0));
-    let ud2 = u.y() + rand() * sqrt(R_sim.get_diagonal(1));
-    let ud = Vector2::new(ud1, ud2);
-
-    *xd = motion_model(*xd, ud, dt);
-
-    (z, ud)
+    Sensor::default().observe(rng, x_true, xd, u, rf_id, dt)
 }
 
+/// State after driving for `dt` with input `u`, see [`VehicleState::predict`]
 pub fn motion_model(x: Vector4, u: Vector2, dt: f32) -> Vector4 {
-    let F = diag![1., 1., 1., 0.];
+    VehicleState::from(x).predict(u, dt).into()
+}
 
-    let B = matrix![cos(x.phi()) * dt, 0.;
-                    sin(x.phi()) * dt, 0.;
-                    0., dt;
-                    1., 0.];
+/// Model of the likelihood of observations `Z` of a particle at state `x`,
+/// to weight the particles of a [`ParticleFilter`]
+pub trait MeasurementModel<Z: ?Sized> {
+    /// Logarithm of the likelihood of observations `z` from state `x`
+    fn log_likelihood(&self, x: &VehicleState, z: &Z) -> f32;
+}
 
-    F * x + B * u
+/// Range observations `[d, x, y]` to known landmarks
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LandmarkRanges {
+    /// Variance of range measurement [m^2]
+    pub Q: Matrix1,
+}
+
+impl MeasurementModel<[Vector3]> for LandmarkRanges {
+    fn log_likelihood(&self, x: &VehicleState, z: &[Vector3]) -> f32 {
+        z.iter()
+            .map(|zi| {
+                let dz = hypot(x.x - zi.x(), x.y - zi.y()) - zi.d();
+                -0.5 * (TAU * self.Q[0]).ln() - dz.powi(2) / (2.0 * self.Q[0])
+            })
+            .sum()
+    }
 }
 
 pub fn gauss_likelihood(x: f32, sigma: f32) -> f32 {
     1.0 / sqrt(2.0 * PI * sigma.powi(2)) * exp(-(x.powi(2)) / (2.0 * sigma.powi(2)))
 }
 
+/// Weighted mean of the particles, averaging the headings on the circle, see [`mean_state`]
+pub fn calc_mean(px: &PX, pw: &PW) -> Vector4 {
+    mean_state(px.column_iter().map(|x| x.into_owned()), pw.as_slice()).into()
+}
+
+/// Weighted covariance of [x, y, yaw] of the particles around `x_est`, with
+/// the heading differences wrapped
 pub fn calc_covariance(x_est: &Vector4, px: &PX, pw: &PW) -> Matrix3 {
+    particle_covariance(x_est, px.column_iter().map(|x| x.into_owned()), pw)
+}
+
+/// [`calc_covariance`] of particles of any state type, e.g. [`VehicleState`]
+pub fn particle_covariance<X: StateVector>(
+    x_est: &impl StateVector,
+    px: impl IntoIterator<Item = X>,
+    pw: &PW,
+) -> Matrix3 {
     let mut cov = zeros!(3, 3);
-    for i in 0..NP {
-        let dx = px.column(i) - x_est;
-        let dx = dx.rows(0, 3);
-        cov += pw[i] * (dx * dx.transpose());
+    for (x, w) in px.into_iter().zip(pw.iter()) {
+        let dx = state_residual(&x, x_est).xyz();
+        cov += *w * (dx * dx.transpose());
     }
-    cov *= 1. / (vector![1_f32] - pw * pw.transpose())[0];
+    cov *= 1. / (1. - pw.norm_squared());
     cov
 }
 
-pub fn pf_localization(
+/// Particle filter localization with range observations to known landmarks
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParticleFilter {
+    /// Estimated state
+    pub x_est: VehicleState,
+    /// Estimated covariance of [x, y, yaw]
+    pub p_est: Matrix3,
+    /// Particle states
+    pub px: Vec<VehicleState>,
+    /// Particle weights
+    pub pw: PW,
+    /// Variance of range measurement [m^2]
+    pub Q: Matrix1,
+    /// Covariance of input [velocity, yaw rate] used to spread particles
+    pub R: Matrix2,
+    /// Resampling algorithm
+    pub resampling: Resampling,
+    /// When to resample
+    pub trigger: ResampleTrigger,
+    /// Adapt the number of particles by KLD-sampling, resampling after every
+    /// update regardless of [`Self::trigger`]
+    pub kld: Option<KldSampling>,
+    /// Diagnostics of the latest update
+    pub diagnostics: Diagnostics,
+    /// Number of updates since the last resampling
+    steps: usize,
+}
+
+/// Diagnostics of a [`ParticleFilter`] update
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Diagnostics {
+    /// Effective number of particles before resampling
+    pub n_eff: f32,
+    /// Whether the particles were resampled
+    pub resampled: bool,
+    /// Number of particles after the update
+    pub num_particles: usize,
+}
+
+impl Default for ParticleFilter {
+    fn default() -> Self {
+        Self::new(NP)
+    }
+}
+
+impl ParticleFilter {
+    /// Instantiate with `np` particles at the origin
+    pub fn new(np: usize) -> Self {
+        Self {
+            x_est: VehicleState::default(),
+            p_est: Matrix3::zeros(),
+            px: vec![VehicleState::default(); np],
+            pw: uniform_weights(np),
+            Q: diag![0.2],
+            R: diag![2.0, (40_f32).to_radians()],
+            resampling: Resampling::default(),
+            trigger: ResampleTrigger::EffectiveParticles(NTh / NP as f32),
+            kld: None,
+            diagnostics: Diagnostics::default(),
+            steps: 0,
+        }
+    }
+
+    pub fn with_particles(mut self, np: usize) -> Self {
+        self.set_num_particles(np);
+        self
+    }
+
+    /// Place all particles at `x`
+    pub fn with_state(mut self, x: VehicleState) -> Self {
+        self.x_est = x;
+        self.set_num_particles(self.num_particles());
+        self
+    }
+
+    pub fn with_noise(mut self, Q: Matrix1, R: Matrix2) -> Self {
+        self.Q = Q;
+        self.R = R;
+        self
+    }
+
+    /// Resample when the effective number of particles falls below
+    /// `resample_threshold` times the particle count
+    pub fn with_resample_threshold(mut self, resample_threshold: f32) -> Self {
+        self.trigger = ResampleTrigger::EffectiveParticles(resample_threshold);
+        self
+    }
+
+    pub fn with_resampling(mut self, resampling: Resampling) -> Self {
+        self.resampling = resampling;
+        self
+    }
+
+    pub fn with_trigger(mut self, trigger: ResampleTrigger) -> Self {
+        self.trigger = trigger;
+        self
+    }
+
+    pub fn with_kld_sampling(mut self, kld: KldSampling) -> Self {
+        self.kld = Some(kld);
+        self
+    }
+
+    pub fn num_particles(&self) -> usize {
+        self.pw.len()
+    }
+
+    /// Re-initialize `np` particles with equal weights at the current estimate
+    pub fn set_num_particles(&mut self, np: usize) {
+        self.px = vec![self.x_est; np];
+        self.pw = uniform_weights(np);
+    }
+
+    /// Effective number of particles, `1 / sum(w^2)`
+    pub fn effective_particles(&self) -> f32 {
+        effective_particles(self.pw.as_slice())
+    }
+
+    /// Draw a new set of equally weighted particles with [`Self::resampling`],
+    /// or as many as needed with [`Self::kld`] if set
+    pub fn resample<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let ind = match self.kld {
+            Some(kld) => kld.indices(rng, &self.px, self.pw.as_slice()),
+            None => self.resampling.indices(rng, self.pw.as_slice()),
+        };
+        if !ind.is_empty() {
+            self.px = ind.iter().map(|&i| self.px[i]).collect();
+            self.pw = uniform_weights(ind.len());
+        }
+        self.steps = 0;
+    }
+
+    /// Propagate particles with input `u` and weight them by range
+    /// observations `z` to known landmarks.
+    ///
+    /// Returns the estimated covariance of [x, y, yaw].
+    pub fn update<R: Rng + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        z: &[Vector3],
+        u: Vector2,
+        dt: f32,
+    ) -> Matrix3 {
+        let model = LandmarkRanges { Q: self.Q };
+        self.update_with(rng, &model, z, u, dt)
+    }
+
+    /// Propagate particles with input `u` and weight them by the likelihood of
+    /// observations `z` under `model`, e.g. a lidar scan against a map.
+    ///
+    /// Returns the estimated covariance of [x, y, yaw].
+    pub fn update_with<R, M, Z>(
+        &mut self,
+        rng: &mut R,
+        model: &M,
+        z: &Z,
+        u: Vector2,
+        dt: f32,
+    ) -> Matrix3
+    where
+        R: Rng + ?Sized,
+        M: MeasurementModel<Z> + ?Sized,
+        Z: ?Sized,
+    {
+        // Weights are accumulated as logarithms, as the likelihood of many
+        // observations can underflow in single precision
+        let log_w: Vec<f32> = self
+            .px
+            .iter_mut()
+            .zip(self.pw.iter())
+            .map(|(x, w)| {
+                let ud1 = u[0] + rand_with(rng) * sqrt(self.R[(0, 0)]);
+                let ud2 = u[1] + rand_with(rng) * sqrt(self.R[(1, 1)]);
+                *x = x.predict(vector![ud1, ud2], dt);
+                w.ln() + model.log_likelihood(x, z)
+            })
+            .collect();
+
+        let max = log_w.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        self.pw = if max.is_finite() {
+            PW::from_iterator(log_w.len(), log_w.iter().map(|w| exp(w - max)))
+        } else {
+            uniform_weights(log_w.len())
+        };
+        self.pw /= self.pw.sum();
+
+        self.x_est = mean_state(self.px.iter().copied(), self.pw.as_slice());
+        self.p_est = particle_covariance(&self.x_est, self.px.iter().copied(), &self.pw);
+
+        self.steps += 1;
+        let n_eff = self.effective_particles();
+        let resampled = self.kld.is_some()
+            || self
+                .trigger
+                .is_triggered(n_eff, self.num_particles(), self.steps);
+        if resampled {
+            self.resample(rng);
+        }
+        self.diagnostics = Diagnostics {
+            n_eff,
+            resampled,
+            num_particles: self.num_particles(),
+        };
+
+        self.p_est
+    }
+}
+
+/// Thin wrapper around [`ParticleFilter::update`] with the default parameters,
+/// for particles stored as the columns of `px`
+pub fn pf_localization<R: Rng + ?Sized>(
+    rng: &mut R,
     x_est: &mut Vector4,
     px: &mut PX,
     pw: &mut PW,
@@ -94,65 +423,43 @@ pub fn pf_localization(
     u: Vector2,
     dt: f32,
 ) -> Matrix3 {
-    let Q = diag![0.2];
-    let R = diag![2.0, (40_f32).to_radians()];
-
-    for ip in 0..NP {
-        let x = px.column(ip).into_owned();
-        let mut w = pw[(0, ip)];
-
-        let ud1 = u[0] + rand() * sqrt(R[(0, 0)]);
-        let ud2 = u[1] + rand() * sqrt(R[(1, 1)]);
-        let ud = vector![ud1, ud2];
-        let x = motion_model(x, ud, dt);
-
-        for zi in z.iter() {
-            let dx = x[0] - zi.x();
-            let dy = x[1] - zi.y();
-            let pre_z = hypot(dx, dy);
-            let dz = pre_z - zi.d();
-            w = w * gauss_likelihood(dz, sqrt(Q[(0, 0)]));
-        }
-        px.set_column(ip, &x);
-        pw[ip] = w;
-    }
+    let mut pf = ParticleFilter {
+        x_est: VehicleState::from(*x_est),
+        px: px.column_iter().map(|x| x.into_owned().into()).collect(),
+        pw: std::mem::replace(pw, PW::zeros(0)),
+        ..Default::default()
+    };
+    let p_est = pf.update(rng, &z, u, dt);
+
+    *x_est = pf.x_est.into();
+    *px = PX::from_fn(pf.px.len(), |i, j| Vector4::from(pf.px[j])[i]);
+    *pw = pf.pw;
+    p_est
+}
 
-    *pw /= pw.sum();
+pub fn re_sampling(px: &mut PX, pw: &mut PW) {
+    re_sampling_with(&mut rand::thread_rng(), px, pw)
+}
 
-    // let x_est = *px * pw.transpose();
-    *x_est = *px * pw.transpose();
-    let p_est = calc_covariance(x_est, px, pw);
+/// Stratified resampling, drawing random offsets from the given `rng`
+pub fn re_sampling_with<R: Rng + ?Sized>(rng: &mut R, px: &mut PX, pw: &mut PW) {
+    resample_with(Resampling::Stratified, rng, px, pw)
+}
 
-    let N_eff = 1. / (*pw * pw.transpose())[0];
-    if N_eff < NTh {
-        re_sampling(px, pw);
-    }
-    p_est
+/// Resample particles with the given algorithm, resetting to equal weights
+pub fn resample_with<R: Rng + ?Sized>(
+    resampling: Resampling,
+    rng: &mut R,
+    px: &mut PX,
+    pw: &mut PW,
+) {
+    let ind = resampling.indices(rng, pw.as_slice());
+    *px = px.select_columns(ind.iter());
+    *pw = uniform_weights(ind.len());
 }
 
-pub fn re_sampling(px: &mut PX, pw: &mut PW) {
-    let w_cum: Vec<f32> = pw
-        .as_slice()
-        .iter()
-        .scan(0.0, |acc, x| {
-            *acc += *x;
-            Some(*acc)
-        })
-        .collect();
-
-    let base = (0..NP).map(|x| x as f32 / NP as f32);
-    let resample_id: Vec<f32> = base.map(|x| x + rand_unifrom(0., 1. / NP as f32)).collect();
-
-    let mut ind = 0;
-    let mut px_new = zeros!(4, NP);
-    for ip in 0..NP {
-        while resample_id[ip] > w_cum[ind] && ind < NP - 1 {
-            ind += 1;
-        }
-        px_new.set_column(ip, &px.column(ind));
-    }
-    *px = px_new;
-    *pw = ones!(1, NP) * (1. / NP as f32);
+fn uniform_weights(np: usize) -> PW {
+    PW::from_element(np, 1. / np as f32)
 }
 
 /// Getter methods for particle
@@ -164,19 +471,22 @@ pub trait Observation {
 
 impl Observation for Vector3 {
     fn d(&self) -> f32 {
-        *self.get(0).expect("Cannot get 1st element of Vector3")
+        self[0]
     }
     fn x(&self) -> f32 {
-        *self.get(1).expect("Cannot get 2nd element of Vector3")
+        self[1]
     }
     fn y(&self) -> f32 {
-        *self.get(2).expect("Cannot get 3rd element of Vector3")
+        self[2]
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::localization::evaluation::{MonteCarlo, Scenario};
+    use crate::pose::angle_diff;
+    use rand::{rngs::StdRng, SeedableRng};
     // #[test]
     // fn check_heapless() {
     //     let mut v: Vec<f32, 3> = Vec::new();
@@ -195,49 +505,85 @@ mod tests {
     // }
 
     #[test]
-    fn check_rand() {
-        for _ in 0..100 {
-            let r = rand::random::<f32>();
-            println!("{r}");
-        }
+    fn default_filter_beats_dead_reckoning() {
+        let mc = MonteCarlo::new(Scenario::default().with_steps(200, 0.1)).with_trials(5);
+        let report = mc.run(ParticleFilter::default);
+        assert!(
+            report.position_rmse < 0.5 * report.dr_position_rmse,
+            "{report}"
+        );
+        assert!(report.heading_rmse < 0.25, "{report}");
+        let nees = report.nees.unwrap();
+        assert!(nees.in_bounds > 0.5, "{report}");
     }
 
     #[test]
-    fn test_main() {
-        let mut time = 0_f32;
-        let rf_id = vec![
-            vector![10.0_f32, 0.0_f32],
-            vector![10.0, 10.0],
-            vector![0.0, 15.0],
-            vector![-5.0, 20.0],
-        ];
-
-        let mut x_est = zeros!(4, 1);
-        let mut x_true = zeros!(4, 1);
-
-        let mut px = zeros!(4, NP);
-        let mut pw = ones!(1, NP) * (1. / NP as f32);
-        let mut x_dr = zeros!(4, 1);
-
-        let mut h_x_est = vec![x_est];
-        let mut h_x_true = vec![x_true];
-        let mut h_x_dr = vec![x_true];
-
-        let dt = 0.1;
-
-        while 50.0 > time {
-            time += dt;
-            let u = calc_input();
+    fn configured_filter_tracks_vehicle() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut pf = ParticleFilter::default()
+            .with_particles(300)
+            .with_resample_threshold(0.3);
+        assert_eq!(pf.px.len(), 300);
+
+        let scenario = Scenario::default()
+            .with_sensor(Sensor::default().with_max_range(30.0))
+            .with_steps(200, 0.1);
+        let (x_true, _) = scenario.run(&mut rng, |rng, step| {
+            pf.update(rng, step.z, step.u, scenario.dt);
+        });
+        let err = hypot(pf.x_est.x() - x_true.x(), pf.x_est.y() - x_true.y());
+        assert!(err < 3.0, "Position error: {err}");
+    }
 
-            let (z, ud) = observation(&mut x_true, &mut x_dr, u, &rf_id, dt);
-            let _PEst = pf_localization(&mut x_est, &mut px, &mut pw, z, ud, dt);
+    #[test]
+    fn kld_sampling_shrinks_converged_filter() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let kld = KldSampling::default().with_bounds(50, 1000);
+        let mut pf = ParticleFilter::default()
+            .with_particles(1000)
+            .with_kld_sampling(kld);
+
+        let scenario = Scenario::default().with_steps(200, 0.1);
+        let (x_true, _) = scenario.run(&mut rng, |rng, step| {
+            pf.update(rng, step.z, step.u, scenario.dt);
+            assert!((50..=1000).contains(&pf.diagnostics.num_particles));
+        });
+        assert!(pf.num_particles() < 1000);
+        let err = hypot(pf.x_est.x() - x_true.x(), pf.x_est.y() - x_true.y());
+        assert!(err < 3.0, "Position error: {err}");
+    }
 
-            h_x_est.push(x_est);
-            h_x_true.push(x_true);
-            h_x_dr.push(x_dr);
+    #[test]
+    fn same_seed_gives_same_estimate() {
+        let scenario = Scenario::default()
+            .with_landmarks(vec![vector![10.0, 0.0], vector![0.0, 15.0]])
+            .with_steps(50, 0.1);
+        let run = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut pf = ParticleFilter::default();
+            scenario.run(&mut rng, |rng, step| {
+                pf.update(rng, step.z, step.u, scenario.dt);
+            });
+            pf.x_est
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
 
-            println!("{time}");
-            dbg!(&x_est);
-        }
+    #[test]
+    fn headings_are_averaged_on_the_circle() {
+        // Headings either side of PI, as if wrapped
+        let px = PX::from_fn(10, |i, j| match (i, j % 2) {
+            (2, 0) => PI - 0.05,
+            (2, _) => -PI + 0.05,
+            _ => 1.0,
+        });
+        let pw = uniform_weights(10);
+        let x_est = calc_mean(&px, &pw);
+        assert!(angle_diff(x_est.phi(), PI).abs() < 1e-4, "{}", x_est.phi());
+        assert!((x_est.x() - 1.0).abs() < 1e-5);
+
+        let cov = calc_covariance(&x_est, &px, &pw);
+        assert!((cov[(2, 2)] - 0.05_f32.powi(2) * 10. / 9.).abs() < 1e-4);
     }
 }
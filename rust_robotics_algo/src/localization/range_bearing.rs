@@ -0,0 +1,139 @@
+//! Range-bearing observations of landmarks with unknown correspondences
+
+use super::particle_filter::{motion_model, rand_with, randn_with};
+use super::*;
+use crate::pose::normalize_angle;
+use rand::{seq::SliceRandom, Rng};
+
+/// Range and bearing `[r, b]` of `landmark` as seen from state `x`
+pub fn range_bearing(x: &impl StateVector, landmark: &Vector2) -> Vector2 {
+    let dx = landmark.x - x.x();
+    let dy = landmark.y - x.y();
+    vector![hypot(dx, dy), angle_diff(dy.atan2(dx), x.phi())]
+}
+
+/// Jacobians of [`range_bearing`] with respect to the vehicle pose `[x, y, yaw]`
+/// and to the landmark position
+pub fn jacob_range_bearing(x: &impl StateVector, landmark: &Vector2) -> (Mat<2, 3>, Matrix2) {
+    let dx = landmark.x - x.x();
+    let dy = landmark.y - x.y();
+    let q = (dx * dx + dy * dy).max(f32::EPSILON);
+    let r = sqrt(q);
+
+    let H_landmark = matrix![dx / r, dy / r;
+                             -dy / q, dx / q];
+    let H_pose = matrix![-dx / r, -dy / r, 0.;
+                         dy / q, -dx / q, -1.];
+    (H_pose, H_landmark)
+}
+
+/// Position of a landmark observed at range-bearing `z` from state `x`
+pub fn landmark_position(x: &impl StateVector, z: &Vector2) -> Vector2 {
+    let angle = x.phi() + z[1];
+    vector![x.x() + z[0] * cos(angle), x.y() + z[0] * sin(angle)]
+}
+
+/// Jacobians of [`landmark_position`] with respect to the vehicle pose
+/// `[x, y, yaw]` and to the observation `z`
+pub fn jacob_landmark_position(x: &impl StateVector, z: &Vector2) -> (Mat<2, 3>, Matrix2) {
+    let angle = x.phi() + z[1];
+    let (c, s) = (cos(angle), sin(angle));
+
+    let G_pose = matrix![1., 0., -z[0] * s;
+                         0., 1.,  z[0] * c];
+    let G_z = matrix![c, -z[0] * s;
+                      s,  z[0] * c];
+    (G_pose, G_z)
+}
+
+/// Simulated range-bearing sensor, which doesn't tell which landmark was observed
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RangeBearingSensor {
+    /// Covariance of [range, bearing] measurement
+    pub Q: Matrix2,
+    /// Covariance of input [velocity, yaw rate]
+    pub R: Matrix2,
+    /// Maximum observation range [m]
+    pub max_range: f32,
+}
+
+impl Default for RangeBearingSensor {
+    fn default() -> Self {
+        Self {
+            Q: diag![0.1_f32.powi(2), (2_f32).to_radians().powi(2)],
+            R: diag![1.0, (30_f32).to_radians()],
+            max_range: 20.0,
+        }
+    }
+}
+
+impl RangeBearingSensor {
+    pub fn with_noise(mut self, Q: Matrix2, R: Matrix2) -> Self {
+        self.Q = Q;
+        self.R = R;
+        self
+    }
+
+    pub fn with_max_range(mut self, max_range: f32) -> Self {
+        self.max_range = max_range;
+        self
+    }
+
+    /// Move the true and dead-reckoning states with input `u`, and return the
+    /// range-bearing observations of landmarks within range, in random order,
+    /// along with the noisy input.
+    pub fn observe<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        x_true: &mut Vector4,
+        xd: &mut Vector4,
+        u: Vector2,
+        landmarks: &[Vector2],
+        dt: f32,
+    ) -> (Vec<Vector2>, Vector2) {
+        *x_true = motion_model(*x_true, u, dt);
+
+        let mut z: Vec<Vector2> = landmarks
+            .iter()
+            .map(|landmark| range_bearing(x_true, landmark))
+            .filter(|zi| zi[0] <= self.max_range)
+            .map(|zi| {
+                vector![
+                    zi[0] + randn_with(rng) * sqrt(self.Q[(0, 0)]),
+                    normalize_angle(zi[1] + randn_with(rng) * sqrt(self.Q[(1, 1)]))
+                ]
+            })
+            .collect();
+        z.shuffle(rng);
+
+        let ud1 = u[0] + rand_with(rng) * sqrt(self.R[(0, 0)]);
+        let ud2 = u[1] + rand_with(rng) * sqrt(self.R[(1, 1)]);
+        let ud = vector![ud1, ud2];
+
+        *xd = motion_model(*xd, ud, dt);
+
+        (z, ud)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn landmark_position_inverts_observation() {
+        let x = vector![1., -2., 2.5, 1.];
+        let landmark = vector![-5., 3.];
+        let z = range_bearing(&x, &landmark);
+        assert!((landmark_position(&x, &z) - landmark).amax() < 1e-4);
+
+        let h = 1e-3;
+        let (H_pose, _) = jacob_range_bearing(&x, &landmark);
+        for i in 0..3 {
+            let mut dx = Vector4::zeros();
+            dx[i] = h;
+            let dz = range_bearing(&(x + dx), &landmark) - range_bearing(&(x - dx), &landmark);
+            assert!((dz / (2. * h) - H_pose.column(i)).amax() < 1e-2);
+        }
+    }
+}
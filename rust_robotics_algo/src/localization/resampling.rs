@@ -0,0 +1,313 @@
+//! Resampling of weighted particles
+
+use super::association::chi2_inv;
+use crate::prelude::*;
+use rand::Rng;
+use std::collections::HashSet;
+
+/// Algorithm for drawing a new set of equally weighted particles
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Resampling {
+    /// Independent draws from the weight distribution
+    Multinomial,
+    /// One independent draw within each of `N` equal strata
+    #[default]
+    Stratified,
+    /// A single random offset shared by `N` equally spaced pointers
+    /// (a.k.a. low-variance resampling)
+    Systematic,
+    /// Deterministic copies of `floor(N * w)`, with the remainder drawn
+    /// by multinomial resampling of the residual weights
+    Residual,
+}
+
+impl Resampling {
+    pub const ALL: [Self; 4] = [
+        Self::Multinomial,
+        Self::Stratified,
+        Self::Systematic,
+        Self::Residual,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Multinomial => "Multinomial",
+            Self::Stratified => "Stratified",
+            Self::Systematic => "Systematic",
+            Self::Residual => "Residual",
+        }
+    }
+
+    /// Indices of the particles to keep, given normalised weights `w`.
+    ///
+    /// The same number of indices as weights is returned, in ascending order.
+    pub fn indices<R: Rng + ?Sized>(&self, rng: &mut R, w: &[f32]) -> Vec<usize> {
+        let np = w.len();
+        match self {
+            Self::Multinomial => {
+                let mut positions: Vec<f32> = (0..np).map(|_| rng.gen()).collect();
+                positions.sort_by(|a, b| a.total_cmp(b));
+                select(w, &positions)
+            }
+            Self::Stratified => {
+                let positions: Vec<f32> = (0..np)
+                    .map(|i| (i as f32 + rng.gen::<f32>()) / np as f32)
+                    .collect();
+                select(w, &positions)
+            }
+            Self::Systematic => {
+                let offset: f32 = rng.gen();
+                let positions: Vec<f32> =
+                    (0..np).map(|i| (i as f32 + offset) / np as f32).collect();
+                select(w, &positions)
+            }
+            Self::Residual => {
+                let copies: Vec<usize> = w.iter().map(|w| (w * np as f32) as usize).collect();
+                let mut ind: Vec<usize> = copies
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(i, &n)| std::iter::repeat_n(i, n))
+                    .take(np)
+                    .collect();
+
+                let n_rest = np - ind.len();
+                if n_rest > 0 {
+                    let residual: Vec<f32> = w
+                        .iter()
+                        .zip(copies.iter())
+                        .map(|(w, &n)| w * np as f32 - n as f32)
+                        .collect();
+                    let sum: f32 = residual.iter().sum();
+                    let residual: Vec<f32> = residual.iter().map(|r| r / sum).collect();
+
+                    let mut positions: Vec<f32> = (0..n_rest).map(|_| rng.gen()).collect();
+                    positions.sort_by(|a, b| a.total_cmp(b));
+                    ind.extend(select(&residual, &positions));
+                    ind.sort_unstable();
+                }
+                ind
+            }
+        }
+    }
+}
+
+/// Policy for when to resample
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ResampleTrigger {
+    /// Resample after every update
+    Always,
+    /// Resample when the effective number of particles falls below the given
+    /// fraction of the particle count
+    EffectiveParticles(f32),
+    /// Resample once every given number of updates
+    Period(usize),
+}
+
+impl Default for ResampleTrigger {
+    fn default() -> Self {
+        Self::EffectiveParticles(0.5)
+    }
+}
+
+impl ResampleTrigger {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Always => "Always",
+            Self::EffectiveParticles(_) => "Effective Particles",
+            Self::Period(_) => "Fixed Period",
+        }
+    }
+
+    /// Whether to resample `np` particles with `n_eff` effective particles,
+    /// `steps` updates after the last resampling
+    pub fn is_triggered(&self, n_eff: f32, np: usize, steps: usize) -> bool {
+        match *self {
+            Self::Always => true,
+            Self::EffectiveParticles(ratio) => n_eff < ratio * np as f32,
+            Self::Period(period) => steps >= period.max(1),
+        }
+    }
+}
+
+/// KLD-sampling (Fox, 2003), which draws particles until their number bounds
+/// the Kullback-Leibler divergence between the sampled and true posterior,
+/// given the number of histogram bins the drawn particles occupy.
+///
+/// Few particles are drawn when they are concentrated, and many when they
+/// are spread out.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct KldSampling {
+    /// Bound on the Kullback-Leibler divergence
+    pub epsilon: f32,
+    /// Probability that the divergence stays within `epsilon`, i.e. `1 - delta`
+    pub confidence: f32,
+    /// Size of the histogram bins over [x, y, yaw]
+    pub bin_size: Vector3,
+    pub min_particles: usize,
+    pub max_particles: usize,
+}
+
+impl Default for KldSampling {
+    fn default() -> Self {
+        Self {
+            epsilon: 0.05,
+            confidence: 0.99,
+            bin_size: vector![0.5, 0.5, (10_f32).to_radians()],
+            min_particles: 50,
+            max_particles: 2000,
+        }
+    }
+}
+
+impl KldSampling {
+    pub fn with_bounds(mut self, min_particles: usize, max_particles: usize) -> Self {
+        self.min_particles = min_particles;
+        self.max_particles = max_particles.max(min_particles);
+        self
+    }
+
+    pub fn with_error(mut self, epsilon: f32, confidence: f32) -> Self {
+        self.epsilon = epsilon;
+        self.confidence = confidence;
+        self
+    }
+
+    pub fn with_bin_size(mut self, bin_size: Vector3) -> Self {
+        self.bin_size = bin_size;
+        self
+    }
+
+    /// Number of particles needed when they occupy `k` bins, within the bounds
+    pub fn required_particles(&self, k: usize) -> usize {
+        let n = if k > 1 {
+            (chi2_inv(self.confidence, k - 1) / (2. * self.epsilon)).ceil() as usize
+        } else {
+            0
+        };
+        n.clamp(self.min_particles, self.max_particles)
+    }
+
+    /// Indices of particles `px` drawn independently by their normalised
+    /// weights `w`, as many as [`required_particles`](Self::required_particles)
+    /// for the bins occupied so far.
+    ///
+    /// The indices are returned in ascending order.
+    pub fn indices<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        px: &[VehicleState],
+        w: &[f32],
+    ) -> Vec<usize> {
+        let w_cum: Vec<f32> = w
+            .iter()
+            .scan(0.0, |acc, x| {
+                *acc += *x;
+                Some(*acc)
+            })
+            .collect();
+        let total = w_cum.last().copied().unwrap_or(0.);
+        if total <= 0. {
+            return Vec::new();
+        }
+
+        let mut bins = HashSet::new();
+        let mut required = self.required_particles(0);
+        let mut ind = Vec::with_capacity(required);
+        while ind.len() < required {
+            let position = rng.gen::<f32>() * total;
+            let i = w_cum.partition_point(|&c| c <= position).min(w.len() - 1);
+            ind.push(i);
+
+            let x = &px[i];
+            let bin = (
+                (x.x / self.bin_size[0]).floor() as i64,
+                (x.y / self.bin_size[1]).floor() as i64,
+                (x.phi.rem_euclid(TAU) / self.bin_size[2]).floor() as i64,
+            );
+            if bins.insert(bin) {
+                required = self.required_particles(bins.len());
+            }
+        }
+        ind.sort_unstable();
+        ind
+    }
+}
+
+/// Effective number of particles, `1 / sum(w^2)`, of normalised weights
+pub fn effective_particles(w: &[f32]) -> f32 {
+    1. / w.iter().map(|w| w * w).sum::<f32>()
+}
+
+/// Select the particle at each of the ascending `positions` in [0, 1) on
+/// the cumulative distribution of weights `w`
+fn select(w: &[f32], positions: &[f32]) -> Vec<usize> {
+    let w_cum: Vec<f32> = w
+        .iter()
+        .scan(0.0, |acc, x| {
+            *acc += *x;
+            Some(*acc)
+        })
+        .collect();
+
+    let mut ind = 0;
+    positions
+        .iter()
+        .map(|&position| {
+            while position >= w_cum[ind] && ind < w.len() - 1 {
+                ind += 1;
+            }
+            ind
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn strategies_follow_weights() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let w = [0.1, 0.6, 0.0, 0.3];
+        for strategy in Resampling::ALL {
+            let mut counts = [0; 4];
+            for _ in 0..100 {
+                let ind = strategy.indices(&mut rng, &w);
+                assert_eq!(ind.len(), w.len());
+                ind.iter().for_each(|&i| counts[i] += 1);
+            }
+            assert_eq!(counts[2], 0, "{} drew a zero weight", strategy.name());
+            for (i, &count) in counts.iter().enumerate() {
+                let expected = w[i] * 400.;
+                assert!(
+                    (count as f32 - expected).abs() < 0.2 * 400.,
+                    "{}: {counts:?}",
+                    strategy.name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn kld_sampling_adapts_to_spread() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let kld = KldSampling::default().with_bounds(20, 1000);
+        let np = 1000;
+        let w = vec![1. / np as f32; np];
+
+        let concentrated: Vec<VehicleState> = (0..np)
+            .map(|i| VehicleState::new(0.01 * (i % 3) as f32, 0.01 * (i % 3) as f32, 0., 0.))
+            .collect();
+        let ind = kld.indices(&mut rng, &concentrated, &w);
+        assert_eq!(ind.len(), 20);
+        assert!(ind.windows(2).all(|i| i[0] <= i[1]));
+
+        let spread: Vec<VehicleState> = (0..np)
+            .map(|i| VehicleState::new((i % 50) as f32, (i % 50) as f32, 0., 0.))
+            .collect();
+        let n = kld.indices(&mut rng, &spread, &w).len();
+        assert!(n > 200 && n <= 1000, "{n}");
+        assert_eq!(kld.required_particles(10_000), 1000);
+    }
+}
@@ -0,0 +1,217 @@
+use super::ekf::jacob_g;
+use super::evaluation::Nis;
+use super::particle_filter::Observation;
+use super::*;
+
+/// Number of states
+const NX: usize = 4;
+/// Number of sigma points
+const NS: usize = 2 * NX + 1;
+
+/// Sigma points of the state, one per column
+pub type SigmaPoints = Mat<NX, NS>;
+
+/// Unscented Kalman filter localization with range observations to known landmarks
+///
+/// Sigma points are spread by `alpha`, with `beta` encoding prior knowledge of
+/// the distribution (2 is optimal for Gaussians) and `kappa` as a secondary
+/// scaling parameter.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct UKF {
+    /// Estimated state
+    pub x_est: VehicleState,
+    /// Estimated covariance
+    pub P: Matrix4,
+    /// Variance of range measurement [m^2]
+    pub Q: Matrix1,
+    /// Covariance of input [velocity, yaw rate]
+    pub R: Matrix2,
+    /// NIS of the latest correction
+    pub nis: Nis,
+    /// Spread of the sigma points around the mean
+    pub alpha: f32,
+    /// Prior knowledge of the distribution
+    pub beta: f32,
+    /// Secondary scaling parameter
+    pub kappa: f32,
+}
+
+impl Default for UKF {
+    fn default() -> Self {
+        Self {
+            x_est: VehicleState::default(),
+            P: Matrix4::identity() * 1e-3,
+            Q: diag![0.2],
+            R: diag![1.0, (30_f32).to_radians()],
+            nis: Nis::default(),
+            // Small `alpha` gives large, opposite-signed weights, which are
+            // numerically fragile in single precision
+            alpha: 1.0,
+            beta: 2.0,
+            kappa: 0.0,
+        }
+    }
+}
+
+impl UKF {
+    pub fn with_state(mut self, x: VehicleState, P: Matrix4) -> Self {
+        self.x_est = x;
+        self.P = P;
+        self
+    }
+
+    pub fn with_noise(mut self, Q: Matrix1, R: Matrix2) -> Self {
+        self.Q = Q;
+        self.R = R;
+        self
+    }
+
+    pub fn with_parameters(mut self, alpha: f32, beta: f32, kappa: f32) -> Self {
+        self.alpha = alpha;
+        self.beta = beta;
+        self.kappa = kappa;
+        self
+    }
+
+    /// Estimated covariance of [x, y, yaw]
+    pub fn p_est(&self) -> Matrix3 {
+        self.P.fixed_slice::<3, 3>(0, 0).into_owned()
+    }
+
+    fn lambda(&self) -> f32 {
+        self.alpha.powi(2) * (NX as f32 + self.kappa) - NX as f32
+    }
+
+    /// Weights of the sigma points for the mean and the covariance
+    pub fn weights(&self) -> (Vector<NS>, Vector<NS>) {
+        let lambda = self.lambda();
+        let w = 1. / (2. * (NX as f32 + lambda));
+        let mut wm = Vector::<NS>::from_element(w);
+        let mut wc = wm;
+        wm[0] = lambda / (NX as f32 + lambda);
+        wc[0] = wm[0] + (1. - self.alpha.powi(2) + self.beta);
+        (wm, wc)
+    }
+
+    /// Sigma points of the current estimate
+    pub fn sigma_points(&self) -> SigmaPoints {
+        let gamma = sqrt(NX as f32 + self.lambda());
+        let L = sqrt_psd(&self.P) * gamma;
+
+        let x = Vector4::from(self.x_est);
+        let mut sigma = SigmaPoints::from_fn(|i, _| x[i]);
+        for i in 0..NX {
+            let mut plus = sigma.column_mut(1 + i);
+            plus += L.column(i);
+            let mut minus = sigma.column_mut(1 + NX + i);
+            minus -= L.column(i);
+        }
+        sigma
+    }
+
+    /// Propagate sigma points through [`VehicleState::predict`] with input `u`
+    pub fn predict(&mut self, u: Vector2, dt: f32) {
+        let (wm, wc) = self.weights();
+        let sigma: Vec<VehicleState> = self
+            .sigma_points()
+            .column_iter()
+            .map(|col| VehicleState::from(col.into_owned()).predict(u, dt))
+            .collect();
+
+        self.x_est = mean_state(sigma.iter().copied(), wm.as_slice());
+        let G = jacob_g(&self.x_est, dt);
+        self.P = G * self.R * G.transpose();
+        for (i, x) in sigma.iter().enumerate() {
+            let dx = state_residual(x, &self.x_est);
+            self.P += wc[i] * dx * dx.transpose();
+        }
+    }
+
+    /// Correct the estimate with range observations `[d, x, y]`, one at a time
+    pub fn correct(&mut self, z: &[Vector3]) {
+        self.nis = Nis::default();
+        for zi in z.iter() {
+            let (wm, wc) = self.weights();
+            let sigma = self.sigma_points();
+            let z_sigma =
+                Vector::<NS>::from_fn(|i, _| hypot(sigma[(0, i)] - zi.x(), sigma[(1, i)] - zi.y()));
+            let z_pred = wm.dot(&z_sigma);
+
+            let mut S = self.Q[0];
+            let mut Pxz = Vector4::zeros();
+            for (i, col) in sigma.column_iter().enumerate() {
+                let dz = z_sigma[i] - z_pred;
+                S += wc[i] * dz * dz;
+                Pxz += wc[i] * state_residual(&col.into_owned(), &self.x_est) * dz;
+            }
+
+            let K = Pxz / S;
+            self.nis.add(&vector![zi.d() - z_pred], &diag![1. / S]);
+            self.x_est += K * (zi.d() - z_pred);
+            self.P -= K * S * K.transpose();
+        }
+    }
+
+    /// Predict with input `u` and correct with observations `z`.
+    ///
+    /// Returns the estimated covariance of [x, y, yaw].
+    pub fn update(&mut self, z: &[Vector3], u: Vector2, dt: f32) -> Matrix3 {
+        self.predict(u, dt);
+        self.correct(z);
+        self.p_est()
+    }
+}
+
+/// Square root `L` of a positive semi-definite matrix, such that `L * L^T = P`
+///
+/// Falls back to the eigendecomposition when rounding errors make `P`
+/// indefinite for the Cholesky decomposition.
+fn sqrt_psd(P: &Matrix4) -> Matrix4 {
+    let P = (P + P.transpose()) * 0.5;
+    match P.cholesky() {
+        Some(chol) => chol.l(),
+        None => {
+            let eig = P.symmetric_eigen();
+            eig.eigenvectors * Matrix4::from_diagonal(&eig.eigenvalues.map(|e| sqrt(e.max(0.))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localization::evaluation::Scenario;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn sigma_points_recover_mean_and_covariance() {
+        let ukf = UKF::default().with_parameters(0.5, 2.0, 1.0).with_state(
+            VehicleState::new(1., 2., 0.3, 0.5),
+            diag![0.4, 0.2, 0.1, 0.3],
+        );
+        let (wm, wc) = ukf.weights();
+        let sigma = ukf.sigma_points();
+
+        let mean = sigma * wm;
+        let mut cov = Matrix4::zeros();
+        for (i, col) in sigma.column_iter().enumerate() {
+            let dx = col - mean;
+            cov += wc[i] * dx * dx.transpose();
+        }
+
+        assert!((mean - Vector4::from(ukf.x_est)).amax() < 1e-5);
+        assert!((cov - ukf.P).amax() < 1e-5);
+    }
+
+    #[test]
+    fn ukf_tracks_vehicle() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut ukf = UKF::default();
+        let scenario = Scenario::default();
+        let (x_true, _) = scenario.run(&mut rng, |_, step| {
+            ukf.update(step.z, step.u, scenario.dt);
+        });
+        let err = hypot(ukf.x_est.x() - x_true.x(), ukf.x_est.y() - x_true.y());
+        assert!(err < 1.0, "Position error: {err}");
+    }
+}
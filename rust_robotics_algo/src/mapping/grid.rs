@@ -0,0 +1,260 @@
+use super::*;
+use nalgebra::{DMatrix, Scalar};
+
+/// Regular grid of square cells, with rows along x and columns along y
+#[derive(Debug, PartialEq, Clone)]
+pub struct GridMap<T: Scalar> {
+    /// Value of each cell
+    pub cells: DMatrix<T>,
+    /// Lower left corner of the grid [m]
+    pub min: Vector2,
+    /// Side length of a cell [m]
+    pub resolution: f32,
+}
+
+impl<T: Scalar> GridMap<T> {
+    /// Cells of side `resolution` covering `min` to `max`, all set to `value`
+    pub fn new(min: Vector2, max: Vector2, resolution: f32, value: T) -> Self {
+        let size = (max - min) / resolution;
+        let nx = (size.x.ceil() as usize).max(1);
+        let ny = (size.y.ceil() as usize).max(1);
+        Self {
+            cells: DMatrix::from_element(nx, ny, value),
+            min,
+            resolution,
+        }
+    }
+
+    /// Number of cells along x and y
+    pub fn shape(&self) -> (usize, usize) {
+        self.cells.shape()
+    }
+
+    /// Upper right corner of the grid [m]
+    pub fn max(&self) -> Vector2 {
+        let (nx, ny) = self.shape();
+        self.min + vector![nx as f32, ny as f32] * self.resolution
+    }
+
+    /// Position of the center of cell (`i`, `j`)
+    pub fn cell_center(&self, i: usize, j: usize) -> Vector2 {
+        self.min + vector![i as f32 + 0.5, j as f32 + 0.5] * self.resolution
+    }
+
+    /// Cell containing `p`, if within the grid
+    pub fn cell_index(&self, p: &Vector2) -> Option<(usize, usize)> {
+        let (i, j) = self.cell_of(p);
+        self.contains(i, j).then_some((i as usize, j as usize))
+    }
+
+    /// Value of the cell containing `p`, if within the grid
+    pub fn get(&self, p: &Vector2) -> Option<&T> {
+        self.cell_index(p).map(|index| &self.cells[index])
+    }
+
+    /// Cells within the grid crossed by the segment from `from` to `to`, in
+    /// order, each with the distance from `from` at which it is entered
+    pub fn traverse(&self, from: &Vector2, to: &Vector2) -> Vec<((usize, usize), f32)> {
+        let p = (from - self.min) / self.resolution;
+        let q = (to - self.min) / self.resolution;
+        let length = (to - from).norm();
+        let (mut i, mut j) = self.cell_of(from);
+        let (end_i, end_j) = self.cell_of(to);
+
+        // Parameter along the segment, from 0 to 1, of the next cell boundary
+        // along each axis, and its increment from one boundary to the next
+        let axis = |p: f32, q: f32, cell: isize| {
+            let d = q - p;
+            if d > 0.0 {
+                (1, ((cell + 1) as f32 - p) / d, 1.0 / d)
+            } else if d < 0.0 {
+                (-1, (p - cell as f32) / -d, -1.0 / d)
+            } else {
+                (0, f32::INFINITY, f32::INFINITY)
+            }
+        };
+        let (step_i, mut next_i, delta_i) = axis(p.x, q.x, i);
+        let (step_j, mut next_j, delta_j) = axis(p.y, q.y, j);
+
+        let n = (end_i - i).abs() + (end_j - j).abs() + 1;
+        let mut t = 0.0;
+        let mut cells = Vec::with_capacity(n as usize);
+        for _ in 0..n {
+            if self.contains(i, j) {
+                cells.push(((i as usize, j as usize), t * length));
+            }
+            if next_i < next_j {
+                t = next_i;
+                next_i += delta_i;
+                i += step_i;
+            } else {
+                t = next_j;
+                next_j += delta_j;
+                j += step_j;
+            }
+        }
+        cells
+    }
+
+    fn cell_of(&self, p: &Vector2) -> (isize, isize) {
+        let c = (p - self.min) / self.resolution;
+        (c.x.floor() as isize, c.y.floor() as isize)
+    }
+
+    fn contains(&self, i: isize, j: isize) -> bool {
+        let (nx, ny) = self.shape();
+        (0..nx as isize).contains(&i) && (0..ny as isize).contains(&j)
+    }
+}
+
+impl GridMap<bool> {
+    /// Mark the cells crossed by `segment` as occupied
+    pub fn fill_segment(&mut self, segment: &Segment) {
+        for (index, _) in self.traverse(&segment.a, &segment.b) {
+            self.cells[index] = true;
+        }
+    }
+
+    /// Distance [m] from the center of each cell to the center of the nearest
+    /// occupied cell, or infinity if there is none
+    ///
+    /// Exact Euclidean distance transform by Felzenszwalb and Huttenlocher,
+    /// with one pass along x and one along y.
+    pub fn distance_transform(&self) -> GridMap<f32> {
+        let (nx, ny) = self.shape();
+        let mut d2 = self.cells.map(|occupied| if occupied { 0.0 } else { FAR });
+        for j in 0..ny {
+            let column: Vec<f32> = d2.column(j).iter().copied().collect();
+            for (i, d) in distance_1d(&column).into_iter().enumerate() {
+                d2[(i, j)] = d;
+            }
+        }
+        for i in 0..nx {
+            let row: Vec<f32> = d2.row(i).iter().copied().collect();
+            for (j, d) in distance_1d(&row).into_iter().enumerate() {
+                d2[(i, j)] = d;
+            }
+        }
+        GridMap {
+            cells: d2.map(|d| {
+                if d < FAR / 2.0 {
+                    sqrt(d) * self.resolution
+                } else {
+                    f32::INFINITY
+                }
+            }),
+            min: self.min,
+            resolution: self.resolution,
+        }
+    }
+}
+
+/// Squared distance [cells^2] standing in for infinity in [`distance_1d`]
+const FAR: f32 = 1e10;
+
+/// Squared distance transform of the sampled function `f`, i.e. the lower
+/// envelope of the parabolas `(q - p)^2 + f[p]`
+fn distance_1d(f: &[f32]) -> Vec<f32> {
+    let n = f.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    // Vertices of the parabolas in the envelope, and the boundaries between them
+    let mut v = vec![0; n];
+    let mut z = vec![0.0; n + 1];
+    let (mut k, inf) = (0, f32::INFINITY);
+    z[0] = -inf;
+    z[1] = inf;
+    let intersect = |q: usize, p: usize| {
+        let (qf, pf) = (q as f32, p as f32);
+        ((f[q] + qf * qf) - (f[p] + pf * pf)) / (2.0 * qf - 2.0 * pf)
+    };
+    for q in 1..n {
+        let mut s = intersect(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersect(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = inf;
+    }
+
+    k = 0;
+    (0..n)
+        .map(|q| {
+            while z[k + 1] < q as f32 {
+                k += 1;
+            }
+            (q as f32 - v[k] as f32).powi(2) + f[v[k]]
+        })
+        .collect()
+}
+
+impl RayCast for GridMap<bool> {
+    fn cast(&self, origin: &Vector2, angle: f32, max_range: f32) -> Option<f32> {
+        let to = origin + vector![cos(angle), sin(angle)] * max_range;
+        self.traverse(origin, &to)
+            .into_iter()
+            .find(|(index, _)| self.cells[*index])
+            .map(|(_, range)| range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traverse_and_cast_through_cells() {
+        let mut grid = GridMap::new(vector![0., 0.], vector![10., 5.], 1.0, false);
+        assert_eq!(grid.shape(), (10, 5));
+        assert_eq!(grid.cell_index(&vector![3.5, 4.2]), Some((3, 4)));
+        assert_eq!(grid.cell_index(&vector![-0.1, 1.0]), None);
+
+        let cells = grid.traverse(&vector![0.5, 0.5], &vector![3.5, 2.5]);
+        let indices: Vec<_> = cells.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices.first(), Some(&(0, 0)));
+        assert_eq!(indices.last(), Some(&(3, 2)));
+        assert_eq!(indices.len(), 6);
+        assert!(cells.windows(2).all(|w| w[0].1 <= w[1].1));
+
+        grid.fill_segment(&Segment::new(vector![6.5, 0.0], vector![6.5, 5.0]));
+        let range = grid.cast(&vector![0.5, 2.5], 0.0, 10.0).unwrap();
+        assert!((range - 5.5).abs() < 1e-5);
+        assert!(grid.cast(&vector![0.5, 2.5], PI, 10.0).is_none());
+    }
+
+    #[test]
+    fn distance_transform_matches_brute_force() {
+        let mut grid = GridMap::new(vector![-2., -1.], vector![4., 3.], 0.5, false);
+        grid.fill_segment(&Segment::new(vector![-1.0, 2.2], vector![3.0, 0.1]));
+        grid.cells[(1, 1)] = true;
+        let distance = grid.distance_transform();
+
+        let (nx, ny) = grid.shape();
+        let occupied: Vec<_> = (0..nx)
+            .flat_map(|i| (0..ny).map(move |j| (i, j)))
+            .filter(|index| grid.cells[*index])
+            .map(|(i, j)| grid.cell_center(i, j))
+            .collect();
+        for i in 0..nx {
+            for j in 0..ny {
+                let c = grid.cell_center(i, j);
+                let nearest = occupied
+                    .iter()
+                    .map(|p| (p - c).norm())
+                    .fold(f32::INFINITY, f32::min);
+                assert!((distance.cells[(i, j)] - nearest).abs() < 1e-4);
+            }
+        }
+
+        let empty = GridMap::new(vector![0., 0.], vector![1., 1.], 0.5, false);
+        assert!(empty
+            .distance_transform()
+            .cells
+            .iter()
+            .all(|d| d.is_infinite()));
+    }
+}
@@ -0,0 +1,35 @@
+//! Maps of the environment
+//!
+//! - [`world`]: geometric world of line segments and polygons
+//! - [`grid`]: regular grid of cells over the plane
+//! - [`occupancy_grid`]: probabilistic map built from lidar scans
+//! - [`scan_matching`]: registration of lidar scans onto each other
+//!
+//! Both can be ray cast through [`RayCast`], e.g. by a simulated
+//! [`Lidar`](crate::sensor::lidar::Lidar).
+
+use crate::prelude::*;
+
+pub mod grid;
+pub mod occupancy_grid;
+pub mod scan_matching;
+pub mod world;
+
+pub use grid::GridMap;
+pub use occupancy_grid::OccupancyGrid;
+pub use world::{Polygon, Segment, World};
+
+/// Map which rays can be cast against
+pub trait RayCast {
+    /// Distance from `origin` along the ray at `angle` [rad] to the first hit,
+    /// if any within `max_range`
+    fn cast(&self, origin: &Vector2, angle: f32, max_range: f32) -> Option<f32>;
+}
+
+impl<T: RayCast> RayCast for [T] {
+    fn cast(&self, origin: &Vector2, angle: f32, max_range: f32) -> Option<f32> {
+        self.iter()
+            .filter_map(|item| item.cast(origin, angle, max_range))
+            .min_by(|a, b| a.total_cmp(b))
+    }
+}
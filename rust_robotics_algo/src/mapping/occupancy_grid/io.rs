@@ -0,0 +1,278 @@
+//! Reading and writing occupancy grids in the ROS `map_server` format
+//!
+//! The map is a binary PGM image, whose top row is the largest y, described by
+//! a YAML file:
+//!
+//! ```text
+//! image: map.pgm
+//! mode: scale
+//! resolution: 0.25
+//! origin: [-16.0, -6.0, 0.0]
+//! negate: 0
+//! occupied_thresh: 0.65
+//! free_thresh: 0.196
+//! ```
+//!
+//! Darker pixels are more likely occupied, unless `negate` is set.
+
+use super::*;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// How pixels are turned into occupancy
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Mode {
+    /// Cells are either occupied, free or unknown, by the thresholds
+    Trinary,
+    /// Cells keep the probability of their pixel
+    Scale,
+}
+
+/// Contents of the YAML file
+#[derive(Debug, PartialEq, Clone)]
+struct Metadata {
+    image: String,
+    mode: Mode,
+    resolution: f32,
+    origin: Vector2,
+    negate: bool,
+    occupied_thresh: f32,
+    free_thresh: f32,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Metadata {
+    fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut image = None;
+        let mut resolution = None;
+        let mut origin = None;
+        let mut metadata = Self {
+            image: String::new(),
+            mode: Mode::Trinary,
+            resolution: 0.0,
+            origin: Vector2::zeros(),
+            negate: false,
+            occupied_thresh: 0.65,
+            free_thresh: 0.196,
+        };
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.split('#').next().unwrap_or_default();
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+            let number = |value: &str| {
+                value
+                    .parse::<f32>()
+                    .map_err(|_| invalid(format!("invalid number for {key}: {value}")))
+            };
+            match key {
+                "image" => image = Some(value.trim_matches(|c| c == '"' || c == '\'').to_string()),
+                "resolution" => resolution = Some(number(value)?),
+                "origin" => {
+                    let values: Vec<f32> = value
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .split(',')
+                        .map(|v| number(v.trim()))
+                        .collect::<Result<_, _>>()?;
+                    match values[..] {
+                        [x, y, 0.0] => origin = Some(vector![x, y]),
+                        [_, _, _] => return Err(invalid("rotated maps are not supported".into())),
+                        _ => return Err(invalid(format!("expected [x, y, yaw]: {value}"))),
+                    }
+                }
+                "negate" => metadata.negate = number(value)? != 0.0,
+                "occupied_thresh" => metadata.occupied_thresh = number(value)?,
+                "free_thresh" => metadata.free_thresh = number(value)?,
+                "mode" => {
+                    metadata.mode = match value {
+                        "trinary" => Mode::Trinary,
+                        "scale" => Mode::Scale,
+                        _ => return Err(invalid(format!("unsupported mode: {value}"))),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let missing = |key: &str| invalid(format!("missing {key}"));
+        metadata.image = image.ok_or_else(|| missing("image"))?;
+        metadata.resolution = resolution.ok_or_else(|| missing("resolution"))?;
+        metadata.origin = origin.ok_or_else(|| missing("origin"))?;
+        Ok(metadata)
+    }
+}
+
+/// Width, height and pixels, row by row from the top, of a binary PGM image,
+/// with the pixels scaled to [0, 1]
+fn read_pgm<R: Read>(mut reader: R) -> io::Result<(usize, usize, Vec<f32>)> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    // Header of whitespace separated tokens, possibly with comments
+    let mut header = Vec::new();
+    let mut pos = 0;
+    while header.len() < 4 {
+        match bytes.get(pos) {
+            Some(b'#') => {
+                while bytes.get(pos).is_some_and(|b| *b != b'\n') {
+                    pos += 1;
+                }
+            }
+            Some(b) if b.is_ascii_whitespace() => pos += 1,
+            Some(_) => {
+                let start = pos;
+                while bytes.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                    pos += 1;
+                }
+                header.push(String::from_utf8_lossy(&bytes[start..pos]).to_string());
+            }
+            None => return Err(invalid("truncated PGM header".into())),
+        }
+    }
+    if header[0] != "P5" {
+        return Err(invalid(format!(
+            "expected a binary PGM image, got {}",
+            header[0]
+        )));
+    }
+    let size: Vec<usize> = header[1..]
+        .iter()
+        .map(|token| token.parse::<usize>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid("invalid PGM header".into()))?;
+    let (width, height, max) = (size[0], size[1], size[2]);
+    if max == 0 || max > 255 {
+        return Err(invalid(format!("unsupported PGM maximum value: {max}")));
+    }
+
+    // A single whitespace separates the header from the pixels
+    let data = bytes.get(pos + 1..).unwrap_or_default();
+    if data.len() < width * height {
+        return Err(invalid("truncated PGM pixels".into()));
+    }
+    let pixels = data[..width * height]
+        .iter()
+        .map(|v| *v as f32 / max as f32)
+        .collect();
+    Ok((width, height, pixels))
+}
+
+impl OccupancyGrid {
+    /// Read a grid from its YAML description and PGM image
+    pub fn read<Y: BufRead, P: Read>(yaml: Y, pgm: P) -> io::Result<Self> {
+        Self::read_image(&Metadata::read(yaml)?, pgm)
+    }
+
+    /// Read a grid from its PGM image, described by the parsed `metadata`
+    fn read_image<P: Read>(metadata: &Metadata, pgm: P) -> io::Result<Self> {
+        let (width, height, pixels) = read_pgm(pgm)?;
+
+        let max = metadata.origin + vector![width as f32, height as f32] * metadata.resolution;
+        let mut grid = Self::new(metadata.origin, max, metadata.resolution)
+            .with_thresholds(metadata.occupied_thresh, metadata.free_thresh);
+        let [l_min, l_max] = grid.l_bounds;
+        grid.log_odds.cells = DMatrix::from_fn(width, height, |i, j| {
+            let value = pixels[(height - 1 - j) * width + i];
+            let p = if metadata.negate { value } else { 1.0 - value };
+            match metadata.mode {
+                Mode::Scale => log_odds(p).clamp(l_min, l_max),
+                Mode::Trinary if p > metadata.occupied_thresh => l_max,
+                Mode::Trinary if p < metadata.free_thresh => l_min,
+                Mode::Trinary => 0.0,
+            }
+        });
+        Ok(grid)
+    }
+
+    /// Write the PGM image, with the probability of each cell as its darkness
+    pub fn write_pgm<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let (nx, ny) = self.log_odds.shape();
+        write!(writer, "P5\n{nx} {ny}\n255\n")?;
+        let pixels: Vec<u8> = (0..ny)
+            .rev()
+            .flat_map(|j| (0..nx).map(move |i| (i, j)))
+            .map(|index| {
+                let p = probability(self.log_odds.cells[index]);
+                ((1.0 - p) * 255.0).round() as u8
+            })
+            .collect();
+        writer.write_all(&pixels)
+    }
+
+    /// Write the YAML description, referring to the PGM image at `image`
+    pub fn write_yaml<W: Write>(&self, mut writer: W, image: &str) -> io::Result<()> {
+        let min = self.log_odds.min;
+        writeln!(writer, "image: {image}")?;
+        writeln!(writer, "mode: scale")?;
+        writeln!(writer, "resolution: {}", self.resolution())?;
+        writeln!(writer, "origin: [{}, {}, 0.0]", min.x, min.y)?;
+        writeln!(writer, "negate: 0")?;
+        writeln!(writer, "occupied_thresh: {}", self.occupied_threshold)?;
+        writeln!(writer, "free_thresh: {}", self.free_threshold)
+    }
+
+    /// Load a grid from a YAML file, along with the image it refers to, relative
+    /// to the YAML file
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let metadata = Metadata::read(BufReader::new(File::open(path)?))?;
+        let image = path.with_file_name(&metadata.image);
+        Self::read_image(&metadata, BufReader::new(File::open(image)?))
+    }
+
+    /// Save the grid as a YAML file at `path`, with the image next to it under
+    /// the same name with the `.pgm` extension
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let image = path.with_extension("pgm");
+        let name = image
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| invalid(format!("invalid path: {}", path.display())))?;
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_yaml(&mut writer, name)?;
+        writer.flush()?;
+        let mut writer = BufWriter::new(File::create(&image)?);
+        self.write_pgm(&mut writer)?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_map_server_format() {
+        let mut grid = OccupancyGrid::new(vector![-1.0, 2.0], vector![2.0, 4.0], 0.5);
+        grid.log_odds.cells[(0, 0)] = 5.0;
+        grid.log_odds.cells[(5, 3)] = -5.0;
+        grid.log_odds.cells[(2, 1)] = 0.3;
+
+        let (mut yaml, mut pgm) = (Vec::new(), Vec::new());
+        grid.write_yaml(&mut yaml, "map.pgm").unwrap();
+        grid.write_pgm(&mut pgm).unwrap();
+        let read = OccupancyGrid::read(yaml.as_slice(), pgm.as_slice()).unwrap();
+        assert_eq!(read.log_odds.shape(), (6, 4));
+        assert_eq!(read.log_odds.min, grid.log_odds.min);
+        assert!((read.probabilities() - grid.probabilities()).amax() < 1.0 / 255.0);
+
+        // Pixels between the thresholds are unknown in the default mode
+        let yaml = "image: map.pgm\nresolution: 0.5\norigin: [-1.0, 2.0, 0.0]\n";
+        let read = OccupancyGrid::read(yaml.as_bytes(), pgm.as_slice()).unwrap();
+        assert!(read.is_occupied(0, 0) && read.is_free(5, 3));
+        assert_eq!(read.log_odds.cells[(2, 1)], 0.0);
+
+        let rotated = "image: map.pgm\nresolution: 0.5\norigin: [0.0, 0.0, 1.0]\n";
+        assert!(OccupancyGrid::read(rotated.as_bytes(), pgm.as_slice()).is_err());
+    }
+}
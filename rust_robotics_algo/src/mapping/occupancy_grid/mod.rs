@@ -0,0 +1,190 @@
+//! Occupancy grid mapping with a log-odds inverse sensor model
+//!
+//! Each cell holds the log-odds `ln(p / (1 - p))` of being occupied, so that
+//! integrating a scan at a known pose only adds to the cells its beams cross.
+//! Grids can be saved to and loaded from the ROS `map_server` format, see
+//! [`OccupancyGrid::save`] and [`OccupancyGrid::load`].
+
+use super::*;
+use crate::sensor::lidar::Scan;
+use nalgebra::DMatrix;
+
+mod io;
+
+/// Lower left corner of the default map [m]
+pub const MAP_MIN: [f32; 2] = [-15.0, -5.0];
+/// Upper right corner of the default map [m]
+pub const MAP_MAX: [f32; 2] = [15.0, 25.0];
+/// Default side length of a cell [m]
+pub const MAP_RESOLUTION: f32 = 0.25;
+
+/// Log-odds of probability `p`
+pub fn log_odds(p: f32) -> f32 {
+    (p / (1.0 - p)).ln()
+}
+
+/// Probability of log-odds `l`
+pub fn probability(l: f32) -> f32 {
+    1.0 - 1.0 / (1.0 + exp(l))
+}
+
+/// Grid of log-odds of occupancy, updated from lidar scans at known poses
+#[derive(Debug, PartialEq, Clone)]
+pub struct OccupancyGrid {
+    /// Log-odds of each cell being occupied, zero if unknown
+    pub log_odds: GridMap<f32>,
+    /// Log-odds added to the cell a beam ends in
+    pub l_occupied: f32,
+    /// Log-odds added to the cells a beam passes through
+    pub l_free: f32,
+    /// Lower and upper bound of the log-odds, so that the map can still change
+    pub l_bounds: [f32; 2],
+    /// Probability above which a cell is taken as occupied, e.g. when ray casting
+    pub occupied_threshold: f32,
+    /// Probability below which a cell is taken as free
+    pub free_threshold: f32,
+}
+
+impl Default for OccupancyGrid {
+    fn default() -> Self {
+        Self::new(MAP_MIN.into(), MAP_MAX.into(), MAP_RESOLUTION)
+    }
+}
+
+impl OccupancyGrid {
+    /// Unknown cells of side `resolution` covering `min` to `max`
+    pub fn new(min: Vector2, max: Vector2, resolution: f32) -> Self {
+        Self {
+            log_odds: GridMap::new(min, max, resolution, 0.0),
+            l_occupied: log_odds(0.7),
+            l_free: log_odds(0.4),
+            l_bounds: [-5.0, 5.0],
+            occupied_threshold: 0.65,
+            free_threshold: 0.196,
+        }
+    }
+
+    /// Inverse sensor model, as the probability of a cell being occupied given
+    /// a beam ending in it (`p_occupied`) or passing through it (`p_free`)
+    pub fn with_sensor_model(mut self, p_occupied: f32, p_free: f32) -> Self {
+        self.l_occupied = log_odds(p_occupied);
+        self.l_free = log_odds(p_free);
+        self
+    }
+
+    pub fn with_bounds(mut self, l_min: f32, l_max: f32) -> Self {
+        self.l_bounds = [l_min, l_max];
+        self
+    }
+
+    pub fn with_thresholds(mut self, occupied: f32, free: f32) -> Self {
+        self.occupied_threshold = occupied;
+        self.free_threshold = free;
+        self
+    }
+
+    /// Forget everything, leaving all cells unknown
+    pub fn reset(&mut self) {
+        self.log_odds.cells.fill(0.0);
+    }
+
+    pub fn resolution(&self) -> f32 {
+        self.log_odds.resolution
+    }
+
+    /// Probability of each cell being occupied
+    pub fn probabilities(&self) -> DMatrix<f32> {
+        self.log_odds.cells.map(probability)
+    }
+
+    /// Whether cell (`i`, `j`) is likely occupied
+    pub fn is_occupied(&self, i: usize, j: usize) -> bool {
+        probability(self.log_odds.cells[(i, j)]) > self.occupied_threshold
+    }
+
+    /// Whether cell (`i`, `j`) is likely free
+    pub fn is_free(&self, i: usize, j: usize) -> bool {
+        probability(self.log_odds.cells[(i, j)]) < self.free_threshold
+    }
+
+    /// Cells which are likely occupied
+    pub fn occupied(&self) -> GridMap<bool> {
+        let (nx, ny) = self.log_odds.shape();
+        GridMap {
+            cells: DMatrix::from_fn(nx, ny, |i, j| self.is_occupied(i, j)),
+            min: self.log_odds.min,
+            resolution: self.resolution(),
+        }
+    }
+
+    /// Integrate `scan` taken at `pose`.
+    ///
+    /// The cells each beam passes through become more likely free, and the cell
+    /// it ends in more likely occupied. Beams without a hit only clear cells up
+    /// to the maximum range.
+    pub fn update(&mut self, pose: &Pose2, scan: &Scan) {
+        let origin = pose.translation();
+        let [l_min, l_max] = self.l_bounds;
+        for (angle, range) in scan.angles.iter().zip(scan.ranges.iter()) {
+            let angle = pose.theta + angle;
+            let end = origin + vector![cos(angle), sin(angle)] * range.unwrap_or(scan.max_range);
+            let hit = range.and_then(|_| self.log_odds.cell_index(&end));
+            for (index, _) in self.log_odds.traverse(&origin, &end) {
+                let l = if Some(index) == hit {
+                    self.l_occupied
+                } else {
+                    self.l_free
+                };
+                let cell = &mut self.log_odds.cells[index];
+                *cell = (*cell + l).clamp(l_min, l_max);
+            }
+        }
+    }
+}
+
+impl RayCast for OccupancyGrid {
+    fn cast(&self, origin: &Vector2, angle: f32, max_range: f32) -> Option<f32> {
+        let to = origin + vector![cos(angle), sin(angle)] * max_range;
+        self.log_odds
+            .traverse(origin, &to)
+            .into_iter()
+            .find(|((i, j), _)| self.is_occupied(*i, *j))
+            .map(|(_, range)| range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensor::lidar::Lidar;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn map_room_from_scans() {
+        let room = Polygon::rectangle(vector![-4., -3.], vector![4., 3.]);
+        let world = World::default().with_polygon(&room);
+        let lidar = Lidar::default()
+            .with_beams(360, TAU)
+            .with_noise(diag![1e-4]);
+        let mut grid = OccupancyGrid::new(vector![-5., -5.], vector![5., 5.], 0.2);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for x in [-2.0, 0.0, 2.0] {
+            let pose = Pose2::new(x, 0.5, 0.3);
+            grid.update(&pose, &lidar.scan(&mut rng, &pose, &world));
+        }
+
+        let cell = |p: Vector2| grid.log_odds.cell_index(&p).unwrap();
+        let (i, j) = cell(vector![0.1, 0.1]);
+        assert!(grid.is_free(i, j));
+        let (i, j) = cell(vector![4.1, 1.1]);
+        assert!(grid.is_occupied(i, j));
+        let (i, j) = cell(vector![4.7, 4.7]);
+        assert_eq!(grid.log_odds.cells[(i, j)], 0.0);
+
+        // The map can stand in for the world it was built from
+        let pose = Pose2::new(1.0, -1.0, 0.0);
+        let range = grid.cast(&pose.translation(), 0.0, 10.0).unwrap();
+        assert!((range - 3.0).abs() < 0.3, "{range}");
+    }
+}
@@ -0,0 +1,225 @@
+use super::*;
+
+/// Distance minimized between matched points by [`Icp`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IcpMetric {
+    /// Euclidean distance between a point and its nearest neighbour, solved in
+    /// closed form on every iteration
+    PointToPoint,
+    /// Distance from a point to the line through its nearest neighbour, along
+    /// the normal of the target there, which lets points slide along walls
+    PointToPlane,
+}
+
+/// Iterative closest point registration
+#[derive(Debug, PartialEq, Clone)]
+pub struct Icp {
+    pub metric: IcpMetric,
+    pub max_iterations: usize,
+    /// Points further than this from their nearest neighbour are left out [m]
+    pub max_distance: f32,
+    /// Translation [m] and rotation [rad] of an update below which it converged
+    pub tolerance: f32,
+    /// Number of neighbours fitting the normal of each target point
+    pub neighbors: usize,
+}
+
+impl Default for Icp {
+    fn default() -> Self {
+        Self::new(IcpMetric::PointToPoint)
+    }
+}
+
+impl Icp {
+    pub fn new(metric: IcpMetric) -> Self {
+        Self {
+            metric,
+            max_iterations: 50,
+            max_distance: 1.0,
+            tolerance: 1e-4,
+            neighbors: 5,
+        }
+    }
+
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    pub fn with_max_distance(mut self, max_distance: f32) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Register `source` onto `target`, starting from `guess`
+    pub fn align(&self, source: &[Vector2], target: &[Vector2], guess: &SE2) -> Registration {
+        let tree = KdTree::new(target);
+        let normals = match self.metric {
+            IcpMetric::PointToPoint => Vec::new(),
+            IcpMetric::PointToPlane => normals(&tree, target, self.neighbors),
+        };
+        let mut transform = *guess;
+        for iteration in 1..=self.max_iterations {
+            // Source points with their nearest neighbour in the target
+            let pairs: Vec<(Vector2, usize)> = source
+                .iter()
+                .map(|s| transform.transform_point(s))
+                .filter_map(|p| {
+                    tree.nearest(&p)
+                        .filter(|(_, d2)| *d2 <= self.max_distance.powi(2))
+                        .map(|(j, _)| (p, j))
+                })
+                .collect();
+            if pairs.len() < 3 {
+                return Registration::failed(transform, iteration);
+            }
+
+            // Normal equations of the residuals, linearized in a small update
+            // `[dx, dy, dtheta]` applied to the transformed points
+            let (mut jtj, mut jtr, mut sse, mut n) = (Matrix3::zeros(), Vector3::zeros(), 0.0, 0);
+            for (p, j) in pairs.iter() {
+                let r = p - target[*j];
+                let dp = vector![-p.y, p.x];
+                match self.metric {
+                    IcpMetric::PointToPoint => {
+                        let J = matrix![1.0, 0.0, dp.x; 0.0, 1.0, dp.y];
+                        jtj += J.transpose() * J;
+                        jtr += J.transpose() * r;
+                        sse += r.norm_squared();
+                        n += 2;
+                    }
+                    IcpMetric::PointToPlane => {
+                        let normal = normals[*j];
+                        let J = vector![normal.x, normal.y, normal.dot(&dp)];
+                        let r = normal.dot(&r);
+                        jtj += J * J.transpose();
+                        jtr += J * r;
+                        sse += r * r;
+                        n += 1;
+                    }
+                }
+            }
+
+            let delta = match self.metric {
+                IcpMetric::PointToPoint => {
+                    let matched: Vec<_> = pairs.iter().map(|(p, j)| (*p, target[*j])).collect();
+                    rigid_transform(&matched)
+                }
+                IcpMetric::PointToPlane => SE2::from_vector(&solve_constrained(&jtj, &-jtr)),
+            };
+            transform = delta.compose(&transform);
+
+            if delta.translation().norm() < self.tolerance && delta.theta.abs() < self.tolerance {
+                return Registration {
+                    transform,
+                    covariance: covariance(&jtj, sse, n),
+                    status: Convergence::Converged,
+                    iterations: iteration,
+                    matched: pairs.len(),
+                };
+            }
+            if iteration == self.max_iterations {
+                return Registration {
+                    transform,
+                    covariance: covariance(&jtj, sse, n),
+                    status: Convergence::MaxIterations,
+                    iterations: iteration,
+                    matched: pairs.len(),
+                };
+            }
+        }
+        Registration::failed(transform, 0)
+    }
+}
+
+/// Rigid transform best taking the first point of each pair onto the second,
+/// in the least squares sense (Arun et al., 1987)
+fn rigid_transform(pairs: &[(Vector2, Vector2)]) -> SE2 {
+    let n = pairs.len() as f32;
+    let (p_mean, q_mean) = pairs
+        .iter()
+        .fold((Vector2::zeros(), Vector2::zeros()), |(sp, sq), (p, q)| {
+            (sp + p / n, sq + q / n)
+        });
+    let (sin, cos) = pairs.iter().fold((0.0, 0.0), |(sin, cos), (p, q)| {
+        let (p, q) = (p - p_mean, q - q_mean);
+        (sin + p.x * q.y - p.y * q.x, cos + p.dot(&q))
+    });
+    let theta = sin.atan2(cos);
+    let rotated = SE2::new(0.0, 0.0, theta).transform_point(&p_mean);
+    let t = q_mean - rotated;
+    SE2::new(t.x, t.y, theta)
+}
+
+/// Unit normal of the line fit through each point and its `k` nearest
+/// neighbours, looked up in the `tree` of the points
+fn normals(tree: &KdTree, points: &[Vector2], k: usize) -> Vec<Vector2> {
+    points
+        .iter()
+        .map(|p| {
+            let neighbors: Vec<_> = tree
+                .k_nearest(p, k.max(2) + 1)
+                .iter()
+                .map(|(j, _)| points[*j])
+                .collect();
+            let n = neighbors.len() as f32;
+            let mean = neighbors
+                .iter()
+                .fold(Vector2::zeros(), |sum, q| sum + *q / n);
+            let cov = neighbors.iter().fold(Matrix2::zeros(), |sum, q| {
+                sum + (*q - mean) * (*q - mean).transpose()
+            });
+            // Normal to the direction of largest spread
+            let phi = 0.5 * (2.0 * cov[(0, 1)]).atan2(cov[(0, 0)] - cov[(1, 1)]);
+            vector![-sin(phi), cos(phi)]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensor::lidar::Lidar;
+
+    #[test]
+    fn both_metrics_recover_transform() {
+        let world = World::default()
+            .with_polygon(&Polygon::rectangle(vector![-5., -3.], vector![6., 4.]))
+            .with_polygon(&Polygon::new(vec![
+                vector![1., 0.],
+                vector![2., 0.5],
+                vector![1., 1.5],
+            ]));
+        let lidar = Lidar::default();
+        let target_pose = SE2::new(-1.0, 0.5, 0.2);
+        let source_pose = SE2::new(-0.7, 0.3, 0.3);
+        let target = lidar.cast(&target_pose, &world).points();
+        let source = lidar.cast(&source_pose, &world).points();
+        let expected = target_pose.between(&source_pose);
+
+        for metric in [IcpMetric::PointToPoint, IcpMetric::PointToPlane] {
+            let icp = Icp::new(metric).with_max_iterations(100);
+            let registration = icp.align(&source, &target, &SE2::identity());
+            assert!(registration.is_converged(), "{metric:?}: {registration:?}");
+            let error = expected.between(&registration.transform);
+            assert!(error.translation().norm() < 0.05, "{metric:?}: {error:?}");
+            assert!(error.theta.abs() < 0.01, "{metric:?}: {error:?}");
+            let sigma = registration.covariance.diagonal().map(sqrt);
+            assert!(sigma.iter().all(|s| s.is_finite() && *s < 0.05), "{sigma}");
+        }
+
+        // Nothing within reach of the source
+        let far: Vec<_> = source.iter().map(|p| p + vector![50.0, 0.0]).collect();
+        let registration = Icp::default().align(&far, &target, &SE2::identity());
+        assert_eq!(registration.status, Convergence::Failed);
+        // Infinite along the diagonal, without NaN off it
+        let covariance = registration.covariance;
+        assert!(covariance.diagonal().iter().all(|s| *s == f32::INFINITY));
+        assert!(!covariance.iter().any(|s| s.is_nan()), "{covariance}");
+    }
+}
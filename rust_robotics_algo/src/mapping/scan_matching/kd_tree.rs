@@ -0,0 +1,111 @@
+use super::*;
+
+/// k-d tree over 2D points, for nearest neighbour queries in logarithmic time
+#[derive(Debug, PartialEq, Clone)]
+pub struct KdTree<'a> {
+    points: &'a [Vector2],
+    /// Indices of `points`, ordered so that each subtree is a contiguous range
+    /// with its splitting point in the middle, alternately along x and y
+    order: Vec<usize>,
+}
+
+impl<'a> KdTree<'a> {
+    pub fn new(points: &'a [Vector2]) -> Self {
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        build(points, &mut order, 0);
+        Self { points, order }
+    }
+
+    /// Index and squared distance of the point nearest to `p`
+    pub fn nearest(&self, p: &Vector2) -> Option<(usize, f32)> {
+        self.k_nearest(p, 1).first().copied()
+    }
+
+    /// Indices and squared distances of the `k` points nearest to `p`, nearest
+    /// first
+    pub fn k_nearest(&self, p: &Vector2, k: usize) -> Vec<(usize, f32)> {
+        let mut found = Vec::with_capacity(k + 1);
+        if k > 0 {
+            self.search(&self.order, 0, p, k, &mut found);
+        }
+        found
+    }
+
+    fn search(
+        &self,
+        order: &[usize],
+        axis: usize,
+        p: &Vector2,
+        k: usize,
+        found: &mut Vec<(usize, f32)>,
+    ) {
+        if order.is_empty() {
+            return;
+        }
+        let mid = order.len() / 2;
+        let q = self.points[order[mid]];
+        let d2 = (q - p).norm_squared();
+        if found.len() < k || d2 < found[k - 1].1 {
+            let at = found.partition_point(|(_, d)| *d <= d2);
+            found.insert(at, (order[mid], d2));
+            found.truncate(k);
+        }
+
+        // Search the side of the split holding `p` first, and the other side
+        // only if it may hold nearer points than those found
+        let diff = p[axis] - q[axis];
+        let (near, far) = if diff < 0.0 {
+            (&order[..mid], &order[mid + 1..])
+        } else {
+            (&order[mid + 1..], &order[..mid])
+        };
+        self.search(near, 1 - axis, p, k, found);
+        if found.len() < k || diff * diff < found[k - 1].1 {
+            self.search(far, 1 - axis, p, k, found);
+        }
+    }
+}
+
+/// Order `order` into a subtree splitting at its median along `axis`
+fn build(points: &[Vector2], order: &mut [usize], axis: usize) {
+    if order.len() <= 1 {
+        return;
+    }
+    let mid = order.len() / 2;
+    order.select_nth_unstable_by(mid, |a, b| points[*a][axis].total_cmp(&points[*b][axis]));
+    let (below, above) = order.split_at_mut(mid);
+    build(points, below, 1 - axis);
+    build(points, &mut above[1..], 1 - axis);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_brute_force() {
+        let points: Vec<Vector2> = (0..200)
+            .map(|i| {
+                let t = i as f32 * 0.37;
+                vector![5.0 * cos(t) + 0.1 * (i % 7) as f32, 3.0 * sin(1.3 * t)]
+            })
+            .collect();
+        let tree = KdTree::new(&points);
+        assert_eq!(KdTree::new(&[]).nearest(&vector![0.0, 0.0]), None);
+
+        for p in [vector![0.0, 0.0], vector![4.0, -1.0], vector![-7.0, 2.5]] {
+            let mut expected: Vec<_> = points
+                .iter()
+                .map(|q| (q - p).norm_squared())
+                .enumerate()
+                .collect();
+            expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+            let found = tree.k_nearest(&p, 6);
+            let distances: Vec<_> = found.iter().map(|(_, d)| *d).collect();
+            let expected: Vec<_> = expected.iter().take(6).map(|(_, d)| *d).collect();
+            assert_eq!(distances, expected);
+            assert_eq!(tree.nearest(&p), found.first().copied());
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Scan matching, i.e. registration of one 2D point cloud onto another
+//!
+//! - [`Icp`]: iterative closest point, minimizing either the point-to-point or
+//!   the point-to-plane distance between nearest neighbours
+//! - [`Ndt`]: normal distributions transform, fitting the points to a grid of
+//!   Gaussians summarizing the target
+//!
+//! Both estimate the [`SE2`] transform taking the source points into the frame
+//! of the target, along with its covariance and whether it converged. Chaining
+//! registrations of consecutive scans gives [`ScanOdometry`].
+
+use super::*;
+use crate::pose::SE2;
+
+mod icp;
+mod kd_tree;
+mod ndt;
+
+pub use icp::{Icp, IcpMetric};
+use kd_tree::KdTree;
+pub use ndt::Ndt;
+
+/// How an iterative registration ended
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Convergence {
+    /// The update of the transform fell below the tolerance
+    Converged,
+    /// The iterations ran out before converging
+    MaxIterations,
+    /// Too few points were matched to constrain the transform
+    Failed,
+}
+
+/// Result of registering a source point cloud onto a target
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Registration {
+    /// Transform taking the source points into the frame of the target
+    pub transform: SE2,
+    /// Covariance of the transform `[x, y, theta]`
+    pub covariance: Matrix3,
+    pub status: Convergence,
+    pub iterations: usize,
+    /// Number of source points matched in the last iteration
+    pub matched: usize,
+}
+
+impl Registration {
+    /// Registration which gave up at `guess` after `iterations`
+    fn failed(guess: SE2, iterations: usize) -> Self {
+        Self {
+            transform: guess,
+            covariance: Matrix3::from_diagonal_element(f32::INFINITY),
+            status: Convergence::Failed,
+            iterations,
+            matched: 0,
+        }
+    }
+
+    pub fn is_converged(&self) -> bool {
+        self.status == Convergence::Converged
+    }
+}
+
+/// Algorithm registering a point cloud onto another
+#[derive(Debug, PartialEq, Clone)]
+pub enum ScanMatcher {
+    Icp(Icp),
+    Ndt(Ndt),
+}
+
+impl Default for ScanMatcher {
+    fn default() -> Self {
+        Self::Icp(Icp::new(IcpMetric::PointToPlane))
+    }
+}
+
+impl ScanMatcher {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Icp(icp) => match icp.metric {
+                IcpMetric::PointToPoint => "ICP (Point-to-Point)",
+                IcpMetric::PointToPlane => "ICP (Point-to-Plane)",
+            },
+            Self::Ndt(_) => "NDT",
+        }
+    }
+
+    /// Register `source` onto `target`, starting from `guess`
+    pub fn align(&self, source: &[Vector2], target: &[Vector2], guess: &SE2) -> Registration {
+        match self {
+            Self::Icp(icp) => icp.align(source, target, guess),
+            Self::Ndt(ndt) => ndt.align(source, target, guess),
+        }
+    }
+}
+
+/// Ratio to the largest eigenvalue of the normal equations below which a
+/// direction is taken as unconstrained by the points
+const DEGENERACY: f32 = 0.2;
+
+/// Solution of `A x = b` for a symmetric `A` over `[x, y, theta]`, leaving out
+/// the directions `A` barely constrains (Zhang et al., 2016), e.g. along the
+/// only wall in sight, so that the transform stays at its guess there rather
+/// than sliding away
+fn solve_constrained(a: &Matrix3, b: &Vector3) -> Vector3 {
+    // Rotations are scaled by the lever arm of the points, so that they
+    // compare with translations
+    let lever = sqrt(a[(2, 2)] / (0.5 * (a[(0, 0)] + a[(1, 1)])).max(f32::EPSILON));
+    let scale = Vector3::new(1.0, 1.0, 1.0 / lever.max(f32::EPSILON));
+    let scaled = Matrix3::from_diagonal(&scale) * a * Matrix3::from_diagonal(&scale);
+    let eigen = scaled.symmetric_eigen();
+    let largest = eigen.eigenvalues.amax();
+    let b = b.component_mul(&scale);
+    (0..3)
+        .map(|i| (eigen.eigenvalues[i].abs(), eigen.eigenvectors.column(i)))
+        .filter(|(value, _)| *value > DEGENERACY * largest)
+        .fold(Vector3::zeros(), |x, (value, v)| {
+            x + v * (v.dot(&b) / value)
+        })
+        .component_mul(&scale)
+}
+
+/// Covariance of a transform from the normal equations `JᵀJ` of its residuals,
+/// whose variance is estimated from their sum of squares `sse` over `n` residuals
+fn covariance(jtj: &Matrix3, sse: f32, n: usize) -> Matrix3 {
+    let dof = n.saturating_sub(3).max(1) as f32;
+    jtj.try_inverse()
+        .map(|inv| inv * (sse / dof))
+        .unwrap_or_else(|| Matrix3::from_diagonal_element(f32::INFINITY))
+}
+
+/// Pose estimate chained from the registrations of consecutive scans
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScanOdometry {
+    pub matcher: ScanMatcher,
+    /// Pose of the sensor at the latest scan
+    pub pose: SE2,
+    /// Registration of the latest scan onto the one before
+    pub last: Option<Registration>,
+    /// Points of the latest scan, relative to the sensor
+    points: Vec<Vector2>,
+}
+
+impl Default for ScanOdometry {
+    fn default() -> Self {
+        Self::new(ScanMatcher::default())
+    }
+}
+
+impl ScanOdometry {
+    pub fn new(matcher: ScanMatcher) -> Self {
+        Self {
+            matcher,
+            pose: SE2::identity(),
+            last: None,
+            points: Vec::new(),
+        }
+    }
+
+    /// Forget the previous scan and start over from `pose`
+    pub fn reset(&mut self, pose: SE2) {
+        self.pose = pose;
+        self.last = None;
+        self.points.clear();
+    }
+
+    /// Move by the registration of `points` onto the previous scan, starting
+    /// from `motion` since then, e.g. from wheel odometry. The guess is kept as
+    /// is if the registration fails.
+    pub fn update(&mut self, points: Vec<Vector2>, motion: &SE2) -> &SE2 {
+        let delta = if self.points.is_empty() {
+            *motion
+        } else {
+            let registration = self.matcher.align(&points, &self.points, motion);
+            self.last = Some(registration);
+            match registration.status {
+                Convergence::Failed => *motion,
+                _ => registration.transform,
+            }
+        };
+        self.pose = self.pose.compose(&delta);
+        self.points = points;
+        &self.pose
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensor::lidar::Lidar;
+
+    #[test]
+    fn odometry_follows_scans() {
+        let world = World::default()
+            .with_polygon(&Polygon::rectangle(vector![-6., -4.], vector![8., 5.]))
+            .with_polygon(&Polygon::rectangle(vector![1., 1.], vector![2., 2.]));
+        let lidar = Lidar::default();
+        let step = SE2::new(0.2, 0.05, 0.05);
+
+        for matcher in [ScanMatcher::default(), ScanMatcher::Ndt(Ndt::default())] {
+            let mut odometry = ScanOdometry::new(matcher);
+            let mut pose = SE2::new(-2.0, -1.0, 0.1);
+            odometry.reset(pose);
+            odometry.update(lidar.cast(&pose, &world).points(), &SE2::identity());
+
+            for _ in 0..15 {
+                pose = pose.compose(&step);
+                // Odometry off by a few centimetres and a degree
+                let motion = step.compose(&SE2::new(0.03, -0.02, 0.02));
+                odometry.update(lidar.cast(&pose, &world).points(), &motion);
+            }
+            let name = odometry.matcher.name();
+            let error = odometry.pose.between(&pose);
+            assert!(error.translation().norm() < 0.05, "{name}: {error:?}");
+            assert!(error.theta.abs() < 0.01, "{name}: {error:?}");
+            assert!(odometry.last.unwrap().is_converged());
+        }
+    }
+}
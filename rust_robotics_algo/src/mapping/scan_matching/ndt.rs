@@ -0,0 +1,249 @@
+use super::*;
+
+/// Gaussian summarizing the target points in a cell of the [`Ndt`] grid
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Cell {
+    mean: Vector2,
+    /// Inverse of the covariance
+    information: Matrix2,
+}
+
+/// Normal distributions transform registration (Biber and Straßer, 2003)
+///
+/// The target is summarized by a Gaussian per cell of a grid, and the source
+/// points are fit to them by Gauss-Newton, which needs no nearest neighbour
+/// search.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ndt {
+    /// Side length of a cell [m]
+    pub resolution: f32,
+    pub max_iterations: usize,
+    /// Translation [m] and rotation [rad] of an update below which it converged
+    pub tolerance: f32,
+    /// Fewest target points in a cell for it to get a Gaussian
+    pub min_points: usize,
+}
+
+impl Default for Ndt {
+    fn default() -> Self {
+        Self {
+            resolution: 1.0,
+            max_iterations: 50,
+            tolerance: 1e-4,
+            min_points: 3,
+        }
+    }
+}
+
+/// Score of a transform, to be minimized, with its derivatives
+struct Score {
+    value: f32,
+    gradient: Vector3,
+    /// Gauss-Newton approximation of the Hessian, which unlike the exact one
+    /// is never indefinite
+    hessian: Matrix3,
+    /// Information of the transform `Σ Jᵀ C J` from the cells the points are in
+    information: Matrix3,
+    /// Number of source points within a cell
+    matched: usize,
+}
+
+impl Ndt {
+    pub fn with_resolution(mut self, resolution: f32) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Register `source` onto `target`, starting from `guess`
+    pub fn align(&self, source: &[Vector2], target: &[Vector2], guess: &SE2) -> Registration {
+        let grid = match self.cells(target) {
+            Some(grid) => grid,
+            None => return Registration::failed(*guess, 0),
+        };
+        let mut x = guess.to_vector();
+        let mut score = evaluate(&grid, source, &x);
+        for iteration in 1..=self.max_iterations {
+            if score.matched < 3 {
+                return Registration::failed(SE2::from_vector(&x), iteration);
+            }
+
+            // Gauss-Newton step, leaving out the directions the points barely constrain
+            let step = solve_constrained(&score.hessian, &-score.gradient);
+
+            // Backtrack until the score improves
+            let mut alpha = 1.0;
+            let mut next = None;
+            for _ in 0..10 {
+                let candidate = evaluate(&grid, source, &(x + step * alpha));
+                if candidate.value < score.value {
+                    next = Some(candidate);
+                    break;
+                }
+                alpha *= 0.5;
+            }
+            let step = step * alpha;
+            let converged = match next {
+                Some(next) => {
+                    x += step;
+                    score = next;
+                    step.xy().norm() < self.tolerance && step.z.abs() < self.tolerance
+                }
+                // No improvement along the step, so already at the minimum
+                None => true,
+            };
+
+            if converged || iteration == self.max_iterations {
+                let covariance = score
+                    .information
+                    .try_inverse()
+                    .unwrap_or_else(|| Matrix3::from_diagonal_element(f32::INFINITY));
+                return Registration {
+                    transform: SE2::from_vector(&x),
+                    covariance,
+                    status: if converged {
+                        Convergence::Converged
+                    } else {
+                        Convergence::MaxIterations
+                    },
+                    iterations: iteration,
+                    matched: score.matched,
+                };
+            }
+        }
+        Registration::failed(*guess, 0)
+    }
+
+    /// Gaussians of the cells with enough of the `points`, if any
+    fn cells(&self, points: &[Vector2]) -> Option<GridMap<Option<Cell>>> {
+        let (min, max) = points.iter().fold(
+            (vector![f32::MAX, f32::MAX], vector![f32::MIN, f32::MIN]),
+            |(min, max), p| (min.inf(p), max.sup(p)),
+        );
+        if points.is_empty() {
+            return None;
+        }
+        let margin = vector![self.resolution, self.resolution];
+        let mut grid = GridMap::new(min - margin, max + margin, self.resolution, None);
+
+        let (nx, ny) = grid.shape();
+        let mut buckets = vec![Vec::new(); nx * ny];
+        for p in points {
+            if let Some((i, j)) = grid.cell_index(p) {
+                buckets[i * ny + j].push(*p);
+            }
+        }
+        for (index, bucket) in buckets.iter().enumerate() {
+            if bucket.len() < self.min_points.max(3) {
+                continue;
+            }
+            let n = bucket.len() as f32;
+            let mean = bucket.iter().fold(Vector2::zeros(), |sum, p| sum + p / n);
+            let cov = bucket.iter().fold(Matrix2::zeros(), |sum, p| {
+                sum + (p - mean) * (p - mean).transpose() / (n - 1.0)
+            });
+            // Points along a wall leave the covariance near singular, so its
+            // smallest eigenvalue is kept to a fraction of the largest, and of
+            // the cell size
+            let eigen = cov.symmetric_eigen();
+            let largest = eigen.eigenvalues.max();
+            let values = eigen
+                .eigenvalues
+                .map(|v| v.max((0.01 * largest).max((0.1 * self.resolution).powi(2))));
+            let information = eigen.eigenvectors
+                * Matrix2::from_diagonal(&values.map(|v| 1.0 / v))
+                * eigen.eigenvectors.transpose();
+            grid.cells[(index / ny, index % ny)] = Some(Cell { mean, information });
+        }
+        grid.cells.iter().any(Option::is_some).then_some(grid)
+    }
+}
+
+/// Score `-Σ exp(-dᵀ C d / 2)` of the `source` points moved by `x = [x, y, theta]`
+/// against the Gaussians of the cell each lands in and its neighbours
+fn evaluate(grid: &GridMap<Option<Cell>>, source: &[Vector2], x: &Vector3) -> Score {
+    let pose = SE2::from_vector(x);
+    let (sin, cos) = x.z.sin_cos();
+    let (nx, ny) = grid.shape();
+    let mut score = Score {
+        value: 0.0,
+        gradient: Vector3::zeros(),
+        hessian: Matrix3::zeros(),
+        information: Matrix3::zeros(),
+        matched: 0,
+    };
+    for s in source {
+        let p = pose.transform_point(s);
+        let (i, j) = match grid.cell_index(&p) {
+            Some(index) => index,
+            None => continue,
+        };
+        // Derivatives of the point by the transform
+        let dtheta = vector![-sin * s.x - cos * s.y, cos * s.x - sin * s.y];
+        let J = matrix![1.0, 0.0, dtheta.x; 0.0, 1.0, dtheta.y];
+
+        let mut best: Option<(f32, Matrix2)> = None;
+        let neighbors = (i.saturating_sub(1)..(i + 2).min(nx))
+            .flat_map(|i| (j.saturating_sub(1)..(j + 2).min(ny)).map(move |j| (i, j)));
+        for index in neighbors {
+            let Cell { mean, information } = match grid.cells[index] {
+                Some(cell) => cell,
+                None => continue,
+            };
+            let d = p - mean;
+            let e = exp(-0.5 * d.dot(&(information * d)));
+            let dCJ = (d.transpose() * information * J).transpose();
+            score.value -= e;
+            score.gradient += dCJ * e;
+            score.hessian += J.transpose() * information * J * e;
+            if best.is_none_or(|(best, _)| e > best) {
+                best = Some((e, information));
+            }
+        }
+        if let Some((_, information)) = best {
+            score.information += J.transpose() * information * J;
+            score.matched += 1;
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensor::lidar::Lidar;
+
+    #[test]
+    fn fit_points_to_distributions() {
+        let world = World::default()
+            .with_polygon(&Polygon::rectangle(vector![-5., -3.], vector![6., 4.]))
+            .with_polygon(&Polygon::rectangle(vector![1., 0.], vector![2., 1.]));
+        let lidar = Lidar::default().with_beams(360, TAU);
+        let target_pose = SE2::new(-1.0, 0.5, 0.2);
+        let source_pose = SE2::new(-0.8, 0.3, 0.25);
+        let target = lidar.cast(&target_pose, &world).points();
+        let source = lidar.cast(&source_pose, &world).points();
+        let expected = target_pose.between(&source_pose);
+
+        let registration = Ndt::default().align(&source, &target, &SE2::identity());
+        assert!(registration.is_converged(), "{registration:?}");
+        let error = expected.between(&registration.transform);
+        assert!(error.translation().norm() < 0.05, "{error:?}");
+        assert!(error.theta.abs() < 0.01, "{error:?}");
+        assert!(registration.covariance.diagonal().iter().all(|v| *v > 0.0));
+
+        assert_eq!(
+            Ndt::default().align(&source, &[], &SE2::identity()).status,
+            Convergence::Failed
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use super::*;
+
+/// Line segment from `a` to `b`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Segment {
+    pub a: Vector2,
+    pub b: Vector2,
+}
+
+impl Segment {
+    pub fn new(a: Vector2, b: Vector2) -> Self {
+        Self { a, b }
+    }
+}
+
+impl RayCast for Segment {
+    fn cast(&self, origin: &Vector2, angle: f32, max_range: f32) -> Option<f32> {
+        let d = vector![cos(angle), sin(angle)];
+        let e = self.b - self.a;
+        // Solve origin + t * d = a + s * e
+        let denom = d.perp(&e);
+        if denom.abs() < f32::EPSILON {
+            return None;
+        }
+        let w = self.a - origin;
+        let t = w.perp(&e) / denom;
+        let s = w.perp(&d) / denom;
+        ((0.0..=1.0).contains(&s) && (0.0..=max_range).contains(&t)).then_some(t)
+    }
+}
+
+/// Closed polygon through `vertices`
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Polygon {
+    pub vertices: Vec<Vector2>,
+}
+
+impl Polygon {
+    pub fn new(vertices: Vec<Vector2>) -> Self {
+        Self { vertices }
+    }
+
+    /// Axis-aligned rectangle from the lower left corner `min` to the upper
+    /// right corner `max`
+    pub fn rectangle(min: Vector2, max: Vector2) -> Self {
+        Self::new(vec![min, vector![max.x, min.y], max, vector![min.x, max.y]])
+    }
+
+    /// Edges of the polygon, including the one closing it
+    pub fn edges(&self) -> impl Iterator<Item = Segment> + '_ {
+        let n = self.vertices.len();
+        (0..n).map(move |i| Segment::new(self.vertices[i], self.vertices[(i + 1) % n]))
+    }
+}
+
+impl RayCast for Polygon {
+    fn cast(&self, origin: &Vector2, angle: f32, max_range: f32) -> Option<f32> {
+        self.edges()
+            .filter_map(|edge| edge.cast(origin, angle, max_range))
+            .min_by(|a, b| a.total_cmp(b))
+    }
+}
+
+/// World made of walls, each a line segment
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct World {
+    pub walls: Vec<Segment>,
+}
+
+impl World {
+    pub fn with_segment(mut self, segment: Segment) -> Self {
+        self.walls.push(segment);
+        self
+    }
+
+    pub fn with_polygon(mut self, polygon: &Polygon) -> Self {
+        self.walls.extend(polygon.edges());
+        self
+    }
+}
+
+impl RayCast for World {
+    fn cast(&self, origin: &Vector2, angle: f32, max_range: f32) -> Option<f32> {
+        self.walls.cast(origin, angle, max_range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rays_hit_nearest_wall() {
+        let world = World::default()
+            .with_polygon(&Polygon::rectangle(vector![-5., -5.], vector![5., 5.]))
+            .with_segment(Segment::new(vector![2., -1.], vector![2., 1.]));
+        let origin = vector![0., 0.];
+
+        let hit = |angle: f32| world.cast(&origin, angle, 10.0);
+        assert!((hit(0.0).unwrap() - 2.0).abs() < 1e-5);
+        assert!((hit(PI / 2.).unwrap() - 5.0).abs() < 1e-5);
+        assert!((hit(PI / 4.).unwrap() - 5.0 * sqrt(2.0)).abs() < 1e-4);
+        assert!(world.cast(&origin, PI, 4.0).is_none());
+    }
+}
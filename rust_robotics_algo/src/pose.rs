@@ -0,0 +1,281 @@
+//! Typed 2D poses and vehicle states
+//!
+//! - [`Pose2`] (a.k.a. [`SE2`]): rigid transformation `[x, y, theta]`, generic
+//!   over the scalar so that both the filters (`f32`) and the pose graph (`f64`)
+//!   share it
+//! - [`VehicleState`]: pose and velocity `[x, y, phi, v]` of the vehicle, as
+//!   estimated by the filters
+
+use crate::prelude::*;
+use nalgebra::{Isometry2, RealField};
+use std::ops::{AddAssign, Mul};
+
+/// Wrap an angle to [-PI, PI)
+pub fn normalize_angle<T: RealField + Copy>(angle: T) -> T {
+    let (pi, two_pi) = (T::pi(), T::two_pi());
+    let wrapped = angle - two_pi * ((angle + pi) / two_pi).floor();
+    // Rounding can land exactly on PI
+    if wrapped >= pi {
+        wrapped - two_pi
+    } else {
+        wrapped
+    }
+}
+
+/// Difference `a - b` of two angles, wrapped to [-PI, PI)
+pub fn angle_diff<T: RealField + Copy>(a: T, b: T) -> T {
+    normalize_angle(a - b)
+}
+
+/// Angle equal to `angle` up to whole turns, closest to `reference`
+///
+/// Useful to keep a heading continuous along a trajectory.
+pub fn unwrap_angle<T: RealField + Copy>(angle: T, reference: T) -> T {
+    reference + angle_diff(angle, reference)
+}
+
+/// Weighted mean of `(angle, weight)` pairs on the circle, wrapped to [-PI, PI)
+///
+/// Unlike the arithmetic mean, this is unaffected by angles wrapping around,
+/// e.g. the mean of `PI - 0.1` and `-PI + 0.1` is `-PI` rather than zero.
+pub fn circular_mean<T: RealField + Copy>(angles: impl IntoIterator<Item = (T, T)>) -> T {
+    let (s, c) = angles
+        .into_iter()
+        .fold((T::zero(), T::zero()), |(s, c), (angle, w)| {
+            (s + w * angle.sin(), c + w * angle.cos())
+        });
+    normalize_angle(s.atan2(c))
+}
+
+/// Rigid transformation in 2D, `[x, y, theta]`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Pose2<T: RealField + Copy = f32> {
+    pub x: T,
+    pub y: T,
+    /// Heading, wrapped to [-PI, PI)
+    pub theta: T,
+}
+
+/// Element of the special Euclidean group SE(2), i.e. a [`Pose2`]
+pub type SE2<T = f32> = Pose2<T>;
+
+impl<T: RealField + Copy> Default for Pose2<T> {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl<T: RealField + Copy> Pose2<T> {
+    pub fn new(x: T, y: T, theta: T) -> Self {
+        Self {
+            x,
+            y,
+            theta: normalize_angle(theta),
+        }
+    }
+
+    pub fn identity() -> Self {
+        Self {
+            x: T::zero(),
+            y: T::zero(),
+            theta: T::zero(),
+        }
+    }
+
+    pub fn translation(&self) -> nalgebra::Vector2<T> {
+        nalgebra::Vector2::new(self.x, self.y)
+    }
+
+    pub fn rotation(&self) -> nalgebra::Matrix2<T> {
+        let (s, c) = self.theta.sin_cos();
+        nalgebra::Matrix2::new(c, -s, s, c)
+    }
+
+    pub fn inverse(&self) -> Self {
+        let t = -self.rotation().transpose() * self.translation();
+        Self::new(t.x, t.y, -self.theta)
+    }
+
+    /// Pose of `other`, given relative to `self`, in the frame of `self`
+    pub fn compose(&self, other: &Self) -> Self {
+        let t = self.translation() + self.rotation() * other.translation();
+        Self::new(t.x, t.y, self.theta + other.theta)
+    }
+
+    /// Pose of `other` relative to `self`, i.e. `self^-1 * other`
+    pub fn between(&self, other: &Self) -> Self {
+        self.inverse().compose(other)
+    }
+
+    /// Point `p`, given relative to `self`, in the frame of `self`
+    pub fn transform_point(&self, p: &nalgebra::Vector2<T>) -> nalgebra::Vector2<T> {
+        self.translation() + self.rotation() * p
+    }
+
+    /// Point `p` relative to `self`, i.e. the inverse of [`Self::transform_point`]
+    pub fn inverse_transform_point(&self, p: &nalgebra::Vector2<T>) -> nalgebra::Vector2<T> {
+        self.rotation().transpose() * (p - self.translation())
+    }
+
+    pub fn to_vector(&self) -> nalgebra::Vector3<T> {
+        nalgebra::Vector3::new(self.x, self.y, self.theta)
+    }
+
+    pub fn from_vector(v: &nalgebra::Vector3<T>) -> Self {
+        Self::new(v.x, v.y, v.z)
+    }
+}
+
+impl<T: RealField + Copy> Mul for Pose2<T> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.compose(&rhs)
+    }
+}
+
+impl<T: RealField + Copy> From<Pose2<T>> for Isometry2<T> {
+    fn from(pose: Pose2<T>) -> Self {
+        Isometry2::new(pose.translation(), pose.theta)
+    }
+}
+
+impl<T: RealField + Copy> From<Isometry2<T>> for Pose2<T> {
+    fn from(iso: Isometry2<T>) -> Self {
+        Self::new(
+            iso.translation.vector.x,
+            iso.translation.vector.y,
+            iso.rotation.angle(),
+        )
+    }
+}
+
+impl<T: RealField + Copy> From<nalgebra::Vector3<T>> for Pose2<T> {
+    fn from(v: nalgebra::Vector3<T>) -> Self {
+        Self::from_vector(&v)
+    }
+}
+
+impl<T: RealField + Copy> From<Pose2<T>> for nalgebra::Vector3<T> {
+    fn from(pose: Pose2<T>) -> Self {
+        pose.to_vector()
+    }
+}
+
+/// State of the vehicle, `[x, y, phi, v]`
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct VehicleState {
+    /// X Position [m]
+    pub x: f32,
+    /// Y Position [m]
+    pub y: f32,
+    /// Heading Angle [rad], which is left unwrapped so that it stays
+    /// continuous along a trajectory
+    pub phi: f32,
+    /// Velocity [m/s]
+    pub v: f32,
+}
+
+impl VehicleState {
+    pub fn new(x: f32, y: f32, phi: f32, v: f32) -> Self {
+        Self { x, y, phi, v }
+    }
+
+    /// State at `pose` moving with velocity `v`
+    pub fn from_pose(pose: Pose2, v: f32) -> Self {
+        Self::new(pose.x, pose.y, pose.theta, v)
+    }
+
+    /// Pose of the vehicle, with the heading wrapped
+    pub fn pose(&self) -> Pose2 {
+        Pose2::new(self.x, self.y, self.phi)
+    }
+
+    /// Position `[x, y]` of the vehicle
+    pub fn position(&self) -> Vector2 {
+        vector![self.x, self.y]
+    }
+
+    /// State after driving for `dt` with input `u` [velocity, yaw rate], moving
+    /// along the current heading before turning
+    pub fn predict(&self, u: Vector2, dt: f32) -> Self {
+        Self {
+            x: self.x + u[0] * cos(self.phi) * dt,
+            y: self.y + u[0] * sin(self.phi) * dt,
+            phi: self.phi + u[1] * dt,
+            v: u[0],
+        }
+    }
+}
+
+/// Apply a correction `[dx, dy, dphi, dv]`, e.g. the Kalman gain times the innovation
+impl AddAssign<Vector4> for VehicleState {
+    fn add_assign(&mut self, dx: Vector4) {
+        self.x += dx[0];
+        self.y += dx[1];
+        self.phi += dx[2];
+        self.v += dx[3];
+    }
+}
+
+impl From<Vector4> for VehicleState {
+    fn from(x: Vector4) -> Self {
+        Self::new(x[0], x[1], x[2], x[3])
+    }
+}
+
+impl From<VehicleState> for Vector4 {
+    fn from(state: VehicleState) -> Self {
+        vector![state.x, state.y, state.phi, state.v]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn angles_wrap_around() {
+        assert!((angle_diff(PI - 0.1, -PI + 0.1) + 0.2).abs() < 1e-5);
+        assert!((unwrap_angle(-PI + 0.1, 3.0) - (PI + 0.1)).abs() < 1e-5);
+        assert!((unwrap_angle(0.5, 4. * PI) - (4. * PI + 0.5)).abs() < 1e-5);
+
+        let mean = circular_mean([(PI - 0.1, 0.5), (-PI + 0.1, 0.5)]);
+        assert!((mean.abs() - PI).abs() < 1e-5);
+        let mean = circular_mean([(0.1_f64, 0.75), (-0.1, 0.25)]);
+        assert!((mean - 0.05).abs() < 1e-3);
+    }
+
+    #[test]
+    fn pose_composition_and_inversion() {
+        assert!((normalize_angle(3. * PI) + PI).abs() < 1e-5);
+        assert_eq!(normalize_angle(PI), -PI);
+        assert!((normalize_angle(-0.5_f64) + 0.5).abs() < 1e-12);
+
+        let a = Pose2::new(1.0_f32, 2.0, 0.5);
+        let b = Pose2::new(-0.5, 1.0, 2.9);
+        let ab = a * b;
+        assert!((ab.theta - normalize_angle(3.4)).abs() < 1e-5);
+        assert!((a.between(&ab).to_vector() - b.to_vector()).amax() < 1e-5);
+        assert!((a.compose(&a.inverse()).to_vector()).amax() < 1e-5);
+
+        let p = vector![3.0, -1.0];
+        assert!((a.inverse_transform_point(&a.transform_point(&p)) - p).amax() < 1e-5);
+        let iso: Isometry2<f32> = ab.into();
+        assert!((Pose2::from(iso).to_vector() - ab.to_vector()).amax() < 1e-5);
+    }
+
+    #[test]
+    fn vehicle_state_round_trips_through_vector() {
+        let x = vector![1.0, 2.0, 7.0, 0.5];
+        let state = VehicleState::from(x);
+        assert_eq!(Vector4::from(state), x);
+        // The pose wraps the heading, while the state keeps it continuous
+        assert!((state.pose().theta - normalize_angle(7.0)).abs() < 1e-6);
+
+        let next = state.predict(vector![1.0, 0.1], 0.1);
+        assert!((next.x - (1.0 + 0.1 * cos(7.0))).abs() < 1e-6);
+        assert!((next.phi - 7.01).abs() < 1e-6);
+        assert_eq!(next.v, 1.0);
+    }
+}
@@ -0,0 +1,146 @@
+use super::*;
+use crate::localization::particle_filter::randn_with;
+use rand::Rng;
+
+/// Default update rate of the GNSS receiver [Hz]
+pub const GNSS_RATE: f32 = 5.0;
+
+/// Simulated GNSS receiver, measuring the horizontal position
+///
+/// Fixes have white noise, and once in a while are off by a multipath outlier
+/// much larger than the noise. Between outages, e.g. in tunnels or under
+/// bridges, no fix is given at all.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Gnss {
+    /// Standard deviation of the position along each axis [m]
+    pub noise: f32,
+    /// Probability of a fix being a multipath outlier
+    pub outlier_probability: f32,
+    /// Standard deviation of the offset of an outlier along each axis [m]
+    pub outlier_noise: f32,
+    /// Mean number of outages starting per second [1/s]
+    pub outage_rate: f32,
+    /// Mean duration of an outage [s]
+    pub outage_duration: f32,
+    pub clock: SampleClock,
+    /// Time left in the current outage [s]
+    outage: f32,
+}
+
+impl Default for Gnss {
+    fn default() -> Self {
+        Self {
+            noise: 1.0,
+            outlier_probability: 0.05,
+            outlier_noise: 10.0,
+            outage_rate: 1.0 / 30.0,
+            outage_duration: 5.0,
+            clock: SampleClock::new(GNSS_RATE),
+            outage: 0.0,
+        }
+    }
+}
+
+impl Gnss {
+    pub fn with_noise(mut self, noise: f32) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    pub fn with_outliers(mut self, probability: f32, noise: f32) -> Self {
+        self.outlier_probability = probability;
+        self.outlier_noise = noise;
+        self
+    }
+
+    pub fn with_outages(mut self, rate: f32, duration: f32) -> Self {
+        self.outage_rate = rate;
+        self.outage_duration = duration;
+        self
+    }
+
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.clock.rate = rate;
+        self
+    }
+
+    /// Covariance of a fix which is not an outlier [m²]
+    pub fn covariance(&self) -> Matrix2 {
+        Matrix2::identity() * self.noise.powi(2)
+    }
+
+    /// Whether the receiver is in an outage
+    pub fn in_outage(&self) -> bool {
+        self.outage > 0.0
+    }
+
+    /// Lose the fix for `duration` [s], as when driving into a tunnel
+    pub fn start_outage(&mut self, duration: f32) {
+        self.outage = self.outage.max(duration);
+    }
+
+    /// Advance by `dt` at the true `position`, returning a fix when one is due
+    /// and the receiver is not in an outage
+    pub fn measure<R: Rng + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        position: &Vector2,
+        dt: f32,
+    ) -> Option<Vector2> {
+        let window = self.clock.tick(dt)?;
+        if self.in_outage() {
+            self.outage -= window;
+            return None;
+        }
+        if rng.gen::<f32>() < self.outage_rate * window {
+            // Exponentially distributed duration
+            let duration = -self.outage_duration * (1.0 - rng.gen::<f32>()).ln();
+            self.start_outage(duration);
+            return None;
+        }
+
+        let noise = vector![randn_with(rng), randn_with(rng)] * self.noise;
+        let outlier = if rng.gen::<f32>() < self.outlier_probability {
+            vector![randn_with(rng), randn_with(rng)] * self.outlier_noise
+        } else {
+            Vector2::zeros()
+        };
+        Some(position + noise + outlier)
+    }
+
+    /// End any outage, with no fix due until a full period has passed
+    pub fn reset(&mut self) {
+        self.outage = 0.0;
+        self.clock.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn fixes_have_noise_outliers_and_outages() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (position, dt, t) = (vector![3.0, -2.0], 0.01, 600.0);
+        let mut gnss = Gnss::default();
+        let fixes: Vec<Vector2> = (0..(t / dt) as usize)
+            .filter_map(|_| gnss.measure(&mut rng, &position, dt))
+            .collect();
+
+        // Outages take out about the fraction of time spent in them
+        let expected = GNSS_RATE * t / (1.0 + gnss.outage_rate * gnss.outage_duration);
+        let ratio = fixes.len() as f32 / expected;
+        assert!((ratio - 1.0).abs() < 0.1, "{} fixes", fixes.len());
+
+        // Outliers lie well beyond the noise of the other fixes
+        let errors: Vec<f32> = fixes.iter().map(|p| (p - position).norm()).collect();
+        let outliers = errors.iter().filter(|e| **e > 5.0).count() as f32;
+        let ratio = outliers / fixes.len() as f32;
+        assert!((ratio - 0.05).abs() < 0.015, "{ratio}");
+        let inliers: Vec<f32> = errors.into_iter().filter(|e| *e <= 5.0).collect();
+        let var = inliers.iter().map(|e| e * e).sum::<f32>() / (2.0 * inliers.len() as f32);
+        assert!((var - 1.0).abs() < 0.1, "{var}");
+    }
+}
@@ -0,0 +1,202 @@
+use super::*;
+use crate::localization::particle_filter::randn_with;
+use rand::Rng;
+
+/// Default update rate of the IMU [Hz]
+pub const IMU_RATE: f32 = 100.0;
+
+/// Simulated single-axis gyroscope, measuring the yaw rate
+///
+/// A sample is `(1 + scale_factor) * rate + bias + white noise`, where the bias
+/// starts at `bias` and drifts as a random walk.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Gyroscope {
+    /// Density of the white noise, i.e. angle random walk [rad/s/√Hz]
+    pub noise_density: f32,
+    /// Density of the bias random walk, i.e. rate random walk [rad/s²/√Hz]
+    pub bias_random_walk: f32,
+    /// Relative error of the measured rate
+    pub scale_factor: f32,
+    /// Bias at start [rad/s]
+    pub bias: f32,
+    pub clock: SampleClock,
+    /// Drift of the bias since the start [rad/s]
+    drift: f32,
+    /// Latest sample [rad/s]
+    last: f32,
+}
+
+impl Default for Gyroscope {
+    fn default() -> Self {
+        Self {
+            noise_density: 0.002,
+            bias_random_walk: 1e-4,
+            scale_factor: 0.01,
+            bias: 0.005,
+            clock: SampleClock::new(IMU_RATE),
+            drift: 0.0,
+            last: 0.0,
+        }
+    }
+}
+
+impl Gyroscope {
+    pub fn with_noise(mut self, noise_density: f32, bias_random_walk: f32) -> Self {
+        self.noise_density = noise_density;
+        self.bias_random_walk = bias_random_walk;
+        self
+    }
+
+    pub fn with_bias(mut self, bias: f32, scale_factor: f32) -> Self {
+        self.bias = bias;
+        self.scale_factor = scale_factor;
+        self
+    }
+
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.clock.rate = rate;
+        self
+    }
+
+    /// Current bias, including its drift [rad/s]
+    pub fn current_bias(&self) -> f32 {
+        self.bias + self.drift
+    }
+
+    /// Advance by `dt` at the true yaw rate `rate`, returning the latest sample,
+    /// held between samples
+    pub fn measure<R: Rng + ?Sized>(&mut self, rng: &mut R, rate: f32, dt: f32) -> f32 {
+        if let Some(window) = self.clock.tick(dt) {
+            self.drift += self.bias_random_walk * sqrt(window) * randn_with(rng);
+            let noise = self.noise_density / sqrt(window) * randn_with(rng);
+            self.last = (1.0 + self.scale_factor) * rate + self.current_bias() + noise;
+        }
+        self.last
+    }
+
+    /// Go back to the bias at start, with no sample yet
+    pub fn reset(&mut self) {
+        self.drift = 0.0;
+        self.last = 0.0;
+        self.clock.reset();
+    }
+}
+
+/// Simulated two-axis accelerometer, measuring the specific force along and
+/// across the heading of the vehicle
+///
+/// Each axis has the same error model as the [`Gyroscope`].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Accelerometer {
+    /// Density of the white noise, i.e. velocity random walk [m/s²/√Hz]
+    pub noise_density: f32,
+    /// Density of the bias random walk [m/s³/√Hz]
+    pub bias_random_walk: f32,
+    /// Relative error of the measured acceleration
+    pub scale_factor: f32,
+    /// Bias at start [m/s²]
+    pub bias: Vector2,
+    pub clock: SampleClock,
+    /// Drift of the bias since the start [m/s²]
+    drift: Vector2,
+    /// Latest sample [m/s²]
+    last: Vector2,
+}
+
+impl Default for Accelerometer {
+    fn default() -> Self {
+        Self {
+            noise_density: 0.005,
+            bias_random_walk: 5e-4,
+            scale_factor: 0.01,
+            bias: vector![0.02, -0.01],
+            clock: SampleClock::new(IMU_RATE),
+            drift: Vector2::zeros(),
+            last: Vector2::zeros(),
+        }
+    }
+}
+
+impl Accelerometer {
+    pub fn with_noise(mut self, noise_density: f32, bias_random_walk: f32) -> Self {
+        self.noise_density = noise_density;
+        self.bias_random_walk = bias_random_walk;
+        self
+    }
+
+    pub fn with_bias(mut self, bias: Vector2, scale_factor: f32) -> Self {
+        self.bias = bias;
+        self.scale_factor = scale_factor;
+        self
+    }
+
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.clock.rate = rate;
+        self
+    }
+
+    /// Current bias, including its drift [m/s²]
+    pub fn current_bias(&self) -> Vector2 {
+        self.bias + self.drift
+    }
+
+    /// Advance by `dt` at the true acceleration `accel` `[along, across]` the
+    /// heading, returning the latest sample, held between samples
+    pub fn measure<R: Rng + ?Sized>(&mut self, rng: &mut R, accel: Vector2, dt: f32) -> Vector2 {
+        if let Some(window) = self.clock.tick(dt) {
+            let walk = vector![randn_with(rng), randn_with(rng)];
+            self.drift += walk * self.bias_random_walk * sqrt(window);
+            let noise = vector![randn_with(rng), randn_with(rng)];
+            self.last = accel * (1.0 + self.scale_factor)
+                + self.current_bias()
+                + noise * (self.noise_density / sqrt(window));
+        }
+        self.last
+    }
+
+    /// Go back to the bias at start, with no sample yet
+    pub fn reset(&mut self) {
+        self.drift = Vector2::zeros();
+        self.last = Vector2::zeros();
+        self.clock.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn gyro_noise_matches_densities() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (rate, dt, n) = (0.2, 0.01, 10000);
+
+        // Only white noise, with the variance of its density over the bandwidth
+        let mut gyro = Gyroscope::default()
+            .with_noise(0.01, 0.0)
+            .with_bias(0.05, 0.02);
+        let samples: Vec<f32> = (0..n).map(|_| gyro.measure(&mut rng, rate, dt)).collect();
+        let mean = samples.iter().sum::<f32>() / n as f32;
+        let var = samples.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / n as f32;
+        assert!((mean - (1.02 * rate + 0.05)).abs() < 2e-3, "{mean}");
+        assert!(
+            (var / (0.01f32.powi(2) * IMU_RATE) - 1.0).abs() < 0.1,
+            "{var}"
+        );
+
+        // The bias wanders by its random walk density times the square root of time
+        let t = 10.0;
+        let drift: Vec<f32> = (0..200)
+            .map(|_| {
+                let mut gyro = Gyroscope::default().with_noise(0.0, 0.01);
+                (0..(t / dt) as usize).for_each(|_| {
+                    gyro.measure(&mut rng, rate, dt);
+                });
+                gyro.current_bias() - gyro.bias
+            })
+            .collect();
+        let var = drift.iter().map(|d| d * d).sum::<f32>() / drift.len() as f32;
+        assert!((var / (0.01f32.powi(2) * t) - 1.0).abs() < 0.3, "{var}");
+    }
+}
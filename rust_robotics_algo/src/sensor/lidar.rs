@@ -0,0 +1,180 @@
+use super::*;
+use crate::localization::particle_filter::randn_with;
+use crate::mapping::RayCast;
+use rand::Rng;
+
+/// Default number of beams in a scan
+pub const NUM_BEAMS: usize = 180;
+/// Default maximum range of a beam [m]
+pub const MAX_RANGE: f32 = 10.0;
+
+/// Simulated 2D lidar, casting evenly spaced beams over its field of view
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Lidar {
+    /// Number of beams in a scan
+    pub num_beams: usize,
+    /// Field of view centered on the heading [rad]
+    pub fov: f32,
+    /// Maximum range of a beam [m]
+    pub max_range: f32,
+    /// Variance of range measurement [m^2]
+    pub Q: Matrix1,
+}
+
+impl Default for Lidar {
+    fn default() -> Self {
+        Self {
+            num_beams: NUM_BEAMS,
+            fov: TAU,
+            max_range: MAX_RANGE,
+            Q: diag![0.01],
+        }
+    }
+}
+
+impl Lidar {
+    /// Cast `num_beams` beams over the field of view `fov` [rad]
+    pub fn with_beams(mut self, num_beams: usize, fov: f32) -> Self {
+        self.num_beams = num_beams;
+        self.fov = fov;
+        self
+    }
+
+    pub fn with_max_range(mut self, max_range: f32) -> Self {
+        self.max_range = max_range;
+        self
+    }
+
+    pub fn with_noise(mut self, Q: Matrix1) -> Self {
+        self.Q = Q;
+        self
+    }
+
+    /// Bearing of each beam relative to the heading [rad], counterclockwise
+    /// from `-fov / 2`
+    ///
+    /// A full circle is split into equal gaps, while a narrower field of view
+    /// has beams on both of its edges.
+    pub fn angles(&self) -> Vec<f32> {
+        let n = self.num_beams;
+        let gap = if self.fov >= TAU || n < 2 {
+            self.fov / n.max(1) as f32
+        } else {
+            self.fov / (n - 1) as f32
+        };
+        (0..n).map(|i| -self.fov / 2.0 + i as f32 * gap).collect()
+    }
+
+    /// Exact ranges of the beams from `pose` to the first hit in `map`
+    pub fn cast<M: RayCast + ?Sized>(&self, pose: &Pose2, map: &M) -> Scan {
+        let origin = pose.translation();
+        let angles = self.angles();
+        let ranges = angles
+            .iter()
+            .map(|angle| map.cast(&origin, pose.theta + angle, self.max_range))
+            .collect();
+        Scan {
+            angles,
+            ranges,
+            max_range: self.max_range,
+        }
+    }
+
+    /// Scan `map` from `pose`, adding Gaussian noise to the range of each hit
+    pub fn scan<R: Rng + ?Sized, M: RayCast + ?Sized>(
+        &self,
+        rng: &mut R,
+        pose: &Pose2,
+        map: &M,
+    ) -> Scan {
+        let mut scan = self.cast(pose, map);
+        let std = sqrt(self.Q[0]);
+        for range in scan.ranges.iter_mut().flatten() {
+            *range = (*range + randn_with(rng) * std).clamp(0.0, self.max_range);
+        }
+        scan
+    }
+}
+
+/// Ranges measured by a [`Lidar`], one per beam
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Scan {
+    /// Bearing of each beam relative to the heading [rad]
+    pub angles: Vec<f32>,
+    /// Range of each beam [m], or `None` if nothing was hit within `max_range`
+    pub ranges: Vec<Option<f32>>,
+    /// Maximum range of a beam [m]
+    pub max_range: f32,
+}
+
+impl Scan {
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Bearing and range of the beams which hit something
+    pub fn hits(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        self.angles
+            .iter()
+            .zip(self.ranges.iter())
+            .filter_map(|(angle, range)| range.map(|range| (*angle, range)))
+    }
+
+    /// Points hit by the beams, relative to the sensor
+    pub fn points(&self) -> Vec<Vector2> {
+        self.hits()
+            .map(|(angle, range)| vector![range * cos(angle), range * sin(angle)])
+            .collect()
+    }
+
+    /// Points hit by the beams, in the frame the sensor is at `pose` in
+    pub fn points_from(&self, pose: &Pose2) -> Vec<Vector2> {
+        self.points()
+            .iter()
+            .map(|p| pose.transform_point(p))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::{Polygon, World};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn scan_sees_the_walls_around() {
+        let room = Polygon::rectangle(vector![-4., -2.], vector![4., 2.]);
+        let world = World::default().with_polygon(&room);
+        let lidar = Lidar::default().with_beams(4, TAU);
+        let pose = Pose2::new(1.0, 0.0, PI / 2.);
+
+        // Beams behind, to the right, ahead and to the left of the vehicle facing +y
+        let scan = lidar.cast(&pose, &world);
+        let expected = [2.0, 3.0, 2.0, 5.0];
+        for (range, expected) in scan.ranges.iter().zip(expected) {
+            assert!((range.unwrap() - expected).abs() < 1e-4, "{scan:?}");
+        }
+        for p in scan.points_from(&pose) {
+            assert!((p.x.abs() - 4.).abs() < 1e-4 || (p.y.abs() - 2.).abs() < 1e-4);
+        }
+
+        let narrow = lidar.with_beams(3, PI / 2.).with_max_range(2.5);
+        assert_eq!(narrow.angles(), vec![-PI / 4., 0.0, PI / 4.]);
+        let scan = narrow.cast(&pose, &world);
+        assert_eq!(scan.hits().count(), 1);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let noisy = lidar.with_noise(diag![0.04]).scan(&mut rng, &pose, &world);
+        assert_ne!(noisy, lidar.cast(&pose, &world));
+        assert!(noisy
+            .ranges
+            .iter()
+            .flatten()
+            .all(|r| (0.0..=10.0).contains(r)));
+    }
+}
@@ -0,0 +1,67 @@
+//! Simulated sensors
+//!
+//! - [`gnss`]: position fixes with noise, multipath outliers and outages
+//! - [`lidar`]: 2D laser range finder, ray casting against a map
+//! - [`imu`]: gyroscope and accelerometer with white noise, bias random walk
+//!   and scale factor errors
+//! - [`odometry`]: wheel encoders, combined with the gyroscope into the
+//!   `[velocity, yaw rate]` input of the motion model
+//!
+//! Noise is given as a density, e.g. [rad/s/√Hz], so that its effect does not
+//! depend on the update rate of the sensor.
+
+use crate::prelude::*;
+
+pub mod gnss;
+pub mod imu;
+pub mod lidar;
+pub mod odometry;
+
+/// Timing of a sensor sampling at a fixed rate, slower than the simulation
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SampleClock {
+    /// Update rate [Hz]
+    pub rate: f32,
+    /// Phase of the clock within its period [s]
+    elapsed: f32,
+    /// Time since the last sample [s]
+    window: f32,
+}
+
+impl SampleClock {
+    pub fn new(rate: f32) -> Self {
+        Self {
+            rate,
+            elapsed: 0.0,
+            window: 0.0,
+        }
+    }
+
+    /// Time between samples [s]
+    pub fn period(&self) -> f32 {
+        1.0 / self.rate
+    }
+
+    /// Advance by `dt`, returning the time since the previous sample [s] if a
+    /// sample is due.
+    ///
+    /// Samples are taken on steps of `dt`, so this window differs from the
+    /// [`period`](Self::period) when the period is not a multiple of `dt`,
+    /// while the samples keep the rate on average.
+    pub fn tick(&mut self, dt: f32) -> Option<f32> {
+        self.elapsed += dt;
+        self.window += dt;
+        // Small tolerance, so that e.g. ten steps of 0.01 s make 0.1 s
+        if self.elapsed + 1e-6 >= self.period() {
+            self.elapsed = (self.elapsed - self.period()).max(0.0) % self.period();
+            Some(std::mem::take(&mut self.window))
+        } else {
+            None
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.elapsed = 0.0;
+        self.window = 0.0;
+    }
+}
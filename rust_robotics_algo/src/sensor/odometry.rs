@@ -0,0 +1,273 @@
+use super::imu::{Accelerometer, Gyroscope};
+use super::*;
+use crate::localization::particle_filter::randn_with;
+use rand::Rng;
+
+/// Default update rate of the wheel encoders [Hz]
+pub const ENCODER_RATE: f32 = 50.0;
+
+/// Simulated incremental encoders on the left and right wheels of a
+/// differential drive, measuring its velocity and yaw rate from tick counts
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WheelEncoders {
+    pub ticks_per_revolution: u32,
+    /// Nominal radius of the wheels [m]
+    pub wheel_radius: f32,
+    /// Distance between the wheels [m]
+    pub track_width: f32,
+    /// Relative error of the nominal radius of the left and right wheel, e.g.
+    /// from tire wear, which makes the yaw rate drift
+    pub radius_error: Vector2,
+    /// Density of the slip of each wheel over the ground [m/s/√Hz]
+    pub slip_density: f32,
+    pub clock: SampleClock,
+    /// True angle the left and right wheels have turned by [rad]
+    angles: Vector2,
+    /// Ticks counted on the left and right wheel at the last sample
+    ticks: [i64; 2],
+    /// Latest sample `[velocity, yaw rate]`
+    last: Vector2,
+}
+
+impl Default for WheelEncoders {
+    fn default() -> Self {
+        Self {
+            ticks_per_revolution: 1024,
+            wheel_radius: 0.3,
+            track_width: 1.5,
+            radius_error: vector![0.005, -0.005],
+            slip_density: 0.02,
+            clock: SampleClock::new(ENCODER_RATE),
+            angles: Vector2::zeros(),
+            ticks: [0, 0],
+            last: Vector2::zeros(),
+        }
+    }
+}
+
+impl WheelEncoders {
+    pub fn with_geometry(mut self, wheel_radius: f32, track_width: f32) -> Self {
+        self.wheel_radius = wheel_radius;
+        self.track_width = track_width;
+        self
+    }
+
+    pub fn with_resolution(mut self, ticks_per_revolution: u32) -> Self {
+        self.ticks_per_revolution = ticks_per_revolution;
+        self
+    }
+
+    pub fn with_errors(mut self, radius_error: Vector2, slip_density: f32) -> Self {
+        self.radius_error = radius_error;
+        self.slip_density = slip_density;
+        self
+    }
+
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.clock.rate = rate;
+        self
+    }
+
+    /// Advance by `dt` at the true input `u = [velocity, yaw rate]`, returning
+    /// the latest `[velocity, yaw rate]` from the ticks counted since the
+    /// previous sample, held between samples
+    pub fn measure<R: Rng + ?Sized>(&mut self, rng: &mut R, u: Vector2, dt: f32) -> Vector2 {
+        let half_track = self.track_width / 2.0;
+        let speeds = vector![u[0] - u[1] * half_track, u[0] + u[1] * half_track];
+        let slip = vector![randn_with(rng), randn_with(rng)] * (self.slip_density / sqrt(dt));
+        let radii = (vector![1.0, 1.0] + self.radius_error) * self.wheel_radius;
+        self.angles += (speeds + slip).component_div(&radii) * dt;
+
+        if let Some(window) = self.clock.tick(dt) {
+            let tick = TAU / self.ticks_per_revolution as f32;
+            let ticks = [
+                (self.angles[0] / tick).floor() as i64,
+                (self.angles[1] / tick).floor() as i64,
+            ];
+            // Wheel speeds as seen with the nominal radius
+            let scale = tick * self.wheel_radius / window;
+            let left = (ticks[0] - self.ticks[0]) as f32 * scale;
+            let right = (ticks[1] - self.ticks[1]) as f32 * scale;
+            self.ticks = ticks;
+            self.last = vector![(left + right) / 2.0, (right - left) / self.track_width];
+        }
+        self.last
+    }
+
+    /// Go back to zero ticks, with no sample yet
+    pub fn reset(&mut self) {
+        self.angles = Vector2::zeros();
+        self.ticks = [0, 0];
+        self.last = Vector2::zeros();
+        self.clock.reset();
+    }
+}
+
+/// Dead reckoning input `[velocity, yaw rate]` measured by the wheel encoders
+/// and, unless disabled, the gyroscope
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Odometry {
+    pub encoders: WheelEncoders,
+    pub gyro: Gyroscope,
+    pub accelerometer: Accelerometer,
+    /// Whether the yaw rate is taken from the gyroscope rather than the
+    /// difference of the wheel speeds
+    pub use_gyro: bool,
+    /// True velocity at the previous measurement [m/s], if any
+    velocity: Option<f32>,
+    /// Latest accelerometer sample [m/s²]
+    accel: Vector2,
+}
+
+impl Default for Odometry {
+    fn default() -> Self {
+        Self {
+            encoders: WheelEncoders::default(),
+            gyro: Gyroscope::default(),
+            accelerometer: Accelerometer::default(),
+            use_gyro: true,
+            velocity: None,
+            accel: Vector2::zeros(),
+        }
+    }
+}
+
+impl Odometry {
+    pub fn with_encoders(mut self, encoders: WheelEncoders) -> Self {
+        self.encoders = encoders;
+        self
+    }
+
+    pub fn with_gyro(mut self, gyro: Gyroscope) -> Self {
+        self.gyro = gyro;
+        self
+    }
+
+    pub fn with_accelerometer(mut self, accelerometer: Accelerometer) -> Self {
+        self.accelerometer = accelerometer;
+        self
+    }
+
+    /// Advance all sensors by `dt` at the true input `u = [velocity, yaw rate]`,
+    /// returning the measured input
+    pub fn measure<R: Rng + ?Sized>(&mut self, rng: &mut R, u: Vector2, dt: f32) -> Vector2 {
+        let wheels = self.encoders.measure(rng, u, dt);
+        let yaw_rate = self.gyro.measure(rng, u[1], dt);
+        // Tangential and centripetal acceleration of the vehicle, taking the
+        // first measurement to be at constant velocity
+        let velocity = self.velocity.unwrap_or(u[0]);
+        let accel = vector![(u[0] - velocity) / dt, u[0] * u[1]];
+        self.accel = self.accelerometer.measure(rng, accel, dt);
+        self.velocity = Some(u[0]);
+        if self.use_gyro {
+            vector![wheels[0], yaw_rate]
+        } else {
+            wheels
+        }
+    }
+
+    /// Latest accelerometer sample `[along, across]` the heading [m/s²]
+    ///
+    /// This is not part of the measured input: the velocity comes from the
+    /// encoders, whose error stays bounded, while integrating the accelerometer
+    /// would make it drift with the bias. The sample is there for filters that
+    /// estimate the velocity themselves or detect wheel slip.
+    pub fn acceleration(&self) -> Vector2 {
+        self.accel
+    }
+
+    /// Reset all sensors to their state at start
+    pub fn reset(&mut self) {
+        self.encoders.reset();
+        self.gyro.reset();
+        self.accelerometer.reset();
+        self.velocity = None;
+        self.accel = Vector2::zeros();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localization::particle_filter::{calc_input, motion_model};
+    use crate::localization::StateVector;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn encoders_measure_input_and_drift() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (u, dt) = (calc_input(), 0.01);
+
+        // Ideal wheels only quantize the speed to whole ticks per sample
+        let mut encoders = WheelEncoders::default().with_errors(Vector2::zeros(), 0.0);
+        let tick_speed = TAU / 1024.0 * 0.3 * ENCODER_RATE;
+        for step in 1..=100 {
+            let ud = encoders.measure(&mut rng, u, dt);
+            // No sample before the first period of 0.02 s
+            if step == 1 {
+                assert_eq!(ud, Vector2::zeros());
+            } else {
+                assert!((ud[0] - u[0]).abs() <= tick_speed, "{ud}");
+            }
+        }
+
+        // At a rate that is not a multiple of the step, the ticks are counted
+        // over windows of 0.03 or 0.04 s instead of the period of 1/30 s
+        let mut encoders = WheelEncoders::default()
+            .with_errors(Vector2::zeros(), 0.0)
+            .with_rate(30.0);
+        let tick_speed = TAU / 1024.0 * 0.3 / 0.03;
+        for step in 1..=100 {
+            let ud = encoders.measure(&mut rng, u, dt);
+            if step > 3 {
+                assert!((ud[0] - u[0]).abs() <= tick_speed, "{ud}");
+            }
+        }
+
+        // A larger right wheel turns slower, so the measured yaw rate is lower
+        // and dead reckoning curves away from the truth
+        let mut odometry = Odometry::default()
+            .with_encoders(WheelEncoders::default().with_errors(vector![0.0, 0.02], 0.0));
+        odometry.use_gyro = false;
+        let (mut x_true, mut x_dr) = (Vector4::zeros(), Vector4::zeros());
+        let mut yaw_rate = 0.0;
+        for _ in 0..1000 {
+            let ud = odometry.measure(&mut rng, u, dt);
+            yaw_rate += ud[1] / 1000.0;
+            x_true = motion_model(x_true, u, dt);
+            x_dr = motion_model(x_dr, ud, dt);
+        }
+        assert!(yaw_rate < u[1] - 0.005, "{yaw_rate}");
+        assert!(x_dr.phi() < x_true.phi() - 0.05);
+
+        // The gyroscope measures the yaw rate instead, up to its bias, and the
+        // accelerometer the centripetal acceleration
+        odometry.use_gyro = true;
+        let (mut yaw_rate, mut accel) = (0.0, Vector2::zeros());
+        for _ in 0..100 {
+            yaw_rate += odometry.measure(&mut rng, u, dt)[1] / 100.0;
+            accel += odometry.acceleration() / 100.0;
+        }
+        assert!((yaw_rate - u[1]).abs() < 0.01, "{yaw_rate}");
+        assert!((accel[1] - u[0] * u[1]).abs() < 0.03, "{accel}");
+    }
+
+    #[test]
+    fn no_acceleration_spike_at_start() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (u, dt) = (calc_input(), 0.01);
+        let accelerometer = Accelerometer::default()
+            .with_noise(0.0, 0.0)
+            .with_bias(Vector2::zeros(), 0.0);
+        let mut odometry = Odometry::default().with_accelerometer(accelerometer);
+        // Both from the start and after a reset, at constant speed
+        for _ in 0..2 {
+            for _ in 0..10 {
+                odometry.measure(&mut rng, u, dt);
+                let accel = odometry.acceleration();
+                assert!(accel[0].abs() < 1e-4, "{accel}");
+            }
+            odometry.reset();
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use super::*;
+use crate::localization::association::{Association, Predictions};
+use crate::localization::ekf::jacob_g;
+use crate::localization::range_bearing::{
+    jacob_landmark_position, jacob_range_bearing, landmark_position, range_bearing,
+};
+use crate::pose::{angle_diff, normalize_angle};
+
+use nalgebra::{DMatrix, DVector};
+
+/// Number of pose states [x, y, yaw]
+const NP: usize = 3;
+/// Number of states per landmark [x, y]
+const NL: usize = 2;
+
+/// EKF-SLAM with range-bearing observations of unknown landmarks
+///
+/// The state vector is `[x, y, yaw, l1x, l1y, l2x, l2y, ...]`, and grows with
+/// each observation that cannot be associated with a known landmark.
+///
+/// Jacobians are evaluated at the first estimates of the pose and landmarks
+/// (FEJ-EKF, Huang et al., 2008). Relinearising at the updated estimates
+/// instead lets the filter gain spurious information about the global
+/// heading, which turns it overconfident once the heading is uncertain, and
+/// then re-observed landmarks fall out of the gate and are added again.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EkfSlam {
+    /// Estimated pose and landmark positions
+    pub x_est: DVector<f32>,
+    /// Estimated covariance
+    pub P: DMatrix<f32>,
+    /// Covariance of [range, bearing] measurement
+    pub Q: Matrix2,
+    /// Covariance of input [velocity, yaw rate]
+    pub R: Matrix2,
+    /// Association of observations with known landmarks
+    pub association: Association,
+    /// Squared Mahalanobis distance to the nearest known landmark, beyond which
+    /// an unassociated observation is added as a new landmark. Closer ones are
+    /// ambiguous and discarded.
+    pub new_landmark_distance: f32,
+    /// Predicted pose [x, y, yaw] of the current step, before any correction
+    pub pose_prior: Vector3,
+    /// Position of each landmark when it was added
+    pub landmarks_first: Vec<Vector2>,
+}
+
+impl Default for EkfSlam {
+    fn default() -> Self {
+        Self {
+            x_est: DVector::zeros(NP),
+            P: DMatrix::zeros(NP, NP),
+            Q: diag![0.1_f32.powi(2), (2_f32).to_radians().powi(2)],
+            R: diag![1.0, (30_f32).to_radians()],
+            association: Association::default(),
+            new_landmark_distance: 100.0,
+            pose_prior: Vector3::zeros(),
+            landmarks_first: Vec::new(),
+        }
+    }
+}
+
+impl EkfSlam {
+    pub fn with_noise(mut self, Q: Matrix2, R: Matrix2) -> Self {
+        self.Q = Q;
+        self.R = R;
+        self
+    }
+
+    pub fn with_association(mut self, association: Association) -> Self {
+        self.association = association;
+        self
+    }
+
+    pub fn with_new_landmark_distance(mut self, distance: f32) -> Self {
+        self.new_landmark_distance = distance;
+        self
+    }
+
+    /// Estimated pose [x, y, yaw]
+    pub fn pose(&self) -> Vector3 {
+        self.x_est.fixed_rows::<NP>(0).into_owned()
+    }
+
+    /// Estimated pose as a [`VehicleState`], with zero velocity
+    pub fn state(&self) -> VehicleState {
+        VehicleState::new(self.x_est[0], self.x_est[1], self.x_est[2], 0.)
+    }
+
+    /// Estimated covariance of the pose
+    pub fn pose_covariance(&self) -> Matrix3 {
+        self.P.fixed_slice::<NP, NP>(0, 0).into_owned()
+    }
+
+    pub fn num_landmarks(&self) -> usize {
+        (self.x_est.len() - NP) / NL
+    }
+
+    /// Estimated position of the `i`th landmark
+    pub fn landmark(&self, i: usize) -> Vector2 {
+        self.x_est.fixed_rows::<NL>(NP + NL * i).into_owned()
+    }
+
+    /// Estimated covariance of the `i`th landmark
+    pub fn landmark_covariance(&self, i: usize) -> Matrix2 {
+        let j = NP + NL * i;
+        self.P.fixed_slice::<NL, NL>(j, j).into_owned()
+    }
+
+    /// Propagate the pose through [`VehicleState::predict`] with input `u`
+    pub fn predict(&mut self, u: Vector2, dt: f32) {
+        let x = self.state();
+        let G = jacob_g(&x, dt).fixed_rows::<NP>(0).into_owned();
+
+        let x = x.predict(u, dt);
+        // Jacobian of the motion, with the displacement taken from the previous
+        // predicted pose rather than from the corrected one
+        let d = x.position() - self.pose_prior.xy();
+        let F = matrix![1., 0., -d.y;
+                        0., 1., d.x;
+                        0., 0., 1.];
+        self.pose_prior = vector![x.x, x.y, x.phi];
+        self.x_est
+            .fixed_rows_mut::<NP>(0)
+            .copy_from(&self.pose_prior);
+
+        let n = self.x_est.len();
+        let P_rr =
+            F * self.P.fixed_slice::<NP, NP>(0, 0) * F.transpose() + G * self.R * G.transpose();
+        let P_rl = F * self.P.slice((0, NP), (NP, n - NP));
+        self.P.fixed_slice_mut::<NP, NP>(0, 0).copy_from(&P_rr);
+        self.P.slice_mut((0, NP), (NP, n - NP)).copy_from(&P_rl);
+        self.P
+            .slice_mut((NP, 0), (n - NP, NP))
+            .copy_from(&P_rl.transpose());
+    }
+
+    /// Correct the estimate with range-bearing observations `z`, adding
+    /// observations that can't be associated and are far from every known
+    /// landmark as new landmarks.
+    ///
+    /// Returns the landmark index of each observation, if it was used.
+    pub fn correct(&mut self, z: &[Vector2]) -> Vec<Option<usize>> {
+        let predictions = self.predictions();
+        let pairing = self.association.associate(z, &predictions);
+
+        z.iter()
+            .zip(pairing.iter())
+            .map(|(zi, j)| match *j {
+                Some(j) => {
+                    self.update_landmark(zi, j);
+                    Some(j)
+                }
+                None => {
+                    let nearest = (0..predictions.len())
+                        .map(|j| predictions.mahalanobis(zi, j))
+                        .fold(f32::INFINITY, f32::min);
+                    (nearest > self.new_landmark_distance).then(|| self.add_landmark(zi))
+                }
+            })
+            .collect()
+    }
+
+    /// Predict with input `u` and correct with observations `z`.
+    ///
+    /// Returns the landmark index of each observation, if it was used.
+    pub fn update(&mut self, z: &[Vector2], u: Vector2, dt: f32) -> Vec<Option<usize>> {
+        self.predict(u, dt);
+        self.correct(z)
+    }
+
+    fn predictions(&self) -> Predictions {
+        let x = self.state();
+        Predictions {
+            z: (0..self.num_landmarks())
+                .map(|i| range_bearing(&x, &self.landmark(i)))
+                .collect(),
+            H: (0..self.num_landmarks()).map(|i| self.jacob_h(i)).collect(),
+            P: self.P.clone(),
+            Q: self.Q,
+        }
+    }
+
+    /// Jacobian of the observation of the `i`th landmark with respect to the
+    /// state, at the predicted pose and the first estimate of the landmark
+    fn jacob_h(&self, i: usize) -> DMatrix<f32> {
+        let x = self.pose_prior.insert_row(NP, 0.);
+        let (H_pose, H_landmark) = jacob_range_bearing(&x, &self.landmarks_first[i]);
+        let mut H = DMatrix::zeros(2, self.x_est.len());
+        H.fixed_slice_mut::<2, NP>(0, 0).copy_from(&H_pose);
+        H.fixed_slice_mut::<2, NL>(0, NP + NL * i)
+            .copy_from(&H_landmark);
+        H
+    }
+
+    fn update_landmark(&mut self, z: &Vector2, i: usize) {
+        let H = self.jacob_h(i);
+        let z_hat = range_bearing(&self.state(), &self.landmark(i));
+        let nu = vector![z[0] - z_hat[0], angle_diff(z[1], z_hat[1])];
+
+        let Q = DMatrix::from_iterator(2, 2, self.Q.iter().copied());
+        let S = &H * &self.P * H.transpose() + &Q;
+        if let Some(S_inv) = S.try_inverse() {
+            let K = &self.P * H.transpose() * S_inv;
+            self.x_est += &K * DVector::from_column_slice(nu.as_slice());
+            self.x_est[2] = normalize_angle(self.x_est[2]);
+
+            // Joseph form, which keeps the growing covariance symmetric and
+            // positive semi-definite in single precision
+            let n = self.x_est.len();
+            let I_KH = DMatrix::identity(n, n) - &K * &H;
+            self.P = &I_KH * &self.P * I_KH.transpose() + &K * Q * K.transpose();
+        }
+    }
+
+    /// Augment the state with the landmark observed at `z`, returning its index
+    fn add_landmark(&mut self, z: &Vector2) -> usize {
+        let x = self.state();
+        let landmark = landmark_position(&x, z);
+        let (G_pose, G_z) = jacob_landmark_position(&x, z);
+
+        let n = self.x_est.len();
+        let P_pose = self.P.rows(0, NP).into_owned();
+        let P_lx = G_pose * P_pose;
+        let P_ll =
+            G_pose * self.pose_covariance() * G_pose.transpose() + G_z * self.Q * G_z.transpose();
+
+        let mut P = DMatrix::zeros(n + NL, n + NL);
+        P.slice_mut((0, 0), (n, n)).copy_from(&self.P);
+        P.slice_mut((n, 0), (NL, n)).copy_from(&P_lx);
+        P.slice_mut((0, n), (n, NL)).copy_from(&P_lx.transpose());
+        P.fixed_slice_mut::<NL, NL>(n, n).copy_from(&P_ll);
+        self.P = P;
+
+        self.x_est = self.x_est.clone().insert_rows(n, NL, 0.);
+        self.x_est.fixed_rows_mut::<NL>(n).copy_from(&landmark);
+        self.landmarks_first.push(landmark);
+        self.num_landmarks() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localization::particle_filter::calc_input;
+    use crate::localization::range_bearing::RangeBearingSensor;
+    use crate::localization::StateVector;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn map_converges_to_landmarks() {
+        let landmarks = [
+            vector![10.0_f32, -2.0_f32],
+            vector![15.0, 10.0],
+            vector![3.0, 15.0],
+            vector![-5.0, 20.0],
+        ];
+        let sensor = RangeBearingSensor::default();
+        let (seeds, steps, dt) = (8, 500, 0.1);
+        let mut nees = 0.0;
+        for seed in 0..seeds {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut slam = EkfSlam::default().with_association(Association::JCBB(0.99));
+            let (mut x_true, mut x_dr) = (Vector4::zeros(), Vector4::zeros());
+            for _ in 0..steps {
+                let (z, ud) = sensor.observe(
+                    &mut rng,
+                    &mut x_true,
+                    &mut x_dr,
+                    calc_input(),
+                    &landmarks,
+                    dt,
+                );
+                slam.update(&z, ud, dt);
+
+                let mut e = slam.pose() - x_true.xyz();
+                e[2] = angle_diff(slam.pose()[2], x_true.phi());
+                // The first steps leave the covariance singular
+                if let Some(P_inv) = slam.pose_covariance().try_inverse() {
+                    nees += (e.transpose() * P_inv * e)[0];
+                }
+            }
+
+            assert_eq!(slam.num_landmarks(), landmarks.len(), "Seed {seed}");
+            let pose_err = hypot(slam.pose()[0] - x_true.x(), slam.pose()[1] - x_true.y());
+            assert!(pose_err < 1.5, "Seed {seed}, pose error: {pose_err}");
+            // The map drifts and turns together with the pose, so match the
+            // landmarks as seen from the vehicle, and compare the distances
+            // between them
+            let (pose_est, pose) = (Pose2::from(slam.pose()), VehicleState::from(x_true).pose());
+            let matched: Vec<Vector2> = landmarks
+                .iter()
+                .map(|landmark| {
+                    let dist = |l: &Vector2| {
+                        (pose_est.inverse_transform_point(l)
+                            - pose.inverse_transform_point(landmark))
+                        .norm()
+                    };
+                    (0..slam.num_landmarks())
+                        .map(|i| slam.landmark(i))
+                        .min_by(|a, b| dist(a).total_cmp(&dist(b)))
+                        .unwrap()
+                })
+                .collect();
+            for i in 0..landmarks.len() {
+                for j in 0..i {
+                    let err =
+                        (matched[i] - matched[j]).norm() - (landmarks[i] - landmarks[j]).norm();
+                    assert!(
+                        err.abs() < 0.5,
+                        "Seed {seed}, landmark distance error: {err}"
+                    );
+                }
+            }
+        }
+
+        // A consistent filter has a mean NEES of 3, the pose dimension, and
+        // less here as the input noise is uniform within its variance
+        let nees = nees / (seeds * steps) as f32;
+        assert!(nees < 3.0, "Mean pose NEES: {nees}");
+    }
+}
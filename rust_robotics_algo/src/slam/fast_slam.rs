@@ -0,0 +1,492 @@
+use super::*;
+use crate::localization::ekf::jacob_g;
+use crate::localization::mean_state;
+use crate::localization::particle_filter::{
+    effective_particles, particle_covariance, randn_with, Diagnostics, ResampleTrigger, Resampling,
+    NP, PW,
+};
+use crate::localization::range_bearing::{
+    jacob_landmark_position, jacob_range_bearing, landmark_position, range_bearing,
+};
+use crate::pose::normalize_angle;
+use rand::Rng;
+
+/// Gaussian estimate of a landmark position, carried by each particle
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Landmark {
+    /// Estimated position
+    pub x: Vector2,
+    /// Estimated covariance
+    pub P: Matrix2,
+}
+
+/// Proposal distribution of the particle poses
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Version {
+    /// FastSLAM 1.0, sampling poses from the motion model.
+    ///
+    /// When the input noise is large next to the measurement noise, as in the
+    /// default configuration, few samples match the observations. The
+    /// effective number of particles then drops to a handful on every step,
+    /// and the particles, which share ever fewer ancestors, underestimate the
+    /// covariance.
+    FastSlam1,
+    /// FastSLAM 2.0, sampling poses from the motion model corrected by the
+    /// observations
+    #[default]
+    FastSlam2,
+}
+
+impl Version {
+    pub const ALL: [Self; 2] = [Self::FastSlam1, Self::FastSlam2];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::FastSlam1 => "FastSLAM 1.0",
+            Self::FastSlam2 => "FastSLAM 2.0",
+        }
+    }
+}
+
+/// FastSLAM with range-bearing observations of unknown landmarks
+///
+/// Each particle carries a pose along with its own map, in which every
+/// landmark is estimated by an independent 2x2 EKF. Observations are paired
+/// with the most likely landmark of each particle's map, or added as a new
+/// landmark when they are far from all of them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FastSlam {
+    /// Estimated state
+    pub x_est: VehicleState,
+    /// Estimated covariance of [x, y, yaw]
+    pub p_est: Matrix3,
+    /// Particle states
+    pub px: Vec<VehicleState>,
+    /// Particle weights
+    pub pw: PW,
+    /// Map of each particle
+    pub maps: Vec<Vec<Landmark>>,
+    /// Covariance of [range, bearing] measurement
+    pub Q: Matrix2,
+    /// Covariance of input [velocity, yaw rate] used to spread particles
+    pub R: Matrix2,
+    /// Proposal distribution of the particle poses
+    pub version: Version,
+    /// Squared Mahalanobis distance to the nearest landmark of a particle's
+    /// map, beyond which an observation is added as a new landmark
+    pub new_landmark_distance: f32,
+    /// Resampling algorithm
+    pub resampling: Resampling,
+    /// When to resample
+    pub trigger: ResampleTrigger,
+    /// Diagnostics of the latest update
+    pub diagnostics: Diagnostics,
+    /// Index of the particle each particle was drawn from in the latest update
+    pub ancestors: Vec<usize>,
+    /// Index of the particle with the highest weight
+    best: usize,
+    /// Number of updates since the last resampling
+    steps: usize,
+}
+
+impl Default for FastSlam {
+    fn default() -> Self {
+        Self::new(NP)
+    }
+}
+
+impl FastSlam {
+    /// Instantiate with `np` particles at the origin, with empty maps
+    pub fn new(np: usize) -> Self {
+        Self {
+            x_est: VehicleState::default(),
+            p_est: Matrix3::zeros(),
+            px: vec![VehicleState::default(); np],
+            pw: PW::from_element(np, 1. / np as f32),
+            maps: vec![Vec::new(); np],
+            Q: diag![0.1_f32.powi(2), (2_f32).to_radians().powi(2)],
+            R: diag![1.0, (30_f32).to_radians()],
+            version: Version::default(),
+            new_landmark_distance: 100.0,
+            resampling: Resampling::default(),
+            trigger: ResampleTrigger::default(),
+            diagnostics: Diagnostics::default(),
+            ancestors: (0..np).collect(),
+            best: 0,
+            steps: 0,
+        }
+    }
+
+    pub fn with_particles(mut self, np: usize) -> Self {
+        self.set_num_particles(np);
+        self
+    }
+
+    pub fn with_noise(mut self, Q: Matrix2, R: Matrix2) -> Self {
+        self.Q = Q;
+        self.R = R;
+        self
+    }
+
+    pub fn with_version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn with_new_landmark_distance(mut self, distance: f32) -> Self {
+        self.new_landmark_distance = distance;
+        self
+    }
+
+    pub fn with_resampling(mut self, resampling: Resampling) -> Self {
+        self.resampling = resampling;
+        self
+    }
+
+    pub fn with_trigger(mut self, trigger: ResampleTrigger) -> Self {
+        self.trigger = trigger;
+        self
+    }
+
+    pub fn num_particles(&self) -> usize {
+        self.pw.len()
+    }
+
+    /// Re-initialize `np` particles with equal weights and empty maps at the
+    /// current estimate
+    pub fn set_num_particles(&mut self, np: usize) {
+        self.px = vec![self.x_est; np];
+        self.pw = PW::from_element(np, 1. / np as f32);
+        self.maps = vec![Vec::new(); np];
+        self.ancestors = (0..np).collect();
+        self.best = 0;
+    }
+
+    /// Index of the particle with the highest weight in the latest update
+    pub fn best(&self) -> usize {
+        self.best
+    }
+
+    /// Map of the particle with the highest weight
+    pub fn best_map(&self) -> &[Landmark] {
+        &self.maps[self.best]
+    }
+
+    /// Draw a new set of equally weighted particles with [`Self::resampling`],
+    /// along with their maps
+    pub fn resample<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let ind = self.resampling.indices(rng, self.pw.as_slice());
+        self.px = ind.iter().map(|&i| self.px[i]).collect();
+        self.pw = PW::from_element(ind.len(), 1. / ind.len() as f32);
+        self.maps = ind.iter().map(|&i| self.maps[i].clone()).collect();
+        self.best = ind.iter().position(|&i| i == self.best).unwrap_or(0);
+        self.ancestors = ind;
+        self.steps = 0;
+    }
+
+    /// Propagate particles with input `u`, then weight them and update their
+    /// maps with observations `z`.
+    ///
+    /// Returns the estimated covariance of [x, y, yaw].
+    pub fn update<R: Rng + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        z: &[Vector2],
+        u: Vector2,
+        dt: f32,
+    ) -> Matrix3 {
+        // Weights are accumulated as logarithms, as the likelihood of several
+        // observations can underflow in single precision
+        let log_w: Vec<f32> = (0..self.num_particles())
+            .map(|ip| {
+                let x = self.px[ip];
+                let (x, log_l) = match self.version {
+                    Version::FastSlam1 => self.sample_motion(rng, x, u, dt),
+                    Version::FastSlam2 => self.sample_proposal(rng, ip, x, z, u, dt),
+                };
+                self.px[ip] = x;
+                // Weights which underflowed to zero stay finite in the log domain
+                self.pw[ip].max(f32::MIN_POSITIVE).ln() + log_l + self.update_map(ip, &x, z)
+            })
+            .collect();
+
+        let max = log_w.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        self.pw = if max.is_finite() {
+            let pw = PW::from_iterator(log_w.len(), log_w.iter().map(|w| exp(w - max)));
+            &pw / pw.sum()
+        } else {
+            // No particle explains the observations, so none is preferred
+            PW::from_element(log_w.len(), 1. / log_w.len() as f32)
+        };
+        self.best = self
+            .pw
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map_or(0, |(i, _)| i);
+        self.ancestors = (0..self.num_particles()).collect();
+
+        self.x_est = mean_state(self.px.iter().copied(), self.pw.as_slice());
+        self.p_est = particle_covariance(&self.x_est, self.px.iter().copied(), &self.pw);
+
+        self.steps += 1;
+        let n_eff = effective_particles(self.pw.as_slice());
+        let resampled = self
+            .trigger
+            .is_triggered(n_eff, self.num_particles(), self.steps);
+        if resampled {
+            self.resample(rng);
+        }
+        self.diagnostics = Diagnostics {
+            n_eff,
+            resampled,
+            num_particles: self.num_particles(),
+        };
+
+        self.p_est
+    }
+
+    /// Sample the next state from [`VehicleState::predict`] with noisy input (FastSLAM 1.0)
+    fn sample_motion<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        x: VehicleState,
+        u: Vector2,
+        dt: f32,
+    ) -> (VehicleState, f32) {
+        let ud = vector![
+            u[0] + randn_with(rng) * sqrt(self.R[(0, 0)]),
+            u[1] + randn_with(rng) * sqrt(self.R[(1, 1)])
+        ];
+        (x.predict(ud, dt), 0.)
+    }
+
+    /// Sample the next state from the motion model corrected by the
+    /// observations of known landmarks (FastSLAM 2.0).
+    ///
+    /// Returns the sampled state, and the log-likelihood of the observations
+    /// given the predicted pose distribution.
+    fn sample_proposal<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        ip: usize,
+        x: VehicleState,
+        z: &[Vector2],
+        u: Vector2,
+        dt: f32,
+    ) -> (VehicleState, f32) {
+        let G = jacob_g(&x, dt).fixed_rows::<3>(0).into_owned();
+        let mut x = x.predict(u, dt);
+        let mut P = G * self.R * G.transpose();
+
+        let mut log_l = 0.;
+        let mut used = vec![false; self.maps[ip].len()];
+        for zi in z.iter() {
+            if let Some((j, _)) = self.associate(ip, &x, zi, &used) {
+                used[j] = true;
+                let landmark = &self.maps[ip][j];
+                let (H_pose, H_landmark) = jacob_range_bearing(&x, &landmark.x);
+                let nu = innovation(&x, landmark, zi);
+                let S = H_pose * P * H_pose.transpose()
+                    + H_landmark * landmark.P * H_landmark.transpose()
+                    + self.Q;
+                if let Some(S_inv) = S.try_inverse() {
+                    let K = P * H_pose.transpose() * S_inv;
+                    x += (K * nu).insert_row(3, 0.);
+                    P = (Matrix3::identity() - K * H_pose) * P;
+                    log_l += log_gauss(&nu, &S);
+                }
+            }
+        }
+
+        x += sample_gaussian(rng, &P).insert_row(3, 0.);
+        (x, log_l)
+    }
+
+    /// Update the map of particle `ip` at state `x` with observations `z`.
+    ///
+    /// Returns the log-likelihood of the observations.
+    fn update_map(&mut self, ip: usize, x: &VehicleState, z: &[Vector2]) -> f32 {
+        let log_new = -0.5 * self.new_landmark_distance
+            - (TAU).ln()
+            - 0.5 * self.Q.determinant().max(f32::MIN_POSITIVE).ln();
+
+        let mut log_l = 0.;
+        let mut used = vec![false; self.maps[ip].len()];
+        for zi in z.iter() {
+            match self.associate(ip, x, zi, &used) {
+                Some((j, _)) => {
+                    used[j] = true;
+                    let landmark = &mut self.maps[ip][j];
+                    let (_, H) = jacob_range_bearing(x, &landmark.x);
+                    let nu = innovation(x, landmark, zi);
+                    let S = H * landmark.P * H.transpose() + self.Q;
+                    if let Some(S_inv) = S.try_inverse() {
+                        let K = landmark.P * H.transpose() * S_inv;
+                        landmark.x += K * nu;
+                        let I_KH = Matrix2::identity() - K * H;
+                        landmark.P =
+                            I_KH * landmark.P * I_KH.transpose() + K * self.Q * K.transpose();
+                        if self.version == Version::FastSlam1 {
+                            log_l += log_gauss(&nu, &S);
+                        }
+                    }
+                }
+                None => {
+                    let (_, G_z) = jacob_landmark_position(x, zi);
+                    self.maps[ip].push(Landmark {
+                        x: landmark_position(x, zi),
+                        P: G_z * self.Q * G_z.transpose(),
+                    });
+                    used.push(true);
+                    log_l += log_new;
+                }
+            }
+        }
+        log_l
+    }
+
+    /// Landmark of particle `ip`, among those not `used` yet, that is nearest
+    /// to observation `z` in squared Mahalanobis distance, if within
+    /// [`Self::new_landmark_distance`]
+    fn associate(
+        &self,
+        ip: usize,
+        x: &VehicleState,
+        z: &Vector2,
+        used: &[bool],
+    ) -> Option<(usize, f32)> {
+        self.maps[ip]
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| !used[*j])
+            .filter_map(|(j, landmark)| {
+                let (_, H) = jacob_range_bearing(x, &landmark.x);
+                let S = H * landmark.P * H.transpose() + self.Q;
+                let nu = innovation(x, landmark, z);
+                S.try_inverse()
+                    .map(|S_inv| (j, (nu.transpose() * S_inv * nu)[0]))
+            })
+            .filter(|(_, d)| *d <= self.new_landmark_distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+/// Innovation of observation `z` against `landmark` from state `x`, with wrapped bearing
+fn innovation(x: &VehicleState, landmark: &Landmark, z: &Vector2) -> Vector2 {
+    let dz = z - range_bearing(x, &landmark.x);
+    vector![dz[0], normalize_angle(dz[1])]
+}
+
+/// Log-likelihood of innovation `nu` with covariance `S`
+fn log_gauss(nu: &Vector2, S: &Matrix2) -> f32 {
+    match S.try_inverse() {
+        Some(S_inv) => {
+            -0.5 * (nu.transpose() * S_inv * nu)[0]
+                - (TAU).ln()
+                - 0.5 * S.determinant().max(f32::MIN_POSITIVE).ln()
+        }
+        None => f32::NEG_INFINITY,
+    }
+}
+
+/// Sample from a zero-mean Gaussian with positive semi-definite covariance `P`
+fn sample_gaussian<R: Rng + ?Sized>(rng: &mut R, P: &Matrix3) -> Vector3 {
+    let eig = ((P + P.transpose()) * 0.5).symmetric_eigen();
+    let n = vector![randn_with(rng), randn_with(rng), randn_with(rng)];
+    eig.eigenvectors * eig.eigenvalues.map(|e| sqrt(e.max(0.))).component_mul(&n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localization::particle_filter::calc_input;
+    use crate::localization::range_bearing::RangeBearingSensor;
+    use crate::localization::StateVector;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn best_map_converges_to_landmarks() {
+        let landmarks = [
+            vector![10.0_f32, -2.0_f32],
+            vector![15.0, 10.0],
+            vector![3.0, 15.0],
+            vector![-5.0, 20.0],
+        ];
+        let sensor = RangeBearingSensor::default();
+        let (seeds, steps, dt) = (4, 300, 0.1);
+        for version in Version::ALL {
+            let name = version.name();
+            let (mut pose_err, mut dr_err) = (0.0, 0.0);
+            for seed in 0..seeds {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let mut slam = FastSlam::default().with_version(version);
+                let (mut x_true, mut x_dr) = (Vector4::zeros(), Vector4::zeros());
+                for _ in 0..steps {
+                    let (z, ud) = sensor.observe(
+                        &mut rng,
+                        &mut x_true,
+                        &mut x_dr,
+                        calc_input(),
+                        &landmarks,
+                        dt,
+                    );
+                    slam.update(&mut rng, &z, ud, dt);
+                }
+
+                let map = slam.best_map();
+                assert_eq!(map.len(), landmarks.len(), "{name}, seed {seed}");
+                let err = hypot(slam.x_est.x() - x_true.x(), slam.x_est.y() - x_true.y());
+                assert!(err < 4.0, "{name}, seed {seed}, pose error: {err}");
+                pose_err += err;
+                dr_err += hypot(x_dr.x() - x_true.x(), x_dr.y() - x_true.y());
+
+                // The map drifts and turns together with the pose, so match the
+                // landmarks as seen from the vehicle, and compare the distances
+                // between them
+                let best = slam.px[slam.best()].pose();
+                let pose = VehicleState::from(x_true).pose();
+                let matched: Vec<Vector2> = landmarks
+                    .iter()
+                    .map(|landmark| {
+                        let dist = |l: &Landmark| {
+                            (best.inverse_transform_point(&l.x)
+                                - pose.inverse_transform_point(landmark))
+                            .norm()
+                        };
+                        map.iter()
+                            .min_by(|a, b| dist(a).total_cmp(&dist(b)))
+                            .unwrap()
+                            .x
+                    })
+                    .collect();
+                for i in 0..landmarks.len() {
+                    for j in 0..i {
+                        let err =
+                            (matched[i] - matched[j]).norm() - (landmarks[i] - landmarks[j]).norm();
+                        assert!(
+                            err.abs() < 0.5,
+                            "{name}, seed {seed}, landmark distance error: {err}"
+                        );
+                    }
+                }
+            }
+            // Dead reckoning can be close on a single run by chance
+            assert!(
+                pose_err < 0.5 * dr_err,
+                "{name} pose error {pose_err} not below half of DR error {dr_err}"
+            );
+        }
+    }
+
+    #[test]
+    fn collapsed_weights_stay_finite() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut slam = FastSlam::default();
+        slam.pw.fill(0.0);
+        let z = [vector![5.0, 0.1]];
+        slam.update(&mut rng, &z, calc_input(), 0.1);
+        assert!(slam.pw.iter().all(|w| w.is_finite()), "{}", slam.pw);
+        assert!((slam.pw.sum() - 1.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,10 @@
+//! Simultaneous localization and mapping
+
+use crate::prelude::*;
+
+pub mod ekf_slam;
+pub mod fast_slam;
+pub mod pose_graph;
+
+pub use ekf_slam::*;
+pub use fast_slam::{FastSlam, Landmark};
@@ -0,0 +1,204 @@
+//! Reading and writing pose graphs in the g2o and TORO 2D text formats
+//!
+//! ```text
+//! VERTEX_SE2 id x y theta
+//! EDGE_SE2 from to x y theta I11 I12 I13 I22 I23 I33
+//!
+//! VERTEX2 id x y theta
+//! EDGE2 from to x y theta Ixx Ixy Iyy Itt Ixt Iyt
+//! ```
+
+use super::{EdgeKind, PoseGraph, SE2};
+use nalgebra::Matrix3;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Text format of a pose graph
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    /// `VERTEX_SE2` and `EDGE_SE2` lines, with the upper triangle of the
+    /// information matrix in row-major order
+    G2o,
+    /// `VERTEX2` and `EDGE2` lines, with the information matrix in the order
+    /// `xx xy yy tt xt yt`
+    Toro,
+}
+
+impl Format {
+    /// Format of a file from its extension, `.g2o` or `.graph` for TORO
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "g2o" => Some(Self::G2o),
+            "graph" => Some(Self::Toro),
+            _ => None,
+        }
+    }
+}
+
+impl PoseGraph {
+    /// Read a graph in either format, skipping lines of other types.
+    ///
+    /// Edges between consecutive poses are read as odometry, and all others as
+    /// loop closures.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut graph = PoseGraph::default();
+        let mut index: HashMap<usize, usize> = HashMap::new();
+
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+            let tag = match tokens.next() {
+                Some(tag) => tag,
+                None => continue,
+            };
+            let invalid = |msg: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {msg}: {line}", n + 1),
+                )
+            };
+            let values: Vec<f64> = tokens
+                .map(|token| token.parse::<f64>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid("invalid number"))?;
+            let id = |value: f64| {
+                if value >= 0. && value.fract() == 0. {
+                    Ok(value as usize)
+                } else {
+                    Err(invalid("invalid identifier"))
+                }
+            };
+
+            match tag {
+                "VERTEX_SE2" | "VERTEX2" => {
+                    if values.len() < 4 {
+                        return Err(invalid("expected id x y theta"));
+                    }
+                    let id = id(values[0])?;
+                    if index.contains_key(&id) {
+                        return Err(invalid("duplicate vertex"));
+                    }
+                    let pose = SE2::new(values[1], values[2], values[3]);
+                    index.insert(id, graph.add_pose_with_id(pose, id));
+                }
+                "EDGE_SE2" | "EDGE2" => {
+                    if values.len() < 11 {
+                        return Err(invalid(
+                            "expected from to x y theta and 6 information values",
+                        ));
+                    }
+                    let from = *index
+                        .get(&id(values[0])?)
+                        .ok_or_else(|| invalid("unknown vertex"))?;
+                    let to = *index
+                        .get(&id(values[1])?)
+                        .ok_or_else(|| invalid("unknown vertex"))?;
+                    let measurement = SE2::new(values[2], values[3], values[4]);
+                    let i = &values[5..11];
+                    let information = if tag == "EDGE_SE2" {
+                        Matrix3::new(i[0], i[1], i[2], i[1], i[3], i[4], i[2], i[4], i[5])
+                    } else {
+                        Matrix3::new(i[0], i[1], i[4], i[1], i[2], i[5], i[4], i[5], i[3])
+                    };
+                    let kind = if from.abs_diff(to) == 1 {
+                        EdgeKind::Odometry
+                    } else {
+                        EdgeKind::LoopClosure
+                    };
+                    graph.add_edge(from, to, measurement, information, kind);
+                }
+                _ => {}
+            }
+        }
+        Ok(graph)
+    }
+
+    /// Write the graph in the given format
+    pub fn write<W: Write>(&self, mut writer: W, format: Format) -> io::Result<()> {
+        let (vertex, edge) = match format {
+            Format::G2o => ("VERTEX_SE2", "EDGE_SE2"),
+            Format::Toro => ("VERTEX2", "EDGE2"),
+        };
+        for (pose, id) in self.poses.iter().zip(self.ids.iter()) {
+            writeln!(writer, "{vertex} {id} {} {} {}", pose.x, pose.y, pose.theta)?;
+        }
+        for e in self.edges.iter() {
+            let (z, i) = (&e.measurement, &e.information);
+            let information = match format {
+                Format::G2o => [
+                    i[(0, 0)],
+                    i[(0, 1)],
+                    i[(0, 2)],
+                    i[(1, 1)],
+                    i[(1, 2)],
+                    i[(2, 2)],
+                ],
+                Format::Toro => [
+                    i[(0, 0)],
+                    i[(0, 1)],
+                    i[(1, 1)],
+                    i[(2, 2)],
+                    i[(0, 2)],
+                    i[(1, 2)],
+                ],
+            };
+            write!(
+                writer,
+                "{edge} {} {} {} {} {}",
+                self.ids[e.from], self.ids[e.to], z.x, z.y, z.theta
+            )?;
+            for value in information {
+                write!(writer, " {value}")?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Load a graph from a file in either format
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Save the graph to a file in the given format
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: Format) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer, format)?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_both_formats() {
+        let g2o = "\
+VERTEX_SE2 0 0 0 0
+VERTEX_SE2 1 1 0 0.5
+VERTEX_SE2 2 1.5 1 1.5
+FIX 0
+EDGE_SE2 0 1 1 0 0.5 100 1 2 200 3 300
+EDGE_SE2 1 2 0.9 0.6 1 100 0 0 100 0 1000
+EDGE_SE2 0 2 1.5 1 1.5 50 0 0 50 0 500
+";
+        let graph = PoseGraph::read(g2o.as_bytes()).unwrap();
+        assert_eq!(graph.poses.len(), 3);
+        assert_eq!(graph.edges.len(), 3);
+        assert_eq!(graph.edges[2].kind, EdgeKind::LoopClosure);
+        assert_eq!(graph.edges[0].information[(2, 1)], 3.);
+
+        for format in [Format::G2o, Format::Toro] {
+            let mut text = Vec::new();
+            graph.write(&mut text, format).unwrap();
+            assert_eq!(PoseGraph::read(text.as_slice()).unwrap(), graph);
+        }
+
+        assert!(PoseGraph::read("EDGE2 0 1 0 0 0 1 0 1 1 0 0".as_bytes()).is_err());
+        let error = PoseGraph::read("VERTEX2 0 0 0 0\nVERTEX_SE2 0 1 0 0".as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("duplicate vertex"), "{error}");
+    }
+}
@@ -0,0 +1,279 @@
+//! 2D pose-graph SLAM
+//!
+//! Poses in SE(2) are connected by odometry and loop-closure edges, each
+//! holding a relative pose measurement with its information matrix. The graph
+//! is optimised by sparse Gauss-Newton or Levenberg-Marquardt (see
+//! [`Optimizer`]), and can be read from and written to the g2o and TORO 2D text
+//! formats (see [`Format`]).
+//!
+//! Everything here is in double precision, as the normal equations of large
+//! graphs are too poorly conditioned for `f32`.
+
+use nalgebra::{Matrix2, Matrix3, Vector3};
+
+mod io;
+mod optimizer;
+mod sparse;
+
+pub use crate::pose::normalize_angle;
+pub use io::Format;
+pub use optimizer::{Method, Optimizer, RobustKernel, Summary};
+
+/// Pose of the graph, a [`Pose2`](crate::pose::Pose2) in double precision
+pub type SE2 = crate::pose::Pose2<f64>;
+
+/// Source of a relative pose measurement
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EdgeKind {
+    /// Between consecutive poses
+    Odometry,
+    /// Between a pose and a previously visited place
+    LoopClosure,
+}
+
+/// Measurement of the pose `to` relative to the pose `from`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Edge {
+    /// Index of the pose the measurement is relative to
+    pub from: usize,
+    /// Index of the measured pose
+    pub to: usize,
+    pub measurement: SE2,
+    /// Inverse covariance of the measurement
+    pub information: Matrix3<f64>,
+    pub kind: EdgeKind,
+}
+
+impl Edge {
+    /// Error `[x, y, theta]` of the measurement against `poses`
+    pub fn error(&self, poses: &[SE2]) -> Vector3<f64> {
+        let (xi, xj, z) = (&poses[self.from], &poses[self.to], &self.measurement);
+        let t = z.rotation().transpose()
+            * (xi.rotation().transpose() * (xj.translation() - xi.translation()) - z.translation());
+        Vector3::new(t.x, t.y, normalize_angle(xj.theta - xi.theta - z.theta))
+    }
+
+    /// Jacobians of [`Self::error`] with respect to the poses `from` and `to`
+    pub fn jacobians(&self, poses: &[SE2]) -> (Matrix3<f64>, Matrix3<f64>) {
+        let (xi, xj, z) = (&poses[self.from], &poses[self.to], &self.measurement);
+        let Rz_t = z.rotation().transpose();
+        let Ri_t = xi.rotation().transpose();
+        let (s, c) = xi.theta.sin_cos();
+        let dRi_t = Matrix2::new(-s, c, -c, -s);
+
+        let mut A = Matrix3::zeros();
+        A.fixed_slice_mut::<2, 2>(0, 0).copy_from(&(-Rz_t * Ri_t));
+        A.fixed_slice_mut::<2, 1>(0, 2)
+            .copy_from(&(Rz_t * dRi_t * (xj.translation() - xi.translation())));
+        A[(2, 2)] = -1.;
+
+        let mut B = Matrix3::zeros();
+        B.fixed_slice_mut::<2, 2>(0, 0).copy_from(&(Rz_t * Ri_t));
+        B[(2, 2)] = 1.;
+        (A, B)
+    }
+
+    /// Squared Mahalanobis norm of the error
+    pub fn chi2(&self, poses: &[SE2]) -> f64 {
+        let e = self.error(poses);
+        (e.transpose() * self.information * e)[0]
+    }
+}
+
+/// Graph of poses connected by relative pose measurements
+///
+/// The first pose anchors the graph, and is held fixed by the [`Optimizer`].
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PoseGraph {
+    pub poses: Vec<SE2>,
+    /// Identifier of each pose in the file it was read from
+    pub ids: Vec<usize>,
+    pub edges: Vec<Edge>,
+}
+
+impl PoseGraph {
+    /// Add a pose with the next free identifier, returning its index
+    pub fn add_pose(&mut self, pose: SE2) -> usize {
+        let id = self.ids.iter().max().map_or(0, |id| id + 1);
+        self.add_pose_with_id(pose, id)
+    }
+
+    pub fn add_pose_with_id(&mut self, pose: SE2, id: usize) -> usize {
+        self.poses.push(pose);
+        self.ids.push(id);
+        self.poses.len() - 1
+    }
+
+    pub fn add_odometry(
+        &mut self,
+        from: usize,
+        to: usize,
+        measurement: SE2,
+        information: Matrix3<f64>,
+    ) {
+        self.add_edge(from, to, measurement, information, EdgeKind::Odometry);
+    }
+
+    pub fn add_loop_closure(
+        &mut self,
+        from: usize,
+        to: usize,
+        measurement: SE2,
+        information: Matrix3<f64>,
+    ) {
+        self.add_edge(from, to, measurement, information, EdgeKind::LoopClosure);
+    }
+
+    pub fn add_edge(
+        &mut self,
+        from: usize,
+        to: usize,
+        measurement: SE2,
+        information: Matrix3<f64>,
+        kind: EdgeKind,
+    ) {
+        self.edges.push(Edge {
+            from,
+            to,
+            measurement,
+            information,
+            kind,
+        });
+    }
+
+    /// Sum of the squared Mahalanobis norms of all edge errors
+    pub fn chi2(&self) -> f64 {
+        self.edges.iter().map(|edge| edge.chi2(&self.poses)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::localization::particle_filter::randn_with;
+    use nalgebra::Isometry2;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::f64::consts::PI;
+
+    /// Poses driving around a square `laps` times, along with a graph whose
+    /// poses are chained from noisy odometry, and whose loop closures connect
+    /// the same corner of the square on different laps
+    fn square_loop(laps: usize, rng: &mut StdRng) -> (Vec<SE2>, PoseGraph) {
+        let side = 10;
+        let step = SE2::new(1.0, 0.0, 0.0);
+        let turn = SE2::new(1.0, 0.0, PI / 2.);
+        let mut truth = vec![SE2::identity()];
+        for k in 1..laps * 4 * side {
+            let motion = if k % side == 0 { turn } else { step };
+            truth.push(truth[k - 1] * motion);
+        }
+
+        let mut noise = |std: f64| randn_with(rng) as f64 * std;
+        let info = Matrix3::from_diagonal(&Vector3::new(100., 100., 1000.));
+        let mut graph = PoseGraph::default();
+        graph.add_pose(truth[0]);
+        for k in 1..truth.len() {
+            let odom = truth[k - 1].between(&truth[k]);
+            let odom = odom * SE2::new(noise(0.1), noise(0.1), noise(0.03));
+            let pose = graph.poses[k - 1] * odom;
+            graph.add_pose(pose);
+            graph.add_odometry(k - 1, k, odom, info);
+        }
+        for k in 4 * side..truth.len() {
+            if k % side == 0 {
+                let j = k - 4 * side;
+                graph.add_loop_closure(j, k, truth[j].between(&truth[k]), info);
+            }
+        }
+        (truth, graph)
+    }
+
+    fn rmse(truth: &[SE2], poses: &[SE2]) -> f64 {
+        let sum: f64 = truth
+            .iter()
+            .zip(poses.iter())
+            .map(|(a, b)| (a.translation() - b.translation()).norm_squared())
+            .sum();
+        (sum / truth.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn se2_composition_and_inversion() {
+        let a = SE2::new(1., 2., 0.5);
+        let b = SE2::new(-0.3, 0.7, 3.0);
+        let ab = a * b;
+        assert!((a.between(&ab).to_vector() - b.to_vector()).amax() < 1e-12);
+        assert!(((a * a.inverse()).to_vector()).amax() < 1e-12);
+        assert!(ab.theta >= -PI && ab.theta < PI);
+
+        let iso: Isometry2<f64> = ab.into();
+        let expected = Isometry2::from(a) * Isometry2::from(b);
+        assert!((SE2::from(iso).to_vector() - SE2::from(expected).to_vector()).amax() < 1e-12);
+    }
+
+    #[test]
+    fn jacobians_match_finite_differences() {
+        let poses = [SE2::new(1., 2., 0.5), SE2::new(3., 1., -2.)];
+        let edge = Edge {
+            from: 0,
+            to: 1,
+            measurement: SE2::new(0.5, -1., 0.3),
+            information: Matrix3::identity(),
+            kind: EdgeKind::LoopClosure,
+        };
+        let (A, B) = edge.jacobians(&poses);
+        let h = 1e-6;
+        for (k, J) in [(0, A), (1, B)] {
+            for i in 0..3 {
+                let mut dx = Vector3::zeros();
+                dx[i] = h;
+                let mut plus = poses;
+                let mut minus = poses;
+                plus[k] = SE2::from_vector(&(poses[k].to_vector() + dx));
+                minus[k] = SE2::from_vector(&(poses[k].to_vector() - dx));
+                let col = (edge.error(&plus) - edge.error(&minus)) / (2. * h);
+                assert!((col - J.column(i)).amax() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn optimise_square_loops() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (truth, graph) = square_loop(3, &mut rng);
+        let initial = rmse(&truth, &graph.poses);
+
+        for method in [Method::GaussNewton, Method::LevenbergMarquardt] {
+            let mut graph = graph.clone();
+            let summary = Optimizer::default()
+                .with_method(method)
+                .optimize(&mut graph);
+            let err = rmse(&truth, &graph.poses);
+            assert!(summary.converged, "{method:?}: {summary:?}");
+            assert!(summary.final_cost < summary.initial_cost);
+            assert!(
+                err < 0.25 * initial,
+                "{method:?} RMSE {err} from initial {initial}"
+            );
+        }
+    }
+
+    #[test]
+    fn robust_kernel_rejects_false_loop_closure() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (truth, mut graph) = square_loop(3, &mut rng);
+        let info = graph.edges[0].information;
+        graph.add_loop_closure(5, 75, SE2::new(0.0, 0.0, 0.0), info);
+
+        let optimise = |kernel| {
+            let mut graph = graph.clone();
+            Optimizer::default()
+                .with_kernel(kernel)
+                .optimize(&mut graph);
+            rmse(&truth, &graph.poses)
+        };
+        let squared = optimise(RobustKernel::Squared);
+        let cauchy = optimise(RobustKernel::Cauchy(1.0));
+        assert!(cauchy < 0.5 * squared, "Cauchy {cauchy}, squared {squared}");
+    }
+}
@@ -0,0 +1,217 @@
+use super::sparse::BlockSystem;
+use super::{PoseGraph, SE2};
+use nalgebra::{DVector, Vector3};
+
+/// Step computation of the [`Optimizer`]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Method {
+    GaussNewton,
+    /// Gauss-Newton with an adaptive damping of the diagonal, which only
+    /// accepts steps that decrease the cost
+    LevenbergMarquardt,
+}
+
+/// Cost of an edge as a function of its squared Mahalanobis error `s`,
+/// limiting the influence of outliers such as false loop closures
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RobustKernel {
+    /// Least squares, `s`
+    Squared,
+    /// Quadratic up to an error of `delta`, and linear beyond it
+    Huber(f64),
+    /// `c^2 ln(1 + s / c^2)`
+    Cauchy(f64),
+}
+
+impl RobustKernel {
+    /// Cost and its derivative, which weights the edge in the normal equations
+    pub fn rho(&self, s: f64) -> (f64, f64) {
+        match *self {
+            Self::Squared => (s, 1.),
+            Self::Huber(delta) => {
+                if s <= delta * delta {
+                    (s, 1.)
+                } else {
+                    let e = s.sqrt();
+                    (2. * delta * e - delta * delta, delta / e)
+                }
+            }
+            Self::Cauchy(c) => {
+                let c2 = c * c;
+                (c2 * (s / c2).ln_1p(), 1. / (1. + s / c2))
+            }
+        }
+    }
+}
+
+/// Outcome of [`Optimizer::optimize`]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Summary {
+    pub iterations: usize,
+    pub initial_cost: f64,
+    pub final_cost: f64,
+    /// Whether the relative decrease of the cost fell below the tolerance
+    pub converged: bool,
+}
+
+/// Sparse non-linear least squares optimiser of a [`PoseGraph`]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Optimizer {
+    pub method: Method,
+    pub kernel: RobustKernel,
+    pub max_iterations: usize,
+    /// Relative decrease of the cost below which the optimisation stops
+    pub tolerance: f64,
+    /// Initial damping of [`Method::LevenbergMarquardt`]
+    pub lambda: f64,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self {
+            method: Method::LevenbergMarquardt,
+            kernel: RobustKernel::Squared,
+            max_iterations: 100,
+            tolerance: 1e-9,
+            lambda: 1e-4,
+        }
+    }
+}
+
+impl Optimizer {
+    pub fn with_method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn with_kernel(mut self, kernel: RobustKernel) -> Self {
+        self.kernel = kernel;
+        self
+    }
+
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Total robust cost of the edges of `graph` at `poses`
+    pub fn cost(&self, graph: &PoseGraph, poses: &[SE2]) -> f64 {
+        graph
+            .edges
+            .iter()
+            .map(|edge| self.kernel.rho(edge.chi2(poses)).0)
+            .sum()
+    }
+
+    /// Optimise the poses of `graph` in place, holding the first one fixed
+    pub fn optimize(&self, graph: &mut PoseGraph) -> Summary {
+        let initial_cost = self.cost(graph, &graph.poses);
+        let mut summary = Summary {
+            iterations: 0,
+            initial_cost,
+            final_cost: initial_cost,
+            converged: false,
+        };
+        if graph.poses.len() < 2 {
+            summary.converged = true;
+            return summary;
+        }
+
+        let mut cost = initial_cost;
+        let mut lambda = self.lambda;
+        while summary.iterations < self.max_iterations && !summary.converged {
+            summary.iterations += 1;
+            let system = self.linearize(graph);
+
+            let (poses, new_cost) = match self.method {
+                Method::GaussNewton => match system.solve() {
+                    Some(dx) => {
+                        let poses = apply(&graph.poses, &dx);
+                        let new_cost = self.cost(graph, &poses);
+                        (poses, new_cost)
+                    }
+                    None => break,
+                },
+                Method::LevenbergMarquardt => {
+                    // Increase the damping until the step decreases the cost
+                    let mut step = None;
+                    while lambda < 1e10 {
+                        if let Some(dx) = system.damped(lambda).solve() {
+                            let poses = apply(&graph.poses, &dx);
+                            let new_cost = self.cost(graph, &poses);
+                            if new_cost <= cost {
+                                lambda = (lambda / 10.).max(1e-12);
+                                step = Some((poses, new_cost));
+                                break;
+                            }
+                        }
+                        lambda *= 10.;
+                    }
+                    match step {
+                        Some(step) => step,
+                        None => {
+                            // No step decreases the cost, so we are at a minimum
+                            summary.converged = true;
+                            break;
+                        }
+                    }
+                }
+            };
+
+            summary.converged = (cost - new_cost).abs() <= self.tolerance * cost.max(1e-12);
+            graph.poses = poses;
+            cost = new_cost;
+        }
+        summary.final_cost = cost;
+        summary
+    }
+
+    /// Normal equations of the robustly weighted edges, for all but the first pose
+    fn linearize(&self, graph: &PoseGraph) -> BlockSystem {
+        let mut system = BlockSystem::new(graph.poses.len() - 1);
+        let index = |i: usize| i.checked_sub(1);
+        for edge in graph.edges.iter() {
+            let e = edge.error(&graph.poses);
+            let (A, B) = edge.jacobians(&graph.poses);
+            let s = (e.transpose() * edge.information * e)[0];
+            let (_, w) = self.kernel.rho(s);
+            let omega = edge.information * w;
+
+            let (i, j) = (index(edge.from), index(edge.to));
+            if let Some(i) = i {
+                system.add_block(i, i, &(A.transpose() * omega * A));
+                system.add_rhs(i, &(-A.transpose() * omega * e));
+            }
+            if let Some(j) = j {
+                system.add_block(j, j, &(B.transpose() * omega * B));
+                system.add_rhs(j, &(-B.transpose() * omega * e));
+            }
+            if let (Some(i), Some(j)) = (i, j) {
+                if i != j {
+                    system.add_block(i, j, &(A.transpose() * omega * B));
+                }
+            }
+        }
+        system
+    }
+}
+
+/// Poses moved by the step `dx` of all but the first pose
+fn apply(poses: &[SE2], dx: &DVector<f64>) -> Vec<SE2> {
+    poses
+        .iter()
+        .enumerate()
+        .map(|(k, pose)| match k.checked_sub(1) {
+            Some(i) => {
+                let d: Vector3<f64> = dx.fixed_rows::<3>(3 * i).into_owned();
+                SE2::from_vector(&(pose.to_vector() + d))
+            }
+            None => *pose,
+        })
+        .collect()
+}
@@ -0,0 +1,286 @@
+//! Sparse symmetric positive definite systems of 3x3 blocks
+//!
+//! Solved by an LDL^T factorisation (after T. Davis, "Algorithm 849: A concise
+//! sparse Cholesky factorization package") of the system permuted by a
+//! minimum degree ordering of its blocks.
+
+use nalgebra::{DVector, Matrix3, Vector3};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Block size
+const B: usize = 3;
+
+/// Symmetric system `H x = b` of `n` x `n` blocks
+pub(super) struct BlockSystem {
+    n: usize,
+    /// Upper triangular blocks of `H`, keyed by (row, column)
+    blocks: BTreeMap<(usize, usize), Matrix3<f64>>,
+    b: DVector<f64>,
+}
+
+impl BlockSystem {
+    pub fn new(n: usize) -> Self {
+        Self {
+            n,
+            blocks: BTreeMap::new(),
+            b: DVector::zeros(B * n),
+        }
+    }
+
+    /// Add `m` to block (`i`, `j`) of `H`, and its transpose to block (`j`, `i`)
+    pub fn add_block(&mut self, i: usize, j: usize, m: &Matrix3<f64>) {
+        let (key, m) = if i <= j {
+            ((i, j), *m)
+        } else {
+            ((j, i), m.transpose())
+        };
+        *self.blocks.entry(key).or_insert_with(Matrix3::zeros) += m;
+    }
+
+    pub fn add_rhs(&mut self, i: usize, v: &Vector3<f64>) {
+        let mut rows = self.b.fixed_rows_mut::<B>(B * i);
+        rows += v;
+    }
+
+    /// Copy of the system with its diagonal scaled by `1 + lambda`
+    pub fn damped(&self, lambda: f64) -> Self {
+        let mut blocks = self.blocks.clone();
+        for i in 0..self.n {
+            if let Some(block) = blocks.get_mut(&(i, i)) {
+                for k in 0..B {
+                    block[(k, k)] *= 1. + lambda;
+                }
+            }
+        }
+        Self {
+            n: self.n,
+            blocks,
+            b: self.b.clone(),
+        }
+    }
+
+    /// Solve `H x = b`, or `None` if `H` isn't positive definite
+    pub fn solve(&self) -> Option<DVector<f64>> {
+        let order = minimum_degree(self.n, self.blocks.keys());
+        let mut position = vec![0; self.n];
+        for (k, &i) in order.iter().enumerate() {
+            position[i] = k;
+        }
+
+        // Upper triangle of the permuted matrix in compressed columns
+        let mut entries: BTreeMap<(usize, usize), f64> = BTreeMap::new();
+        for (&(bi, bj), m) in self.blocks.iter() {
+            for r in 0..B {
+                for c in 0..B {
+                    if bi == bj && r > c {
+                        continue;
+                    }
+                    let row = B * position[bi] + r;
+                    let col = B * position[bj] + c;
+                    let key = (row.max(col), row.min(col));
+                    *entries.entry(key).or_insert(0.) += m[(r, c)];
+                }
+            }
+        }
+        let size = B * self.n;
+        let mut Ap = vec![0; size + 1];
+        let mut Ai = Vec::with_capacity(entries.len());
+        let mut Ax = Vec::with_capacity(entries.len());
+        for (&(col, row), &value) in entries.iter() {
+            Ap[col + 1] += 1;
+            Ai.push(row);
+            Ax.push(value);
+        }
+        for k in 0..size {
+            Ap[k + 1] += Ap[k];
+        }
+
+        let ldl = Ldl::factorize(size, &Ap, &Ai, &Ax)?;
+        let mut x: Vec<f64> = (0..size)
+            .map(|k| self.b[B * order[k / B] + k % B])
+            .collect();
+        ldl.solve(&mut x);
+
+        let mut solution = DVector::zeros(size);
+        for (k, value) in x.into_iter().enumerate() {
+            solution[B * order[k / B] + k % B] = value;
+        }
+        Some(solution)
+    }
+}
+
+/// Elimination order of the blocks, picking the block with the fewest
+/// neighbours in the elimination graph each time
+fn minimum_degree<'a>(n: usize, keys: impl Iterator<Item = &'a (usize, usize)>) -> Vec<usize> {
+    let mut adjacency = vec![BTreeSet::new(); n];
+    for &(i, j) in keys {
+        if i != j {
+            adjacency[i].insert(j);
+            adjacency[j].insert(i);
+        }
+    }
+
+    let mut eliminated = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    for _ in 0..n {
+        let v = (0..n)
+            .filter(|&i| !eliminated[i])
+            .min_by_key(|&i| adjacency[i].len())
+            .unwrap_or(0);
+        let neighbours: Vec<usize> = std::mem::take(&mut adjacency[v]).into_iter().collect();
+        for &a in neighbours.iter() {
+            adjacency[a].remove(&v);
+            adjacency[a].extend(neighbours.iter().filter(|&&b| b != a));
+        }
+        eliminated[v] = true;
+        order.push(v);
+    }
+    order
+}
+
+/// Sparse `L D L^T` factorisation, with `L` in compressed columns
+struct Ldl {
+    Lp: Vec<usize>,
+    Li: Vec<usize>,
+    Lx: Vec<f64>,
+    D: Vec<f64>,
+}
+
+impl Ldl {
+    /// Factorize the matrix whose upper triangle is given in compressed
+    /// columns (`Ap`, `Ai`, `Ax`)
+    fn factorize(n: usize, Ap: &[usize], Ai: &[usize], Ax: &[f64]) -> Option<Self> {
+        const NONE: usize = usize::MAX;
+
+        // Elimination tree and column counts of L
+        let mut parent = vec![NONE; n];
+        let mut flag = vec![0; n];
+        let mut Lnz = vec![0; n];
+        for k in 0..n {
+            flag[k] = k;
+            for &row in &Ai[Ap[k]..Ap[k + 1]] {
+                let mut i = row;
+                while i < k && flag[i] != k {
+                    if parent[i] == NONE {
+                        parent[i] = k;
+                    }
+                    Lnz[i] += 1;
+                    flag[i] = k;
+                    i = parent[i];
+                }
+            }
+        }
+        let mut Lp = vec![0; n + 1];
+        for k in 0..n {
+            Lp[k + 1] = Lp[k] + Lnz[k];
+        }
+
+        // Numerical factorisation, one row of L at a time
+        let mut Li = vec![0; Lp[n]];
+        let mut Lx = vec![0.; Lp[n]];
+        let mut D = vec![0.; n];
+        let mut y = vec![0.; n];
+        let mut pattern = vec![0; n];
+        for k in 0..n {
+            let mut top = n;
+            flag[k] = k;
+            Lnz[k] = 0;
+            for p in Ap[k]..Ap[k + 1] {
+                let mut i = Ai[p];
+                y[i] += Ax[p];
+                let mut len = 0;
+                while flag[i] != k {
+                    pattern[len] = i;
+                    len += 1;
+                    flag[i] = k;
+                    i = parent[i];
+                }
+                while len > 0 {
+                    top -= 1;
+                    len -= 1;
+                    pattern[top] = pattern[len];
+                }
+            }
+            D[k] = y[k];
+            y[k] = 0.;
+            for &i in &pattern[top..n] {
+                let yi = y[i];
+                y[i] = 0.;
+                let end = Lp[i] + Lnz[i];
+                for p in Lp[i]..end {
+                    y[Li[p]] -= Lx[p] * yi;
+                }
+                let l_ki = yi / D[i];
+                D[k] -= l_ki * yi;
+                Li[end] = k;
+                Lx[end] = l_ki;
+                Lnz[i] += 1;
+            }
+            if D[k] <= 0. || !D[k].is_finite() {
+                return None;
+            }
+        }
+        Some(Self { Lp, Li, Lx, D })
+    }
+
+    /// Overwrite `x` with the solution of `L D L^T x = x`
+    fn solve(&self, x: &mut [f64]) {
+        let n = self.D.len();
+        for j in 0..n {
+            for p in self.Lp[j]..self.Lp[j + 1] {
+                x[self.Li[p]] -= self.Lx[p] * x[j];
+            }
+        }
+        for (xj, d) in x.iter_mut().zip(self.D.iter()) {
+            *xj /= d;
+        }
+        for j in (0..n).rev() {
+            for p in self.Lp[j]..self.Lp[j + 1] {
+                x[j] -= self.Lx[p] * x[self.Li[p]];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::DMatrix;
+
+    #[test]
+    fn solve_matches_dense() {
+        // Chain of blocks with a link between the ends, like a loop closure
+        let n = 6;
+        let mut system = BlockSystem::new(n);
+        let mut dense = DMatrix::zeros(B * n, B * n);
+        let mut add = |system: &mut BlockSystem, i: usize, j: usize, m: Matrix3<f64>| {
+            system.add_block(i, j, &m);
+            let mut block = dense.fixed_slice_mut::<B, B>(B * i, B * j);
+            block += m;
+            if i != j {
+                let mut block = dense.fixed_slice_mut::<B, B>(B * j, B * i);
+                block += m.transpose();
+            }
+        };
+        for i in 0..n {
+            add(
+                &mut system,
+                i,
+                i,
+                Matrix3::new(4., 1., 0., 1., 5., 0.5, 0., 0.5, 6.),
+            );
+            let j = (i + 1) % n;
+            add(
+                &mut system,
+                j,
+                i,
+                Matrix3::new(-1., 0.2, 0., 0., -1., 0.3, 0.1, 0., -1.),
+            );
+            system.add_rhs(i, &Vector3::new(i as f64, 1., -2.));
+        }
+
+        let x = system.solve().unwrap();
+        let expected = dense.cholesky().unwrap().solve(&system.b);
+        assert!((x - expected).amax() < 1e-10);
+    }
+}
@@ -42,20 +42,6 @@ impl MatrixUtil for Matrix2{
     }
 }
 
-pub trait Point{
-    fn x(&self)->f32;    
-    fn y(&self)->f32;    
-}
-
-impl Point for Vector2{
-    fn x(&self)->f32{
-        *self.get(0).expect("Cannot get 1st element of Vector2")
-    }
-    fn y(&self)->f32{
-        *self.get(1).expect("Cannot get 2nd element of Vector2")
-    }   
-}
-
 pub fn exp(u:f32)->f32{
     u.exp()
 }
@@ -1,6 +1,7 @@
 use super::*;
 
 use egui::plot::{Value, Values};
+use rb::identification::IoData;
 use rust_robotics_algo as rb;
 
 /// Allows converting from a column in [`TimeTable`] into [`egui`] [`Values`].
@@ -50,3 +51,31 @@ impl VehiclePlot for Vec<rb::Vector4> {
         )
     }
 }
+
+/// Allows extracting named input/output columns of a [`TimeTable`] for
+/// [`identification`](rb::identification).
+pub trait IntoIoData {
+    /// Returns `None` if any of the columns cannot be found
+    fn io_data(&self, inputs: &[&str], outputs: &[&str]) -> Option<IoData>;
+}
+
+impl IntoIoData for TimeTable<f32> {
+    fn io_data(&self, inputs: &[&str], outputs: &[&str]) -> Option<IoData> {
+        let columns = |names: &[&str]| -> Option<Vec<Vec<f32>>> {
+            names
+                .iter()
+                .map(|&name| {
+                    self.column_index(name)
+                        .and_then(|i| self.get_column(i))
+                        .map(|col| col.iter().copied().collect())
+                })
+                .collect()
+        };
+        let u = columns(inputs)?;
+        let y = columns(outputs)?;
+
+        let u: Vec<&[f32]> = u.iter().map(|col| col.as_slice()).collect();
+        let y: Vec<&[f32]> = y.iter().map(|col| col.as_slice()).collect();
+        Some(IoData::from_columns(&u, &y))
+    }
+}
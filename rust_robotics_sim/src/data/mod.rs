@@ -225,6 +225,12 @@ impl<T: Clone> TimeTable<T> {
     pub fn ncols(&self) -> usize {
         self.data.len()
     }
+    /// Index of the column with the given name
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.data
+            .iter()
+            .position(|col| col.name.as_deref() == Some(name))
+    }
     pub fn pop_first(&mut self) {
         self.time.pop_first();
         self.data.iter_mut().for_each(|col| col.pop_first());
@@ -403,6 +409,57 @@ mod tests {
         assert_eq!(&[3.0, 6.0, 9.0], ts.get_range(1.0, 3.0).unwrap());
     }
 
+    #[test]
+    #[allow(non_snake_case)]
+    fn identify_from_table() {
+        use rand::{rngs::StdRng, SeedableRng};
+        use rust_robotics_algo::control::{StateSpace, LQR};
+        use rust_robotics_algo::identification::arx;
+        use rust_robotics_algo::inverted_pendulum::{Model, NU, NX};
+        use rust_robotics_algo::localization::particle_filter::rand_with;
+        use rust_robotics_algo::prelude::*;
+
+        let dt = 0.01;
+        let model = Model::default();
+        let (A, B) = model.model(dt);
+        let K = model.dlqr(A, B);
+        let mut table = TimeTable::init_with_names(vec![
+            "Lateral Position",
+            "Lateral Velocity",
+            "Rod Angle",
+            "Rod Angular Velocity",
+            "Control Input",
+        ]);
+
+        // Log in the same way as `InvertedPendulum`, with a dithered input
+        let mut x = vector![0., 0., 0.2, 0.];
+        let mut rng = StdRng::seed_from_u64(0);
+        for k in 0..300 {
+            let u = (-K * x)[0] + rand_with(&mut rng);
+            x = A * x + B * u;
+            table.add(k as f32 * dt, vec![x[0], x[1], x[2], x[3], u]);
+        }
+
+        let outputs = [
+            "Lateral Position",
+            "Lateral Velocity",
+            "Rod Angle",
+            "Rod Angular Velocity",
+        ];
+        let data = table
+            .io_data(&["Control Input"], &outputs)
+            .unwrap()
+            .advance_inputs(1);
+        let identified = arx(&data, 1, 1)
+            .to_state_space()
+            .to_linear_model::<NX, NU>()
+            .unwrap();
+
+        assert!((identified.A - A).abs().amax() < 1e-3);
+        assert!((identified.B - B).abs().amax() < 1e-3);
+        assert!(table.io_data(&["Missing"], &outputs).is_none());
+    }
+
     #[test]
     fn series_to_table() {
         let _ts = dummy_f32();
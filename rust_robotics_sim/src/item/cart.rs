@@ -1,21 +1,42 @@
 use super::{Circle, Point, Rectangle, Shape, WithPosition, WithSize};
 use crate::math::{cos, sin};
 use egui::plot::{Line, PlotUi, Values};
+use rust_robotics_algo::double_inverted_pendulum as double;
 use rust_robotics_algo::inverted_pendulum::Model;
 
 pub fn draw_cart(plot_ui: &mut PlotUi, x_pos: f32, rod_angle: f32, model: &Model, name: &str) {
+    let rod_bottom = draw_cart_body(plot_ui, x_pos, model.m_cart, name);
+    draw_rod(plot_ui, rod_bottom, rod_angle, model.l_bar, model.m_ball);
+}
+
+/// Draw a cart carrying two rods linked end-to-end, as in
+/// [`double_inverted_pendulum`](rust_robotics_algo::double_inverted_pendulum)
+pub fn draw_double_cart(
+    plot_ui: &mut PlotUi,
+    x_pos: f32,
+    rod_angles: [f32; 2],
+    model: &double::Model,
+    name: &str,
+) {
+    let rod_bottom = draw_cart_body(plot_ui, x_pos, model.m_cart, name);
+    let joint = draw_rod(
+        plot_ui,
+        rod_bottom,
+        rod_angles[0],
+        model.l_bar1,
+        model.m_ball1,
+    );
+    draw_rod(plot_ui, joint, rod_angles[1], model.l_bar2, model.m_ball2);
+}
+
+/// Draw cart body and wheels, and return the point where the rod is attached
+fn draw_cart_body(plot_ui: &mut PlotUi, x_pos: f32, m_cart: f32, name: &str) -> Point {
     let x = x_pos as f64;
     let y = 0.0;
 
-    let r_ball = 0.1 * model.m_ball as f64;
-    let r_whl = 0.1 * model.m_cart as f64;
-    let w = 1.0 * model.m_cart as f64;
-    let h = 0.5 * model.m_cart as f64;
-    let len = model.l_bar as f64;
-    let th = rod_angle as f64;
-
-    let rod_bottom = Point::new(x, y + h + 2.0 * r_whl);
-    let rod_top = Point::new(rod_bottom.x - len * sin(th), rod_bottom.y + len * cos(th));
+    let r_whl = 0.1 * m_cart as f64;
+    let w = 1.0 * m_cart as f64;
+    let h = 0.5 * m_cart as f64;
 
     let body = Rectangle::new()
         .with_width(w)
@@ -30,15 +51,36 @@ pub fn draw_cart(plot_ui: &mut PlotUi, x_pos: f32, rod_angle: f32, model: &Model
         .with_radius(r_whl)
         .at(x + w / 4.0, y + r_whl)
         .into_polygon();
+
+    plot_ui.polygon(body.name(name));
+    plot_ui.polygon(left_wheel);
+    plot_ui.polygon(right_wheel);
+
+    Point::new(x, y + h + 2.0 * r_whl)
+}
+
+/// Draw a rod with a ball at its end, and return the position of the ball
+fn draw_rod(
+    plot_ui: &mut PlotUi,
+    rod_bottom: Point,
+    rod_angle: f32,
+    l_bar: f32,
+    m_ball: f32,
+) -> Point {
+    let r_ball = 0.1 * m_ball as f64;
+    let len = l_bar as f64;
+    let th = rod_angle as f64;
+
+    let rod_top = Point::new(rod_bottom.x - len * sin(th), rod_bottom.y + len * cos(th));
+
     let ball = Circle::new()
         .with_radius(r_ball)
         .at(rod_top.x, rod_top.y)
         .into_polygon();
     let rod = Line::new(Values::from_values(vec![rod_bottom, rod_top]));
 
-    plot_ui.polygon(body.name(name));
-    plot_ui.polygon(left_wheel);
-    plot_ui.polygon(right_wheel);
     plot_ui.polygon(ball);
     plot_ui.line(rod);
+
+    rod_top
 }
@@ -28,6 +28,19 @@ impl Default for Ellipse {
 }
 
 impl Ellipse {
+    /// Ellipse of `n_std` standard deviations of a 2D Gaussian with covariance `cov`
+    pub fn from_covariance(cov: &rust_robotics_algo::Matrix2, n_std: f64) -> Self {
+        let eig = cov.symmetric_eigen();
+        let i = eig.eigenvalues.imax();
+        let major = eig.eigenvectors.column(i);
+        let axis = |lambda: f32| 2.0 * n_std * (lambda.max(0.0) as f64).sqrt();
+
+        Self::default()
+            .with_width(axis(eig.eigenvalues[i]))
+            .with_height(axis(eig.eigenvalues[1 - i]))
+            .with_angle((major[1] as f64).atan2(major[0] as f64))
+    }
+
     pub fn into_polygon(self) -> egui::plot::Polygon {
         let a = self.width() / 2.0; // Horizontal axis
         let b = self.height() / 2.0; // Vertical axis
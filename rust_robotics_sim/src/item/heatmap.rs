@@ -0,0 +1,78 @@
+use egui::plot::{PlotImage, PlotUi, Value};
+use egui::{Color32, ColorImage, TextureHandle};
+use rust_robotics_algo::nalgebra::DMatrix;
+use std::cell::RefCell;
+
+/// Grid of values drawn as an image in the plot, fading from transparent at
+/// zero to `color` at the largest value
+///
+/// The texture is kept between frames and only its contents are replaced.
+pub struct Heatmap {
+    color: Color32,
+    texture: RefCell<Option<TextureHandle>>,
+}
+
+impl Default for Heatmap {
+    fn default() -> Self {
+        Self {
+            color: Color32::from_rgb(255, 120, 0),
+            texture: RefCell::new(None),
+        }
+    }
+}
+
+impl Heatmap {
+    pub fn with_color(mut self, color: Color32) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Draw `values`, with rows along x and columns along y, as cells of side
+    /// `resolution` from the lower left corner `min`
+    pub fn draw(
+        &self,
+        plot_ui: &mut PlotUi,
+        values: &DMatrix<f32>,
+        min: [f64; 2],
+        resolution: f64,
+        name: &str,
+    ) {
+        let (nx, ny) = values.shape();
+        if nx == 0 || ny == 0 {
+            return;
+        }
+        let max = values.max();
+        let scale = if max > 0.0 { 255.0 / max } else { 0.0 };
+        // Image rows run from the top, i.e. the largest y
+        let pixels = (0..ny)
+            .rev()
+            .flat_map(|j| (0..nx).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let [r, g, b, _] = self.color.to_array();
+                let alpha = (values[(i, j)] * scale).clamp(0.0, 255.0) as u8;
+                Color32::from_rgba_unmultiplied(r, g, b, alpha)
+            })
+            .collect();
+        let image = ColorImage {
+            size: [nx, ny],
+            pixels,
+        };
+
+        let mut texture = self.texture.borrow_mut();
+        match texture.as_mut() {
+            Some(texture) => texture.set(image),
+            None => *texture = Some(plot_ui.ctx().load_texture(name, image)),
+        }
+        if let Some(texture) = texture.as_ref() {
+            let size = [nx as f64 * resolution, ny as f64 * resolution];
+            plot_ui.image(
+                PlotImage::new(
+                    texture,
+                    Value::new(min[0] + size[0] / 2.0, min[1] + size[1] / 2.0),
+                    [size[0] as f32, size[1] as f32],
+                )
+                .name(name),
+            );
+        }
+    }
+}
@@ -1,10 +1,12 @@
 mod cart;
 mod ellipse;
+mod heatmap;
 mod rectangle;
 mod vehicle;
 
-pub use cart::draw_cart;
+pub use cart::{draw_cart, draw_double_cart};
 pub use ellipse::{Circle, Ellipse};
+pub use heatmap::Heatmap;
 pub use rectangle::Rectangle;
 pub use vehicle::draw_vehicle;
 
@@ -1,16 +1,17 @@
 use super::{Circle, Point, Rectangle, Shape, WithAngle, WithPosition, WithSize};
 use crate::math::{cos, sin};
 use egui::plot::{Line, PlotUi, Values};
-use rust_robotics_algo::localization::StateVector;
-use rust_robotics_algo::{inverted_pendulum::Model, Vector4};
+use rust_robotics_algo::inverted_pendulum::Model;
+use rust_robotics_algo::pose::VehicleState;
 
-//x, y, phi, v
-pub fn draw_vehicle(plot_ui: &mut PlotUi, state: Vector4, name: &str) {
+/// Draw the body of a vehicle at `state`, e.g. a `Vector4` of `[x, y, phi, v]`
+pub fn draw_vehicle(plot_ui: &mut PlotUi, state: impl Into<VehicleState>, name: &str) {
     let w = 0.4;
     let h = 0.2;
-    let x = state.x() as f64;
-    let y = state.y() as f64;
-    let ang = state.phi() as f64;
+    let pose = state.into().pose();
+    let x = pose.x as f64;
+    let y = pose.y as f64;
+    let ang = pose.theta as f64;
 
     let body = Rectangle::new()
         .with_width(w)
@@ -13,7 +13,8 @@ pub use view::View;
 
 pub mod prelude {
     pub use crate::item::{
-        draw_cart, Circle, Ellipse, Rectangle, Shape, WithAngle, WithPosition, WithSize,
+        draw_cart, draw_double_cart, Circle, Ellipse, Rectangle, Shape, WithAngle, WithPosition,
+        WithSize,
     };
     pub use crate::math::{cos, sin};
     pub use crate::time::Timer;
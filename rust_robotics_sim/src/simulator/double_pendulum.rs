@@ -0,0 +1,258 @@
+#![allow(non_snake_case)]
+
+use super::pendulum::rand;
+use super::Draw;
+use crate::data::{IntoValues, TimeTable};
+use crate::prelude::draw_double_cart;
+
+use egui::plot::Line;
+use egui::{plot::PlotUi, DragValue, Ui};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rb::double_inverted_pendulum::*;
+use rb::prelude::*;
+use rust_robotics_algo as rb;
+
+use super::{Simulate, DEFAULT_SEED};
+
+/// Double inverted pendulum simulation
+///
+/// The plant is integrated with the nonlinear dynamics of [`Model`], and
+/// stabilised by an LQR controller designed on its linearisation.
+pub struct DoubleInvertedPendulum {
+    state: State,
+    /// Model of the plant
+    model: Model,
+    /// Model and weights used by the LQR controller
+    controller: Model,
+    /// LQR gain, along with the controller model it was computed for
+    gain: Option<(Model, Mat<NU, NX>)>,
+    id: usize,
+    data: TimeTable,
+    time_init: f32,
+    /// Seed of `rng`, used again on every [`reset_state`](Simulate::reset_state)
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Default for DoubleInvertedPendulum {
+    fn default() -> Self {
+        let data = TimeTable::init_with_names(vec![
+            "Lateral Position",
+            "Lateral Velocity",
+            "Rod 1 Angle",
+            "Rod 1 Angular Velocity",
+            "Rod 2 Angle",
+            "Rod 2 Angular Velocity",
+            "Control Input",
+        ]);
+
+        let mut rng = StdRng::seed_from_u64(DEFAULT_SEED);
+        Self {
+            state: initial_state(&mut rng),
+            model: Model::default(),
+            controller: Model::default(),
+            gain: None,
+            id: 1,
+            data,
+            time_init: 0.0,
+            seed: DEFAULT_SEED,
+            rng,
+        }
+    }
+}
+
+impl DoubleInvertedPendulum {
+    pub fn new(id: usize, time: f32) -> Self {
+        Self {
+            id,
+            time_init: time,
+            ..Default::default()
+        }
+    }
+
+    /// Draw the initial state from `seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self.state = initial_state(&mut self.rng);
+        self
+    }
+
+    pub fn x_position(&self) -> f32 {
+        self.state[0]
+    }
+
+    pub fn rod_angles(&self) -> [f32; 2] {
+        [self.state[2], self.state[4]]
+    }
+
+    /// LQR gain for the current controller model.
+    ///
+    /// Solving the DARE for 6 states is costly, so the gain is only recomputed
+    /// when the controller model changes.
+    fn lqr_gain(&mut self, dt: f32) -> Mat<NU, NX> {
+        match self.gain {
+            Some((model, K)) if model == self.controller => K,
+            _ => {
+                let (A, B) = self.controller.model(dt);
+                let K = self.controller.dlqr(A, B);
+                self.gain = Some((self.controller, K));
+                K
+            }
+        }
+    }
+}
+
+impl Simulate for DoubleInvertedPendulum {
+    fn get_state(&self) -> &dyn std::any::Any {
+        &self.state
+    }
+
+    fn match_state_with(&mut self, other: &dyn Simulate) {
+        if let Some(data) = other.get_state().downcast_ref::<State>() {
+            // Then set self's data from `other` if the type matches
+            self.state.clone_from(data);
+        }
+    }
+
+    fn step(&mut self, dt: f32) {
+        let K = self.lqr_gain(dt);
+        let u = (-K * self.state)[0];
+
+        self.state = self.model.step(self.state, u, dt);
+
+        // Log data
+        let mut sample: Vec<f32> = self.state.iter().copied().collect();
+        sample.push(u);
+        self.data.add(self.data.time_last() + dt, sample);
+    }
+
+    fn reset_state(&mut self) {
+        self.rng = StdRng::seed_from_u64(self.seed);
+        self.state = initial_state(&mut self.rng);
+        self.time_init = 0.0;
+        self.data.clear();
+    }
+
+    fn reset_all(&mut self) {
+        *self = Self::default();
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+}
+
+impl Draw for DoubleInvertedPendulum {
+    fn plot(&self, plot_ui: &mut PlotUi) {
+        let names: Vec<String> = self
+            .data
+            .names()
+            .iter()
+            .map(|name| format!("{}_{}", name, self.id))
+            .collect();
+
+        (0..self.data.ncols()).for_each(|i| {
+            self.data
+                .values_shifted(i, self.time_init, 0.0)
+                .map(|values| plot_ui.line(Line::new(values).name(&names[i])));
+        });
+    }
+
+    fn scene(&self, plot_ui: &mut PlotUi) {
+        draw_double_cart(
+            plot_ui,
+            self.x_position(),
+            self.rod_angles(),
+            &self.model,
+            &format!("Double Cart {}", self.id),
+        );
+    }
+
+    fn options(&mut self, ui: &mut Ui) {
+        ui.group(|ui| {
+            ui.vertical(|ui| {
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label("Double Cart:");
+                        model_options(ui, &mut self.model);
+                    });
+                });
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label("LQR Parameters:");
+                        model_options(ui, &mut self.controller);
+                        ui.label("Weights");
+                        for (i, name) in [
+                            "Lateral Position: ",
+                            "Lateral Velocity: ",
+                            "Rod 1 Angle: ",
+                            "Rod 1 Angular Vel: ",
+                            "Rod 2 Angle: ",
+                            "Rod 2 Angular Vel: ",
+                        ]
+                        .iter()
+                        .enumerate()
+                        {
+                            ui.add(
+                                DragValue::new(&mut self.controller.Q[(i, i)])
+                                    .speed(0.01)
+                                    .clamp_range(0.0_f32..=100.0)
+                                    .prefix(*name),
+                            );
+                        }
+                        ui.add(
+                            DragValue::new(self.controller.R.get_mut(0).unwrap())
+                                .speed(0.01)
+                                .clamp_range(0.001_f32..=100.0)
+                                .prefix("Control Input: "),
+                        );
+                    });
+                });
+            });
+        });
+    }
+}
+
+/// Draw [`egui`] widgets for the physical parameters of [`Model`]
+fn model_options(ui: &mut Ui, model: &mut Model) {
+    ui.add(
+        DragValue::new(&mut model.l_bar1)
+            .speed(0.01)
+            .clamp_range(0.1_f32..=5.0)
+            .prefix("Beam 1 Length: ")
+            .suffix(" m"),
+    );
+    ui.add(
+        DragValue::new(&mut model.l_bar2)
+            .speed(0.01)
+            .clamp_range(0.1_f32..=5.0)
+            .prefix("Beam 2 Length: ")
+            .suffix(" m"),
+    );
+    ui.add(
+        DragValue::new(&mut model.m_cart)
+            .speed(0.01)
+            .clamp_range(0.1_f32..=3.0)
+            .prefix("Cart Mass: ")
+            .suffix(" kg"),
+    );
+    ui.add(
+        DragValue::new(&mut model.m_ball1)
+            .speed(0.01)
+            .clamp_range(0.1_f32..=5.0)
+            .prefix("Ball 1 Mass: ")
+            .suffix(" kg"),
+    );
+    ui.add(
+        DragValue::new(&mut model.m_ball2)
+            .speed(0.01)
+            .clamp_range(0.1_f32..=5.0)
+            .prefix("Ball 2 Mass: ")
+            .suffix(" kg"),
+    );
+}
+
+fn initial_state<R: Rng + ?Sized>(rng: &mut R) -> State {
+    vector![0., 0., rand(rng, 0.1), 0., rand(rng, 0.1), 0.]
+}
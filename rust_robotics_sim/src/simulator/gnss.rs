@@ -0,0 +1,278 @@
+use super::localization::{draw_covariance, noise_options, odometry_options, State};
+use super::*;
+
+use crate::data::{IntoValues, TimeTable, VehiclePlot};
+use crate::item::draw_vehicle;
+
+use egui::plot::{Line, Points, Value, Values};
+use egui::{plot::PlotUi, DragValue, Slider, Ui};
+use rand::{rngs::StdRng, SeedableRng};
+use rb::localization::es_ekf::ESEKF;
+use rb::localization::particle_filter::{calc_input, motion_model};
+use rb::prelude::*;
+use rb::sensor::{gnss::Gnss, odometry::Odometry};
+use rust_robotics_algo as rb;
+
+const N: usize = 1000;
+
+/// Vehicle fusing GNSS fixes with wheel odometry and a gyroscope in an
+/// error-state EKF, next to GNSS alone and dead reckoning
+pub struct GnssFusion {
+    x_true: State,
+    x_dr: State,
+    /// Wheel encoders and IMU measuring the dead reckoning input
+    odometry: Odometry,
+    gnss: Gnss,
+    esekf: ESEKF,
+    /// Latest GNSS fix, held through outages as the GNSS only estimate
+    fix: rb::Vector2,
+    h_x_est: Vec<State>,
+    h_x_true: Vec<State>,
+    h_x_dr: Vec<State>,
+    /// GNSS fixes, along with whether the filter accepted them
+    h_fix: Vec<(rb::Vector2, bool)>,
+    /// Position error of the fused, GNSS only and dead reckoning estimates
+    diagnostics: TimeTable,
+    id: usize,
+    init_time: f32,
+    /// Seed of `rng`, used again on every [`reset_state`](Simulate::reset_state)
+    seed: u64,
+    rng: StdRng,
+}
+
+impl GnssFusion {
+    pub fn new(id: usize, time: f32) -> Self {
+        Self {
+            x_true: zeros!(4, 1),
+            x_dr: zeros!(4, 1),
+            odometry: Odometry::default(),
+            gnss: Gnss::default(),
+            esekf: ESEKF::default(),
+            fix: zeros!(2, 1),
+            h_x_est: vec![zeros!(4, 1)],
+            h_x_true: vec![zeros!(4, 1)],
+            h_x_dr: vec![zeros!(4, 1)],
+            h_fix: Vec::new(),
+            diagnostics: TimeTable::init_with_names(vec!["Fused Error", "GNSS Error", "DR Error"]),
+            id,
+            init_time: time,
+            seed: DEFAULT_SEED,
+            rng: StdRng::seed_from_u64(DEFAULT_SEED),
+        }
+    }
+
+    /// Draw sensor noise from `seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn update_history(&mut self) {
+        self.h_x_est.push(self.esekf.x_est.into());
+        self.h_x_true.push(self.x_true);
+        self.h_x_dr.push(self.x_dr);
+
+        if self.h_x_est.len() > N {
+            self.h_x_est.remove(0);
+            self.h_x_true.remove(0);
+            self.h_x_dr.remove(0);
+        }
+    }
+}
+
+impl Simulate for GnssFusion {
+    fn get_state(&self) -> &dyn std::any::Any {
+        &self.x_true
+    }
+    fn match_state_with(&mut self, other: &dyn Simulate) {
+        if let Some(data) = other.get_state().downcast_ref::<State>() {
+            // Then set self's data from `other` if the type matches
+            self.x_true.clone_from(data);
+        }
+    }
+    fn step(&mut self, dt: f32) {
+        let u = calc_input();
+        let ud = self.odometry.measure(&mut self.rng, u, dt);
+        self.x_true = motion_model(self.x_true, u, dt);
+        self.x_dr = motion_model(self.x_dr, ud, dt);
+        let z = self.gnss.measure(&mut self.rng, &self.x_true.xy(), dt);
+        self.esekf.update(z, ud, dt);
+        if let Some(z) = z {
+            self.fix = z;
+            self.h_fix.push((z, !self.esekf.rejected));
+            // Keep about as many seconds of fixes as of the other histories
+            let n_fix = (N as f32 * dt * self.gnss.clock.rate).ceil() as usize;
+            if self.h_fix.len() > n_fix {
+                self.h_fix.drain(..self.h_fix.len() - n_fix);
+            }
+        }
+
+        let position = self.x_true.xy();
+        self.diagnostics.add(
+            self.diagnostics.time_last() + dt,
+            vec![
+                (self.esekf.x_est.position() - position).norm(),
+                (self.fix - position).norm(),
+                (self.x_dr.xy() - position).norm(),
+            ],
+        );
+
+        self.update_history();
+    }
+    fn reset_state(&mut self) {
+        self.rng = StdRng::seed_from_u64(self.seed);
+        self.x_true = zeros!(4, 1);
+        self.x_dr = zeros!(4, 1);
+        self.odometry.reset();
+        self.gnss.reset();
+        self.esekf = self
+            .esekf
+            .with_state(VehicleState::default(), ESEKF::default().P);
+        self.esekf.bias = 0.0;
+        self.fix = zeros!(2, 1);
+        self.h_x_est = vec![zeros!(4, 1)];
+        self.h_x_true = vec![zeros!(4, 1)];
+        self.h_x_dr = vec![zeros!(4, 1)];
+        self.h_fix.clear();
+        self.diagnostics.clear();
+        self.init_time = 0.0;
+    }
+    fn reset_all(&mut self) {}
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+}
+
+impl Draw for GnssFusion {
+    fn plot(&self, plot_ui: &mut PlotUi) {
+        let names: Vec<String> = self
+            .diagnostics
+            .names()
+            .iter()
+            .map(|name| format!("{}_{}", name, self.id))
+            .collect();
+
+        (0..self.diagnostics.ncols()).for_each(|i| {
+            if let Some(values) = self.diagnostics.values_shifted(i, self.init_time, 0.0) {
+                plot_ui.line(Line::new(values).name(&names[i]));
+            }
+        });
+    }
+    fn scene(&self, plot_ui: &mut PlotUi) {
+        // Every fix makes the GNSS only trajectory, with the rejected ones
+        // overlaid on top
+        let fixes = |rejected_only: bool| {
+            Values::from_values(
+                self.h_fix
+                    .iter()
+                    .filter(|(_, accepted)| !(rejected_only && *accepted))
+                    .map(|(p, _)| Value::new(p.x, p.y))
+                    .collect(),
+            )
+        };
+        let name = format!("Vehicle {} (GNSS)", self.id);
+        plot_ui.line(Line::new(fixes(false)).width(0.5).name(&name));
+        plot_ui.points(Points::new(fixes(false)).radius(1.5).name(&name));
+        plot_ui.points(
+            Points::new(fixes(true))
+                .radius(3.0)
+                .name(format!("Vehicle {} (GNSS Rejected)", self.id)),
+        );
+
+        plot_ui.line(Line::new(self.h_x_true.positions()));
+        draw_vehicle(
+            plot_ui,
+            self.x_true,
+            &format!("Vehicle {} (Actual)", self.id),
+        );
+        plot_ui.line(Line::new(self.h_x_dr.positions()));
+        draw_vehicle(plot_ui, self.x_dr, &format!("Vehicle {} (DR)", self.id));
+        plot_ui.line(Line::new(self.h_x_est.positions()));
+        draw_vehicle(
+            plot_ui,
+            self.esekf.x_est,
+            &format!("Vehicle {} (ES-EKF)", self.id),
+        );
+        draw_covariance(
+            plot_ui,
+            &self.esekf.x_est,
+            &self.esekf.p_est(),
+            &format!("Vehicle {} (ES-EKF)", self.id),
+        );
+    }
+    fn options(&mut self, ui: &mut Ui) {
+        ui.group(|ui| {
+            ui.vertical(|ui| {
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label("GNSS:");
+                        gnss_options(ui, &mut self.gnss);
+                    });
+                });
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label("Odometry & IMU:");
+                        odometry_options(ui, &mut self.odometry);
+                    });
+                });
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label("ES-EKF:");
+                        let mut gated = self.esekf.gate.is_some();
+                        ui.checkbox(&mut gated, "Reject Outliers")
+                            .on_hover_text("Reject fixes whose NIS is beyond the chi-square gate");
+                        let mut gate = self.esekf.gate.unwrap_or(0.999);
+                        ui.add_enabled(
+                            gated,
+                            Slider::new(&mut gate, 0.9..=0.9999).text("Gate Probability"),
+                        );
+                        self.esekf.gate = gated.then_some(gate);
+                        let mut noise = sqrt(self.esekf.Q[(0, 0)]);
+                        if ui
+                            .add(Slider::new(&mut noise, 0.1..=5.0).text("Fix Std. Dev. [m]"))
+                            .changed()
+                        {
+                            self.esekf.Q = rb::Matrix2::identity() * noise.powi(2);
+                        }
+                        noise_options(ui, &mut self.esekf.R);
+                        ui.add(
+                            DragValue::new(&mut self.esekf.bias_random_walk)
+                                .speed(0.0001)
+                                .clamp_range(0.0_f32..=0.01)
+                                .prefix("Bias Walk [rad/s²/√Hz]: "),
+                        );
+                        ui.label(format!(
+                            "Yaw Rate Bias: {:.4} rad/s (Gyro {:.4})",
+                            self.esekf.bias,
+                            self.odometry.gyro.current_bias()
+                        ));
+                    });
+                });
+            });
+        });
+    }
+}
+
+/// Draw [`egui`] widgets for the noise, rate, outliers and outages of a GNSS receiver
+fn gnss_options(ui: &mut Ui, gnss: &mut Gnss) {
+    ui.add(Slider::new(&mut gnss.noise, 0.1..=5.0).text("Noise Std. Dev. [m]"));
+    ui.add(Slider::new(&mut gnss.clock.rate, 0.2..=20.0).text("Rate [Hz]"));
+    ui.add(Slider::new(&mut gnss.outlier_probability, 0.0..=0.5).text("Outlier Probability"));
+    ui.add(Slider::new(&mut gnss.outlier_noise, 1.0..=50.0).text("Outlier Std. Dev. [m]"));
+    ui.add(
+        DragValue::new(&mut gnss.outage_rate)
+            .speed(0.001)
+            .clamp_range(0.0_f32..=1.0)
+            .prefix("Outages [1/s]: "),
+    );
+    ui.add(Slider::new(&mut gnss.outage_duration, 0.5..=30.0).text("Outage Duration [s]"));
+    ui.horizontal(|ui| {
+        if ui.button("Start Outage").clicked() {
+            gnss.start_outage(gnss.outage_duration);
+        }
+        if gnss.in_outage() {
+            ui.label("No Fix");
+        }
+    });
+}
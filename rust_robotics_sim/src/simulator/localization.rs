@@ -1,67 +1,203 @@
 use super::*;
 
-use crate::data::{TimeTable, VehiclePlot};
-use crate::item::draw_vehicle;
+use crate::data::{IntoValues, TimeTable, VehiclePlot};
+use crate::item::{draw_vehicle, Ellipse, Heatmap};
 
 use egui::plot::Line;
-use egui::{plot::PlotUi, ComboBox, DragValue, Ui};
-use rb::localization::{particle_filter::*, StateVector};
+use egui::{plot::PlotUi, Color32, ComboBox, DragValue, Slider, Ui};
+use rand::{rngs::StdRng, SeedableRng};
+use rb::localization::{
+    ekf::EKF,
+    histogram_filter::{HistogramFilter, GRID_MAX, GRID_MIN},
+    particle_filter::*,
+    ukf::UKF,
+    StateVector,
+};
+use rb::mapping::{
+    occupancy_grid::{MAP_MAX, MAP_MIN},
+    scan_matching::{Icp, IcpMetric, Ndt, ScanMatcher, ScanOdometry},
+    OccupancyGrid, Polygon, Segment, World,
+};
 use rb::prelude::*;
+use rb::sensor::{
+    lidar::{Lidar, Scan},
+    odometry::Odometry,
+};
 use rust_robotics_algo as rb;
 
 pub type State = rb::Vector4;
 
 const N: usize = 1000;
+/// Time between the scans registered by the scan matching odometry [s]
+const SCAN_PERIOD: f32 = 0.1;
 
-const MARKERS: [rb::Vector2; 4] = [
+/// True positions of landmarks
+pub const MARKERS: [rb::Vector2; 4] = [
     vector![10.0_f32, 0.0_f32],
     vector![10.0, 10.0],
     vector![0.0, 15.0],
     vector![-5.0, 20.0],
 ];
 
+/// Walls around the landmarks, with a few obstacles inside, seen by the lidar
+pub fn walls() -> World {
+    World::default()
+        .with_polygon(&Polygon::rectangle(GRID_MIN.into(), GRID_MAX.into()))
+        .with_polygon(&Polygon::rectangle(vector![-2.0, 8.0], vector![2.0, 12.0]))
+        .with_polygon(&Polygon::new(vec![
+            vector![11.0, 16.0],
+            vector![14.0, 16.0],
+            vector![12.5, 20.0],
+        ]))
+        .with_segment(Segment::new(vector![-13.0, -2.0], vector![-8.0, -2.0]))
+}
+
 pub struct ParticleFilter {
-    x_est: State,
     x_true: State,
     x_dr: State,
-    p_est: rb::Matrix3,
-    pw: PW,
-    px: PX,
+    pf: pf::ParticleFilter,
+    ekf: EKF,
+    /// Whether to show the EKF estimate next to the particle filter
+    show_ekf: bool,
+    ukf: UKF,
+    /// Whether to show the UKF estimate next to the particle filter
+    show_ukf: bool,
+    grid: HistogramFilter,
+    /// Whether to show the histogram filter estimate and its belief as a heatmap
+    show_grid: bool,
+    heatmap: Heatmap,
+    sensor: Sensor,
+    /// Wheel encoders and IMU measuring the dead reckoning input
+    wheel_odometry: Odometry,
+    /// Whether the input is measured by `wheel_odometry`, rather than perturbed
+    /// by the white noise of `sensor`
+    use_wheel_odometry: bool,
+    lidar: Lidar,
+    /// Latest lidar scan from the true state
+    scan: Scan,
+    world: World,
+    /// Whether to show the walls and the lidar beams
+    show_lidar: bool,
+    /// Occupancy grid mapped from the lidar scans at the true poses
+    map: OccupancyGrid,
+    /// Whether to show the occupancy grid as an image
+    show_map: bool,
+    map_layer: Heatmap,
+    /// Pose chained from registering each lidar scan onto the one before
+    odometry: ScanOdometry,
+    /// Dead reckoning at the last registered scan, guessing the motion since
+    odometry_dr: State,
+    /// Time since the last registered scan [s]
+    odometry_timer: f32,
+    /// Whether to show the scan matching odometry
+    show_odometry: bool,
     h_x_est: Vec<State>,
+    h_x_ekf: Vec<State>,
+    h_x_ukf: Vec<State>,
+    h_x_grid: Vec<State>,
     h_x_true: Vec<State>,
     h_x_dr: Vec<State>,
+    h_x_odometry: Vec<State>,
+    /// Effective and active number of particles, and resampling events
+    diagnostics: TimeTable,
     id: usize,
     init_time: f32,
+    /// Seed of `rng`, used again on every [`reset_state`](Simulate::reset_state)
+    seed: u64,
+    rng: StdRng,
 }
 
 impl ParticleFilter {
     pub fn new(id: usize, time: f32) -> Self {
         Self {
-            x_est: zeros!(4, 1),
             x_true: zeros!(4, 1),
             x_dr: zeros!(4, 1),
-            p_est: zeros!(3, 3),
-            pw: ones!(1, NP) * (1. / NP as f32),
-            px: zeros!(4, NP),
+            pf: pf::ParticleFilter::default(),
+            ekf: EKF::default(),
+            show_ekf: true,
+            ukf: UKF::default(),
+            show_ukf: false,
+            grid: HistogramFilter::default(),
+            show_grid: false,
+            heatmap: Heatmap::default(),
+            sensor: Sensor::default(),
+            wheel_odometry: Odometry::default(),
+            use_wheel_odometry: true,
+            lidar: Lidar::default(),
+            scan: Scan::default(),
+            world: walls(),
+            show_lidar: false,
+            map: OccupancyGrid::default(),
+            show_map: false,
+            map_layer: Heatmap::default().with_color(Color32::from_gray(40)),
+            odometry: ScanOdometry::default(),
+            odometry_dr: zeros!(4, 1),
+            odometry_timer: 0.0,
+            show_odometry: true,
             h_x_est: vec![zeros!(4, 1)],
+            h_x_ekf: vec![zeros!(4, 1)],
+            h_x_ukf: vec![zeros!(4, 1)],
+            h_x_grid: vec![zeros!(4, 1)],
             h_x_true: vec![zeros!(4, 1)],
             h_x_dr: vec![zeros!(4, 1)],
+            h_x_odometry: vec![zeros!(4, 1)],
+            diagnostics: TimeTable::init_with_names(vec![
+                "Effective Particles",
+                "Particles",
+                "Resampled",
+            ]),
             id,
             init_time: time,
+            seed: DEFAULT_SEED,
+            rng: StdRng::seed_from_u64(DEFAULT_SEED),
         }
     }
 
+    /// Draw sensor and particle noise from `seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
     pub fn update_history(&mut self) {
-        self.h_x_est.push(self.x_est);
+        self.h_x_est.push(self.pf.x_est.into());
+        self.h_x_ekf.push(self.ekf.x_est.into());
+        self.h_x_ukf.push(self.ukf.x_est.into());
+        self.h_x_grid.push(self.grid.x_est.into());
         self.h_x_true.push(self.x_true);
         self.h_x_dr.push(self.x_dr);
+        self.h_x_odometry.push(self.x_odometry());
 
         if self.h_x_est.len() > N {
             self.h_x_est.remove(0);
+            self.h_x_ekf.remove(0);
+            self.h_x_ukf.remove(0);
+            self.h_x_grid.remove(0);
             self.h_x_true.remove(0);
             self.h_x_dr.remove(0);
+            self.h_x_odometry.remove(0);
         }
     }
+
+    /// State of the scan matching odometry, with the velocity of dead reckoning
+    fn x_odometry(&self) -> State {
+        VehicleState::from_pose(self.odometry.pose, self.x_dr.v()).into()
+    }
+
+    /// Register the latest scan every [`SCAN_PERIOD`], starting from the motion
+    /// of dead reckoning since the previous one
+    fn update_odometry(&mut self, dt: f32) {
+        self.odometry_timer += dt;
+        if self.odometry_timer < SCAN_PERIOD {
+            return;
+        }
+        self.odometry_timer = 0.0;
+        let from = VehicleState::from(self.odometry_dr).pose();
+        let to = VehicleState::from(self.x_dr).pose();
+        self.odometry.update(self.scan.points(), &from.between(&to));
+        self.odometry_dr = self.x_dr;
+    }
 }
 
 impl Simulate for ParticleFilter {
@@ -76,30 +212,144 @@ impl Simulate for ParticleFilter {
     }
     fn step(&mut self, dt: f32) {
         let u = calc_input();
-        let (z, ud) = observation(&mut self.x_true, &mut self.x_dr, u, &MARKERS, dt);
-        self.p_est = pf_localization(&mut self.x_est, &mut self.px, &mut self.pw, z, ud, dt);
+        let (z, ud) = if self.use_wheel_odometry {
+            let ud = self.wheel_odometry.measure(&mut self.rng, u, dt);
+            self.x_true = motion_model(self.x_true, u, dt);
+            self.x_dr = motion_model(self.x_dr, ud, dt);
+            let z = self.sensor.ranges(&mut self.rng, &self.x_true, &MARKERS);
+            (z, ud)
+        } else {
+            self.sensor.observe(
+                &mut self.rng,
+                &mut self.x_true,
+                &mut self.x_dr,
+                u,
+                &MARKERS,
+                dt,
+            )
+        };
+        let pose = VehicleState::from(self.x_true).pose();
+        self.scan = self.lidar.scan(&mut self.rng, &pose, &self.world);
+        self.map.update(&pose, &self.scan);
+        self.update_odometry(dt);
+        self.pf.update(&mut self.rng, &z, ud, dt);
+        // Kalman filters use the same observations and noisy inputs as the particle filter
+        self.ekf.update(&z, ud, dt);
+        self.ukf.update(&z, ud, dt);
+        // The grid only spans position, so the heading is taken as known
+        self.grid.update(&z, ud, self.x_true.phi(), dt);
+
+        let Diagnostics {
+            n_eff,
+            resampled,
+            num_particles,
+        } = self.pf.diagnostics;
+        self.diagnostics.add(
+            self.diagnostics.time_last() + dt,
+            vec![
+                n_eff,
+                num_particles as f32,
+                if resampled { 1.0 } else { 0.0 },
+            ],
+        );
 
         self.update_history();
     }
-    fn reset_state(&mut self) {}
+    fn reset_state(&mut self) {
+        self.rng = StdRng::seed_from_u64(self.seed);
+        self.x_true = zeros!(4, 1);
+        self.x_dr = zeros!(4, 1);
+        self.wheel_odometry.reset();
+        self.scan = Scan::default();
+        self.map.reset();
+        self.odometry.reset(Pose2::identity());
+        self.odometry_dr = zeros!(4, 1);
+        self.odometry_timer = 0.0;
+        self.pf.x_est = VehicleState::default();
+        self.pf.set_num_particles(self.pf.num_particles());
+        self.ekf = self
+            .ekf
+            .with_state(VehicleState::default(), EKF::default().P);
+        self.h_x_est = vec![zeros!(4, 1)];
+        self.ukf = self
+            .ukf
+            .with_state(VehicleState::default(), UKF::default().P);
+        self.h_x_ekf = vec![zeros!(4, 1)];
+        self.h_x_ukf = vec![zeros!(4, 1)];
+        self.grid.reset();
+        self.grid.x_est = VehicleState::default();
+        self.h_x_grid = vec![zeros!(4, 1)];
+        self.h_x_true = vec![zeros!(4, 1)];
+        self.h_x_dr = vec![zeros!(4, 1)];
+        self.h_x_odometry = vec![zeros!(4, 1)];
+        self.diagnostics.clear();
+        self.init_time = 0.0;
+    }
     fn reset_all(&mut self) {}
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
 }
 
 impl Draw for ParticleFilter {
-    fn plot(&self, _plot_ui: &mut PlotUi) {}
+    fn plot(&self, plot_ui: &mut PlotUi) {
+        let names: Vec<String> = self
+            .diagnostics
+            .names()
+            .iter()
+            .map(|name| format!("{}_{}", name, self.id))
+            .collect();
+
+        (0..self.diagnostics.ncols()).for_each(|i| {
+            self.diagnostics
+                .values_shifted(i, self.init_time, 0.0)
+                .map(|values| plot_ui.line(Line::new(values).name(&names[i])));
+        });
+    }
     fn scene(&self, plot_ui: &mut PlotUi) {
+        if self.show_grid {
+            self.heatmap.draw(
+                plot_ui,
+                &self.grid.belief,
+                [self.grid.min.x as f64, self.grid.min.y as f64],
+                self.grid.resolution as f64,
+                &format!("Belief {} (Histogram)", self.id),
+            );
+        }
+        if self.show_map {
+            self.map_layer.draw(
+                plot_ui,
+                &self.map.probabilities(),
+                [
+                    self.map.log_odds.min.x as f64,
+                    self.map.log_odds.min.y as f64,
+                ],
+                self.map.resolution() as f64,
+                &format!("Occupancy Grid {}", self.id),
+            );
+        }
+        if self.show_lidar {
+            draw_walls(plot_ui, &self.world, "Walls");
+            draw_scan(
+                plot_ui,
+                &self.scan,
+                &self.x_true,
+                &format!("Lidar {}", self.id),
+            );
+        }
         plot_ui.points(egui::plot::Points::new(Values::from_values(
-            self.px
-                .column_iter()
+            self.pf
+                .px
+                .iter()
                 .map(|state| Value {
-                    x: *state.get(0).unwrap() as f64,
-                    y: *state.get(1).unwrap() as f64,
+                    x: state.x as f64,
+                    y: state.y as f64,
                 })
                 .collect(),
         )));
         MARKERS.iter().for_each(|marker| {
             plot_ui.points(egui::plot::Points::new(marker_values()).radius(2.0));
-            if is_detected(marker, &self.x_true) {
+            if self.sensor.is_detected(&self.x_true, marker) {
                 plot_ui.line(
                     Line::new(values_from_marker_state(marker, &self.x_true))
                         .style(plot::LineStyle::Dotted { spacing: 10.0 }),
@@ -114,29 +364,469 @@ impl Draw for ParticleFilter {
         );
         plot_ui.line(Line::new(self.h_x_dr.positions()));
         draw_vehicle(plot_ui, self.x_dr, &format!("Vehicle {} (DR)", self.id));
+        if self.show_odometry {
+            plot_ui.line(Line::new(self.h_x_odometry.positions()));
+            draw_vehicle(
+                plot_ui,
+                self.x_odometry(),
+                &format!("Vehicle {} (Scan Matching)", self.id),
+            );
+        }
         plot_ui.line(Line::new(self.h_x_est.positions()));
         draw_vehicle(
             plot_ui,
-            self.x_est,
+            self.pf.x_est,
+            &format!("Vehicle {} (Estimate)", self.id),
+        );
+        draw_covariance(
+            plot_ui,
+            &self.pf.x_est,
+            &self.pf.p_est,
             &format!("Vehicle {} (Estimate)", self.id),
         );
+        if self.show_ekf {
+            plot_ui.line(Line::new(self.h_x_ekf.positions()));
+            draw_vehicle(
+                plot_ui,
+                self.ekf.x_est,
+                &format!("Vehicle {} (EKF)", self.id),
+            );
+            draw_covariance(
+                plot_ui,
+                &self.ekf.x_est,
+                &self.ekf.p_est(),
+                &format!("Vehicle {} (EKF)", self.id),
+            );
+        }
+        if self.show_ukf {
+            plot_ui.line(Line::new(self.h_x_ukf.positions()));
+            draw_vehicle(
+                plot_ui,
+                self.ukf.x_est,
+                &format!("Vehicle {} (UKF)", self.id),
+            );
+            draw_covariance(
+                plot_ui,
+                &self.ukf.x_est,
+                &self.ukf.p_est(),
+                &format!("Vehicle {} (UKF)", self.id),
+            );
+        }
+        if self.show_grid {
+            plot_ui.line(Line::new(self.h_x_grid.positions()));
+            draw_vehicle(
+                plot_ui,
+                self.grid.x_est,
+                &format!("Vehicle {} (Histogram)", self.id),
+            );
+        }
+    }
+    fn options(&mut self, ui: &mut Ui) {
+        ui.group(|ui| {
+            ui.vertical(|ui| {
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label("Sensor:");
+                        ui.add(
+                            Slider::new(&mut self.sensor.max_range, 1.0..=50.0)
+                                .text("Max Range [m]"),
+                        );
+                        ui.add(
+                            DragValue::new(&mut self.sensor.Q[0])
+                                .speed(0.01)
+                                .clamp_range(0.001_f32..=10.0)
+                                .prefix("Range Variance: "),
+                        );
+                        ui.checkbox(&mut self.use_wheel_odometry, "Odometry & IMU:");
+                        if self.use_wheel_odometry {
+                            odometry_options(ui, &mut self.wheel_odometry);
+                        } else {
+                            noise_options(ui, &mut self.sensor.R);
+                        }
+                    });
+                });
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.checkbox(&mut self.show_lidar, "Lidar:");
+                        lidar_options(ui, &mut self.lidar);
+                        ui.checkbox(&mut self.show_map, "Occupancy Grid");
+                        let mut resolution = self.map.resolution();
+                        if ui
+                            .add(Slider::new(&mut resolution, 0.1..=1.0).text("Resolution [m]"))
+                            .changed()
+                        {
+                            self.map =
+                                OccupancyGrid::new(MAP_MIN.into(), MAP_MAX.into(), resolution);
+                        }
+                        ui.checkbox(&mut self.show_odometry, "Scan Matching");
+                        scan_matcher_options(ui, self.id, &mut self.odometry.matcher);
+                    });
+                });
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label("Particle Filter:");
+                        let mut np = self.pf.num_particles();
+                        if ui
+                            .add(Slider::new(&mut np, 10..=2000).text("Particles"))
+                            .changed()
+                        {
+                            self.pf.set_num_particles(np);
+                        }
+                        resampling_options(ui, self.id, &mut self.pf);
+                        kld_options(ui, &mut self.pf);
+                        ui.add(
+                            DragValue::new(&mut self.pf.Q[0])
+                                .speed(0.01)
+                                .clamp_range(0.001_f32..=10.0)
+                                .prefix("Range Variance: "),
+                        );
+                        noise_options(ui, &mut self.pf.R);
+                    });
+                });
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.checkbox(&mut self.show_ekf, "EKF:");
+                        ui.add(
+                            DragValue::new(&mut self.ekf.Q[0])
+                                .speed(0.01)
+                                .clamp_range(0.001_f32..=10.0)
+                                .prefix("Range Variance: "),
+                        );
+                        noise_options(ui, &mut self.ekf.R);
+                    });
+                });
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.checkbox(&mut self.show_ukf, "UKF:");
+                        ui.add(
+                            DragValue::new(&mut self.ukf.alpha)
+                                .speed(0.01)
+                                .clamp_range(0.1_f32..=2.0)
+                                .prefix("Alpha: "),
+                        );
+                        ui.add(
+                            DragValue::new(&mut self.ukf.beta)
+                                .speed(0.01)
+                                .clamp_range(0.0_f32..=5.0)
+                                .prefix("Beta: "),
+                        );
+                        ui.add(
+                            DragValue::new(&mut self.ukf.kappa)
+                                .speed(0.01)
+                                .clamp_range(0.0_f32..=5.0)
+                                .prefix("Kappa: "),
+                        );
+                        ui.add(
+                            DragValue::new(&mut self.ukf.Q[0])
+                                .speed(0.01)
+                                .clamp_range(0.001_f32..=10.0)
+                                .prefix("Range Variance: "),
+                        );
+                        noise_options(ui, &mut self.ukf.R);
+                    });
+                });
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.checkbox(&mut self.show_grid, "Histogram Filter:");
+                        let mut resolution = self.grid.resolution;
+                        if ui
+                            .add(Slider::new(&mut resolution, 0.25..=2.0).text("Resolution [m]"))
+                            .changed()
+                        {
+                            self.grid =
+                                HistogramFilter::new(GRID_MIN.into(), GRID_MAX.into(), resolution)
+                                    .with_noise(self.grid.Q, self.grid.R);
+                            self.h_x_grid.clear();
+                        }
+                        ui.add(
+                            DragValue::new(&mut self.grid.Q[0])
+                                .speed(0.01)
+                                .clamp_range(0.001_f32..=10.0)
+                                .prefix("Range Variance: "),
+                        );
+                        ui.add(
+                            DragValue::new(&mut self.grid.R[(0, 0)])
+                                .speed(0.01)
+                                .clamp_range(0.001_f32..=10.0)
+                                .prefix("Velocity Variance: "),
+                        );
+                    });
+                });
+            });
+        });
     }
-    fn options(&mut self, ui: &mut Ui) {}
 }
 
-fn is_detected(marker: &rb::Vector2, state: &rb::Vector4) -> bool {
-    let dx = state.x() - marker.x();
-    let dy = state.y() - marker.y();
-    let d = hypot(dx, dy);
-    d <= MAX_RANGE
+/// Draw [`egui`] widgets for the resampling algorithm and its trigger
+fn resampling_options(ui: &mut Ui, id: usize, pf: &mut pf::ParticleFilter) {
+    // ui.push_id is used here to create unique ID for each `ComboBox`
+    ui.push_id(id, |ui| {
+        ComboBox::from_label("Resampling")
+            .selected_text(pf.resampling.name())
+            .show_ui(ui, |ui| {
+                for option in Resampling::ALL {
+                    ui.selectable_value(&mut pf.resampling, option, option.name());
+                }
+            });
+        ComboBox::from_label("Trigger")
+            .selected_text(pf.trigger.name())
+            .show_ui(ui, |ui| {
+                for option in [
+                    ResampleTrigger::Always,
+                    ResampleTrigger::EffectiveParticles(0.5),
+                    ResampleTrigger::Period(10),
+                ] {
+                    if ui
+                        .selectable_label(pf.trigger.name() == option.name(), option.name())
+                        .clicked()
+                    {
+                        pf.trigger = option;
+                    }
+                }
+            });
+    });
+    match &mut pf.trigger {
+        ResampleTrigger::Always => {}
+        ResampleTrigger::EffectiveParticles(ratio) => {
+            ui.add(Slider::new(ratio, 0.0..=1.0).text("Threshold (ratio)"));
+        }
+        ResampleTrigger::Period(period) => {
+            ui.add(Slider::new(period, 1..=100).text("Period (steps)"));
+        }
+    }
+}
+
+/// Draw [`egui`] widgets to toggle KLD-sampling and set its bounds
+fn kld_options(ui: &mut Ui, pf: &mut pf::ParticleFilter) {
+    let mut enabled = pf.kld.is_some();
+    if ui
+        .checkbox(&mut enabled, "KLD-Sampling")
+        .on_hover_text("Adapt the number of particles, resampling on every update")
+        .changed()
+    {
+        pf.kld =
+            enabled.then(|| KldSampling::default().with_bounds(50, pf.num_particles().max(50)));
+    }
+    if let Some(kld) = &mut pf.kld {
+        ui.add(Slider::new(&mut kld.epsilon, 0.01..=0.5).text("Epsilon"));
+        ui.add(Slider::new(&mut kld.min_particles, 10..=2000).text("Min Particles"));
+        ui.add(Slider::new(&mut kld.max_particles, 10..=5000).text("Max Particles"));
+        kld.max_particles = kld.max_particles.max(kld.min_particles);
+    }
+}
+
+/// Draw [`egui`] widgets for the scan matching algorithm and its parameters
+fn scan_matcher_options(ui: &mut Ui, id: usize, matcher: &mut ScanMatcher) {
+    // ui.push_id is used here to create unique ID for each `ComboBox`
+    ui.push_id(("scan matching", id), |ui| {
+        ComboBox::from_label("Matcher")
+            .selected_text(matcher.name())
+            .show_ui(ui, |ui| {
+                for option in [
+                    ScanMatcher::Icp(Icp::new(IcpMetric::PointToPoint)),
+                    ScanMatcher::Icp(Icp::new(IcpMetric::PointToPlane)),
+                    ScanMatcher::Ndt(Ndt::default().with_resolution(2.0)),
+                ] {
+                    if ui
+                        .selectable_label(matcher.name() == option.name(), option.name())
+                        .clicked()
+                    {
+                        *matcher = option;
+                    }
+                }
+            });
+    });
+    match matcher {
+        ScanMatcher::Icp(icp) => {
+            ui.add(Slider::new(&mut icp.max_distance, 0.1..=5.0).text("Max Distance [m]"));
+            ui.add(Slider::new(&mut icp.max_iterations, 1..=100).text("Max Iterations"));
+        }
+        ScanMatcher::Ndt(ndt) => {
+            ui.add(Slider::new(&mut ndt.resolution, 0.25..=3.0).text("Cell Size [m]"));
+            ui.add(Slider::new(&mut ndt.max_iterations, 1..=100).text("Max Iterations"));
+        }
+    }
+}
+
+/// Draw [`egui`] widgets for the errors and rates of the wheel encoders and IMU
+pub(super) fn odometry_options(ui: &mut Ui, odometry: &mut Odometry) {
+    let encoders = &mut odometry.encoders;
+    ui.add(Slider::new(&mut encoders.ticks_per_revolution, 16..=4096).text("Ticks per Revolution"));
+    ui.add(Slider::new(&mut encoders.clock.rate, 1.0..=100.0).text("Encoder Rate [Hz]"));
+    ui.add(
+        DragValue::new(&mut encoders.radius_error[0])
+            .speed(0.001)
+            .clamp_range(-0.1_f32..=0.1)
+            .prefix("Left Radius Error: "),
+    );
+    ui.add(
+        DragValue::new(&mut encoders.radius_error[1])
+            .speed(0.001)
+            .clamp_range(-0.1_f32..=0.1)
+            .prefix("Right Radius Error: "),
+    );
+    ui.add(
+        DragValue::new(&mut encoders.slip_density)
+            .speed(0.001)
+            .clamp_range(0.0_f32..=1.0)
+            .prefix("Slip [m/s/√Hz]: "),
+    );
+
+    ui.checkbox(&mut odometry.use_gyro, "Gyroscope Yaw Rate");
+    let gyro = &mut odometry.gyro;
+    ui.add(Slider::new(&mut gyro.clock.rate, 1.0..=100.0).text("IMU Rate [Hz]"));
+    odometry.accelerometer.clock.rate = gyro.clock.rate;
+    ui.add(
+        DragValue::new(&mut gyro.noise_density)
+            .speed(0.0001)
+            .clamp_range(0.0_f32..=0.1)
+            .prefix("Gyro Noise [rad/s/√Hz]: "),
+    );
+    ui.add(
+        DragValue::new(&mut gyro.bias_random_walk)
+            .speed(0.0001)
+            .clamp_range(0.0_f32..=0.01)
+            .prefix("Gyro Bias Walk [rad/s²/√Hz]: "),
+    );
+    ui.add(
+        DragValue::new(&mut gyro.bias)
+            .speed(0.001)
+            .clamp_range(-0.1_f32..=0.1)
+            .prefix("Gyro Bias [rad/s]: "),
+    );
+    ui.add(
+        DragValue::new(&mut gyro.scale_factor)
+            .speed(0.001)
+            .clamp_range(-0.1_f32..=0.1)
+            .prefix("Gyro Scale Factor: "),
+    );
+    let accelerometer = &mut odometry.accelerometer;
+    ui.add(
+        DragValue::new(&mut accelerometer.noise_density)
+            .speed(0.001)
+            .clamp_range(0.0_f32..=0.1)
+            .prefix("Accel Noise [m/s²/√Hz]: "),
+    );
+    ui.add(
+        DragValue::new(&mut accelerometer.bias_random_walk)
+            .speed(0.0001)
+            .clamp_range(0.0_f32..=0.01)
+            .prefix("Accel Bias Walk [m/s³/√Hz]: "),
+    );
+    ui.add(
+        DragValue::new(&mut accelerometer.bias[0])
+            .speed(0.001)
+            .clamp_range(-0.5_f32..=0.5)
+            .prefix("Accel Bias Along [m/s²]: "),
+    );
+    ui.add(
+        DragValue::new(&mut accelerometer.bias[1])
+            .speed(0.001)
+            .clamp_range(-0.5_f32..=0.5)
+            .prefix("Accel Bias Across [m/s²]: "),
+    );
+    ui.add(
+        DragValue::new(&mut accelerometer.scale_factor)
+            .speed(0.001)
+            .clamp_range(-0.1_f32..=0.1)
+            .prefix("Accel Scale Factor: "),
+    );
+}
+
+/// Draw [`egui`] widgets for the beams, range and noise of a lidar
+pub(super) fn lidar_options(ui: &mut Ui, lidar: &mut Lidar) {
+    ui.add(Slider::new(&mut lidar.num_beams, 1..=720).text("Beams"));
+    let mut fov = lidar.fov.to_degrees();
+    if ui
+        .add(Slider::new(&mut fov, 10.0..=360.0).text("Field of View [deg]"))
+        .changed()
+    {
+        lidar.fov = fov.to_radians();
+    }
+    ui.add(Slider::new(&mut lidar.max_range, 1.0..=30.0).text("Max Range [m]"));
+    ui.add(
+        DragValue::new(&mut lidar.Q[0])
+            .speed(0.001)
+            .clamp_range(0.0_f32..=1.0)
+            .prefix("Range Variance: "),
+    );
+}
+
+/// Draw the walls of `world` as lines
+pub(super) fn draw_walls(plot_ui: &mut PlotUi, world: &World, name: &str) {
+    world.walls.iter().for_each(|wall| {
+        plot_ui.line(
+            Line::new(Values::from_values(vec![
+                Value::new(wall.a.x, wall.a.y),
+                Value::new(wall.b.x, wall.b.y),
+            ]))
+            .width(2.0)
+            .name(name),
+        );
+    });
+}
+
+/// Draw the beams of `scan` which hit something, from the vehicle at `state`
+pub(super) fn draw_scan(plot_ui: &mut PlotUi, scan: &Scan, state: &State, name: &str) {
+    let pose = VehicleState::from(*state).pose();
+    let points = scan.points_from(&pose);
+    points.iter().for_each(|p| {
+        plot_ui.line(
+            Line::new(Values::from_values(vec![
+                Value::new(pose.x, pose.y),
+                Value::new(p.x, p.y),
+            ]))
+            .width(0.5)
+            .name(name),
+        );
+    });
+    plot_ui.points(
+        egui::plot::Points::new(Values::from_values(
+            points.iter().map(|p| Value::new(p.x, p.y)).collect(),
+        ))
+        .radius(1.5)
+        .name(name),
+    );
+}
+
+/// Draw [`egui`] widgets for the diagonal of input noise covariance
+pub(super) fn noise_options(ui: &mut Ui, cov: &mut rb::Matrix2) {
+    ui.add(
+        DragValue::new(&mut cov[(0, 0)])
+            .speed(0.01)
+            .clamp_range(0.001_f32..=10.0)
+            .prefix("Velocity Variance: "),
+    );
+    ui.add(
+        DragValue::new(&mut cov[(1, 1)])
+            .speed(0.01)
+            .clamp_range(0.001_f32..=10.0)
+            .prefix("Yaw Rate Variance: "),
+    );
+}
+
+/// Draw the 2-sigma ellipse of the position covariance in `p_est`
+pub(super) fn draw_covariance(
+    plot_ui: &mut PlotUi,
+    x: &impl StateVector,
+    p_est: &rb::Matrix3,
+    name: &str,
+) {
+    let cov = p_est.fixed_slice::<2, 2>(0, 0).into_owned();
+    plot_ui.polygon(
+        Ellipse::from_covariance(&cov, 2.0)
+            .at(x.x() as f64, x.y() as f64)
+            .into_polygon()
+            .name(name),
+    );
 }
 
 use egui::plot::{Value, Values};
-fn values_from_marker_state(marker: &rb::Vector2, state: &rb::Vector4) -> Values {
+pub(super) fn values_from_marker_state(marker: &rb::Vector2, state: &rb::Vector4) -> Values {
     Values::from_values(vec![
         Value {
-            x: marker.x() as f64,
-            y: marker.y() as f64,
+            x: marker.x as f64,
+            y: marker.y as f64,
         },
         Value {
             x: state.x() as f64,
@@ -145,13 +835,13 @@ fn values_from_marker_state(marker: &rb::Vector2, state: &rb::Vector4) -> Values
     ])
 }
 
-fn marker_values() -> Values {
+pub(super) fn marker_values() -> Values {
     Values::from_values(
         MARKERS
             .iter()
             .map(|marker| Value {
-                x: marker.x() as f64,
-                y: marker.y() as f64,
+                x: marker.x as f64,
+                y: marker.y as f64,
             })
             .collect(),
     )
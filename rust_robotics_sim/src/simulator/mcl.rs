@@ -0,0 +1,352 @@
+use super::localization::{draw_covariance, draw_scan, lidar_options, noise_options, walls, State};
+use super::*;
+
+use crate::data::{IntoValues, TimeTable, VehiclePlot};
+use crate::item::{draw_vehicle, Heatmap};
+
+use egui::plot::{Line, Points, Value, Values};
+use egui::{plot::PlotUi, Color32, ComboBox, Slider, Ui};
+use rand::{rngs::StdRng, SeedableRng};
+use rb::localization::mcl::{ScanModel, ScanModelKind};
+use rb::localization::particle_filter::{
+    calc_input, motion_model, Diagnostics, ParticleFilter, Sensor,
+};
+use rb::localization::StateVector;
+use rb::mapping::{occupancy_grid::MAP_RESOLUTION, OccupancyGrid, World};
+use rb::prelude::*;
+use rb::sensor::lidar::{Lidar, Scan};
+use rust_robotics_algo as rb;
+
+const N: usize = 1000;
+
+/// Lower left corner of the surveyed map [m], a meter beyond the outer walls
+/// of [`walls`] so that they are mapped inside the grid
+const MAP_MIN: [f32; 2] = [-16.0, -6.0];
+/// Upper right corner of the surveyed map [m]
+const MAP_MAX: [f32; 2] = [16.0, 26.0];
+
+/// Occupancy grid of `world`, as mapped by a long range lidar at known poses
+/// along the path of the vehicle
+pub fn survey(world: &World) -> OccupancyGrid {
+    let lidar = Lidar::default().with_max_range(30.0);
+    let mut map = OccupancyGrid::new(MAP_MIN.into(), MAP_MAX.into(), MAP_RESOLUTION);
+    let mut x = zeros!(4, 1);
+    for _ in 0..64 {
+        x = motion_model(x, calc_input(), 1.0);
+        let pose = VehicleState::from(x).pose();
+        map.update(&pose, &lidar.cast(&pose, world));
+    }
+    map
+}
+
+/// Vehicle localizing with a lidar against an occupancy grid, by Monte Carlo
+/// localization
+///
+/// The lidar is simulated against the same grid, so that a loaded map is also
+/// the world the vehicle drives in.
+pub struct Mcl {
+    x_true: State,
+    x_dr: State,
+    pf: ParticleFilter,
+    model: ScanModel,
+    /// Moves the vehicles, along with noisy odometry input
+    sensor: Sensor,
+    lidar: Lidar,
+    /// Latest lidar scan from the true state
+    scan: Scan,
+    map: OccupancyGrid,
+    map_layer: Heatmap,
+    /// YAML file of the map to load or save
+    #[cfg(not(target_arch = "wasm32"))]
+    map_path: String,
+    /// Outcome of the last load or save of the map
+    #[cfg(not(target_arch = "wasm32"))]
+    map_status: String,
+    h_x_est: Vec<State>,
+    h_x_true: Vec<State>,
+    h_x_dr: Vec<State>,
+    /// Effective number of particles and position error of the estimate
+    diagnostics: TimeTable,
+    id: usize,
+    init_time: f32,
+    /// Seed of `rng`, used again on every [`reset_state`](Simulate::reset_state)
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Mcl {
+    pub fn new(id: usize, time: f32) -> Self {
+        let map = survey(&walls());
+        Self {
+            x_true: zeros!(4, 1),
+            x_dr: zeros!(4, 1),
+            pf: ParticleFilter::new(300),
+            model: ScanModel::new(ScanModelKind::LikelihoodField, &map),
+            sensor: Sensor::default(),
+            lidar: Lidar::default(),
+            scan: Scan::default(),
+            map,
+            map_layer: Heatmap::default().with_color(Color32::from_gray(40)),
+            #[cfg(not(target_arch = "wasm32"))]
+            map_path: "map.yaml".to_string(),
+            #[cfg(not(target_arch = "wasm32"))]
+            map_status: String::new(),
+            h_x_est: vec![zeros!(4, 1)],
+            h_x_true: vec![zeros!(4, 1)],
+            h_x_dr: vec![zeros!(4, 1)],
+            diagnostics: TimeTable::init_with_names(vec!["Effective Particles", "Position Error"]),
+            id,
+            init_time: time,
+            seed: DEFAULT_SEED,
+            rng: StdRng::seed_from_u64(DEFAULT_SEED),
+        }
+    }
+
+    /// Draw sensor and particle noise from `seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn update_history(&mut self) {
+        self.h_x_est.push(self.pf.x_est.into());
+        self.h_x_true.push(self.x_true);
+        self.h_x_dr.push(self.x_dr);
+
+        if self.h_x_est.len() > N {
+            self.h_x_est.remove(0);
+            self.h_x_true.remove(0);
+            self.h_x_dr.remove(0);
+        }
+    }
+
+    /// Use `map` for localization and for the simulated lidar, rebuilding the
+    /// measurement model
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_map(&mut self, map: OccupancyGrid) {
+        self.model = ScanModel::new(self.model.kind(), &map);
+        self.map = map;
+    }
+}
+
+impl Simulate for Mcl {
+    fn get_state(&self) -> &dyn std::any::Any {
+        &self.x_true
+    }
+    fn match_state_with(&mut self, other: &dyn Simulate) {
+        if let Some(data) = other.get_state().downcast_ref::<State>() {
+            // Then set self's data from `other` if the type matches
+            self.x_true.clone_from(data);
+        }
+    }
+    fn step(&mut self, dt: f32) {
+        let u = calc_input();
+        let (_, ud) =
+            self.sensor
+                .observe(&mut self.rng, &mut self.x_true, &mut self.x_dr, u, &[], dt);
+        let pose = VehicleState::from(self.x_true).pose();
+        self.scan = self.lidar.scan(&mut self.rng, &pose, &self.map);
+        self.pf
+            .update_with(&mut self.rng, &self.model, &self.scan, ud, dt);
+
+        let Diagnostics { n_eff, .. } = self.pf.diagnostics;
+        let error = hypot(
+            self.pf.x_est.x() - self.x_true.x(),
+            self.pf.x_est.y() - self.x_true.y(),
+        );
+        self.diagnostics
+            .add(self.diagnostics.time_last() + dt, vec![n_eff, error]);
+
+        self.update_history();
+    }
+    fn reset_state(&mut self) {
+        self.rng = StdRng::seed_from_u64(self.seed);
+        self.x_true = zeros!(4, 1);
+        self.x_dr = zeros!(4, 1);
+        self.scan = Scan::default();
+        self.pf.x_est = VehicleState::default();
+        self.pf.set_num_particles(self.pf.num_particles());
+        self.h_x_est = vec![zeros!(4, 1)];
+        self.h_x_true = vec![zeros!(4, 1)];
+        self.h_x_dr = vec![zeros!(4, 1)];
+        self.diagnostics.clear();
+        self.init_time = 0.0;
+    }
+    fn reset_all(&mut self) {}
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+}
+
+impl Draw for Mcl {
+    fn plot(&self, plot_ui: &mut PlotUi) {
+        let names: Vec<String> = self
+            .diagnostics
+            .names()
+            .iter()
+            .map(|name| format!("{}_{}", name, self.id))
+            .collect();
+
+        (0..self.diagnostics.ncols()).for_each(|i| {
+            if let Some(values) = self.diagnostics.values_shifted(i, self.init_time, 0.0) {
+                plot_ui.line(Line::new(values).name(&names[i]));
+            }
+        });
+    }
+    fn scene(&self, plot_ui: &mut PlotUi) {
+        self.map_layer.draw(
+            plot_ui,
+            &self.map.probabilities(),
+            [
+                self.map.log_odds.min.x as f64,
+                self.map.log_odds.min.y as f64,
+            ],
+            self.map.resolution() as f64,
+            &format!("Map {}", self.id),
+        );
+        draw_scan(
+            plot_ui,
+            &self.scan,
+            &self.x_true,
+            &format!("Lidar {}", self.id),
+        );
+        plot_ui.points(
+            Points::new(Values::from_values(
+                self.pf.px.iter().map(|x| Value::new(x.x, x.y)).collect(),
+            ))
+            .name(format!("Particles {}", self.id)),
+        );
+
+        plot_ui.line(Line::new(self.h_x_true.positions()));
+        draw_vehicle(
+            plot_ui,
+            self.x_true,
+            &format!("Vehicle {} (Actual)", self.id),
+        );
+        plot_ui.line(Line::new(self.h_x_dr.positions()));
+        draw_vehicle(plot_ui, self.x_dr, &format!("Vehicle {} (DR)", self.id));
+        plot_ui.line(Line::new(self.h_x_est.positions()));
+        draw_vehicle(
+            plot_ui,
+            self.pf.x_est,
+            &format!("Vehicle {} (MCL)", self.id),
+        );
+        draw_covariance(
+            plot_ui,
+            &self.pf.x_est,
+            &self.pf.p_est,
+            &format!("Vehicle {} (MCL)", self.id),
+        );
+    }
+    fn options(&mut self, ui: &mut Ui) {
+        ui.group(|ui| {
+            ui.vertical(|ui| {
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label("Lidar:");
+                        lidar_options(ui, &mut self.lidar);
+                        noise_options(ui, &mut self.sensor.R);
+                    });
+                });
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label("Monte Carlo Localization:");
+                        // ui.push_id is used here to create unique ID for each `ComboBox`
+                        let mut kind = self.model.kind();
+                        ui.push_id(("mcl", self.id), |ui| {
+                            ComboBox::from_label("Measurement Model")
+                                .selected_text(kind.name())
+                                .show_ui(ui, |ui| {
+                                    for option in ScanModelKind::ALL {
+                                        ui.selectable_value(&mut kind, option, option.name());
+                                    }
+                                });
+                        });
+                        // Only rebuild on a change, keeping the tuned parameters
+                        if kind != self.model.kind() {
+                            self.model = ScanModel::new(kind, &self.map);
+                        }
+                        scan_model_options(ui, &mut self.model);
+                        let mut np = self.pf.num_particles();
+                        if ui
+                            .add(Slider::new(&mut np, 10..=3000).text("Particles"))
+                            .changed()
+                        {
+                            self.pf.set_num_particles(np);
+                        }
+                        if ui
+                            .button("Global Localization")
+                            .on_hover_text("Spread the particles over the free space of the map")
+                            .clicked()
+                        {
+                            self.pf.spread_over(&mut self.rng, &self.map);
+                        }
+                        noise_options(ui, &mut self.pf.R);
+                    });
+                });
+                map_file_options(ui, self);
+            });
+        });
+    }
+}
+
+/// Draw [`egui`] widgets for the weights and spread of a measurement model
+fn scan_model_options(ui: &mut Ui, model: &mut ScanModel) {
+    match model {
+        ScanModel::Beam(model) => {
+            ui.add(Slider::new(&mut model.z_hit, 0.0..=1.0).text("Hit Weight"));
+            ui.add(Slider::new(&mut model.z_short, 0.0..=1.0).text("Short Weight"));
+            ui.add(Slider::new(&mut model.z_max, 0.0..=1.0).text("Max Range Weight"));
+            ui.add(Slider::new(&mut model.z_rand, 0.0..=1.0).text("Random Weight"));
+            ui.add(Slider::new(&mut model.sigma_hit, 0.05..=2.0).text("Hit Std. Dev. [m]"));
+            ui.add(Slider::new(&mut model.max_beams, 1..=360).text("Beams Used"));
+        }
+        ScanModel::LikelihoodField(model) => {
+            ui.add(Slider::new(&mut model.z_hit, 0.0..=1.0).text("Hit Weight"));
+            ui.add(Slider::new(&mut model.z_rand, 0.0..=1.0).text("Random Weight"));
+            ui.add(Slider::new(&mut model.sigma_hit, 0.05..=2.0).text("Hit Std. Dev. [m]"));
+            ui.add(Slider::new(&mut model.max_beams, 1..=360).text("Beams Used"));
+        }
+    }
+}
+
+/// Draw [`egui`] widgets to load and save the map in the ROS `map_server` format
+#[cfg(not(target_arch = "wasm32"))]
+fn map_file_options(ui: &mut Ui, sim: &mut Mcl) {
+    ui.group(|ui| {
+        ui.vertical(|ui| {
+            ui.label("Map:");
+            ui.text_edit_singleline(&mut sim.map_path)
+                .on_hover_text("YAML file, next to its PGM image");
+            ui.horizontal(|ui| {
+                if ui.button("Load").clicked() {
+                    sim.map_status = match OccupancyGrid::load(&sim.map_path) {
+                        Ok(map) => {
+                            sim.set_map(map);
+                            format!("Loaded {}", sim.map_path)
+                        }
+                        Err(err) => format!("Failed to load {}: {err}", sim.map_path),
+                    };
+                }
+                if ui.button("Save").clicked() {
+                    sim.map_status = match sim.map.save(&sim.map_path) {
+                        Ok(()) => format!("Saved {}", sim.map_path),
+                        Err(err) => format!("Failed to save {}: {err}", sim.map_path),
+                    };
+                }
+                if ui.button("Survey").clicked() {
+                    sim.set_map(survey(&walls()));
+                    sim.map_status.clear();
+                }
+            });
+            if !sim.map_status.is_empty() {
+                ui.label(&sim.map_status);
+            }
+        });
+    });
+}
+
+/// Maps are surveyed from the world, as there is no file system on the web
+#[cfg(target_arch = "wasm32")]
+fn map_file_options(_ui: &mut Ui, _sim: &mut Mcl) {}
@@ -1,13 +1,24 @@
+pub mod double_pendulum;
+pub mod gnss;
 pub mod localization;
+pub mod mcl;
 pub mod pendulum;
+pub mod slam;
 
 use crate::prelude::*;
+use double_pendulum::DoubleInvertedPendulum;
+use gnss::GnssFusion;
 use localization::ParticleFilter;
+use mcl::Mcl;
 use pendulum::InvertedPendulum;
+use slam::Slam;
 
 use egui::{plot::PlotUi, *};
 use plot::{Corner, Legend, Plot};
 
+/// Seed of random number generators, unless set by the [`Simulator`]
+pub const DEFAULT_SEED: u64 = 0;
+
 /// Base trait to make simulation work within `rust robotics`.
 ///
 /// Users can implement this trait to make custom simulations.
@@ -41,6 +52,12 @@ pub trait Simulate {
     /// This is a hard reset on the simulation, instead of restarting the
     /// simulation with same parameters.
     fn reset_all(&mut self);
+
+    /// Set the seed of any random number generators used by the simulation
+    ///
+    /// The seed should take effect from the next [`Simulate::reset_state`], so
+    /// that restarting with the same seed replays the simulation exactly.
+    fn set_seed(&mut self, _seed: u64) {}
 }
 
 /// Trait to allow visually representing simulation (simulation graphics + GUI)
@@ -76,7 +93,16 @@ where
 
 pub enum SimType {
     InvertedPendulum,
+    /// Inverted pendulum with a payload unknown to its adaptive controller
+    AdaptivePendulum,
+    DoubleInvertedPendulum,
     Vehicle,
+    /// Vehicle mapping landmarks with EKF-SLAM
+    Slam,
+    /// Vehicle localizing with a lidar against an occupancy grid
+    Mcl,
+    /// Vehicle fusing GNSS fixes with odometry in an error-state EKF
+    Gnss,
 }
 
 /// A concrete type for containing simulations and executing them
@@ -93,6 +119,8 @@ pub struct Simulator {
     /// Settings to indicate whether to show the graph of simulation signals
     show_graph: bool,
     paused: bool,
+    /// Seed shared by the random number generators of all simulations
+    seed: u64,
 }
 
 impl Default for Simulator {
@@ -103,6 +131,7 @@ impl Default for Simulator {
             sim_speed: 2,
             show_graph: false,
             paused: false,
+            seed: DEFAULT_SEED,
         }
     }
 }
@@ -122,9 +151,11 @@ impl Simulator {
 
     /// Reset the states of all simulations within the currrent [`Simulator`]
     pub fn reset_state(&mut self) {
-        self.simulations
-            .iter_mut()
-            .for_each(|sim| sim.reset_state());
+        let seed = self.seed;
+        self.simulations.iter_mut().for_each(|sim| {
+            sim.set_seed(seed);
+            sim.reset_state();
+        });
     }
 
     /// Add a new simulation instance to the current [`Simulator`]
@@ -134,12 +165,37 @@ impl Simulator {
         //     .push(Box::new(InvertedPendulum::new(id, self.time)));
         match sim {
             SimType::InvertedPendulum => {
-                self.simulations
-                    .push(Box::new(InvertedPendulum::new(id, self.time)));
+                self.simulations.push(Box::new(
+                    InvertedPendulum::new(id, self.time).with_seed(self.seed),
+                ));
+            }
+            SimType::AdaptivePendulum => {
+                self.simulations.push(Box::new(
+                    InvertedPendulum::with_payload_mismatch(id, self.time).with_seed(self.seed),
+                ));
+            }
+            SimType::DoubleInvertedPendulum => {
+                self.simulations.push(Box::new(
+                    DoubleInvertedPendulum::new(id, self.time).with_seed(self.seed),
+                ));
             }
             SimType::Vehicle => {
+                self.simulations.push(Box::new(
+                    ParticleFilter::new(id, self.time).with_seed(self.seed),
+                ));
+            }
+            SimType::Slam => {
+                self.simulations
+                    .push(Box::new(Slam::new(id, self.time).with_seed(self.seed)));
+            }
+            SimType::Mcl => {
                 self.simulations
-                    .push(Box::new(ParticleFilter::new(id, self.time)));
+                    .push(Box::new(Mcl::new(id, self.time).with_seed(self.seed)));
+            }
+            SimType::Gnss => {
+                self.simulations.push(Box::new(
+                    GnssFusion::new(id, self.time).with_seed(self.seed),
+                ));
             }
         }
     }
@@ -161,7 +217,11 @@ impl Simulator {
             });
         });
 
-        ui.checkbox(&mut self.show_graph, "Show Graph");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.show_graph, "Show Graph");
+            ui.add(DragValue::new(&mut self.seed).prefix("Seed: "))
+                .on_hover_text("Applied on restart");
+        });
 
         ui.separator();
 
@@ -171,9 +231,7 @@ impl Simulator {
                 self.paused = !self.paused;
             }
             if ui.button("Restart").clicked() {
-                self.simulations
-                    .iter_mut()
-                    .for_each(|sim| sim.reset_state());
+                self.reset_state();
 
                 // Use the first simulation's states to sync with the rest of simulations
                 let (first, rest) = self.simulations.split_at_mut(1);
@@ -184,13 +242,29 @@ impl Simulator {
             }
             if ui.button("Reset All").clicked() {
                 self.simulations.iter_mut().for_each(|sim| sim.reset_all());
+                self.reset_state();
             }
             if ui.button("Add Pendulum").clicked() {
                 self.add(SimType::InvertedPendulum);
             }
+            if ui.button("Add Adaptive Pendulum").clicked() {
+                self.add(SimType::AdaptivePendulum);
+            }
+            if ui.button("Add Double Pendulum").clicked() {
+                self.add(SimType::DoubleInvertedPendulum);
+            }
             if ui.button("Add Vehicle").clicked() {
                 self.add(SimType::Vehicle);
             }
+            if ui.button("Add SLAM").clicked() {
+                self.add(SimType::Slam);
+            }
+            if ui.button("Add MCL").clicked() {
+                self.add(SimType::Mcl);
+            }
+            if ui.button("Add GNSS").clicked() {
+                self.add(SimType::Gnss);
+            }
         });
 
         ui.horizontal(|ui| {
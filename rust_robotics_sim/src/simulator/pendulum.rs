@@ -6,27 +6,37 @@ use crate::prelude::draw_cart;
 
 use egui::plot::Line;
 use egui::{plot::PlotUi, ComboBox, DragValue, Ui};
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rb::inverted_pendulum::*;
 use rb::prelude::*;
 use rust_robotics_algo as rb;
 
-use super::Simulate;
+use super::{Simulate, DEFAULT_SEED};
 
 pub type State = rb::Vector4;
+/// Measured cart position and rod angle
+pub type Output = rb::Vector2;
 
 /// Controller for the inverted pendulum simulation
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Controller {
     LQR(Model),
     PID(PID),
+    LQG(LQG),
+    Adaptive(AdaptiveLQR),
 }
 
 impl Controller {
-    pub fn control(&mut self, x: State, dt: f32) -> f32 {
+    /// Compute control input.
+    ///
+    /// [`LQR`](Controller::LQR) and [`PID`](Controller::PID) use the true state
+    /// `x`, while [`LQG`](Controller::LQG) only sees the noisy measurement `y`.
+    pub fn control(&mut self, x: State, y: Output, dt: f32) -> f32 {
         match self {
             Self::LQR(model) => *model.control(x, dt).index(0),
             Self::PID(pid) => pid.control(0.0 - x[2], dt),
+            Self::LQG(lqg) => lqg.control(y, dt),
+            Self::Adaptive(adaptive) => adaptive.control(x, dt),
         }
     }
     /// Instantiate a new LQR controller for [`InvertedPendulum`]
@@ -37,6 +47,16 @@ impl Controller {
     pub fn pid() -> Self {
         Self::PID(PID::with_gains(25.0, 3.0, 3.0))
     }
+    /// Instantiate a new LQG controller for [`InvertedPendulum`]
+    pub fn lqg(model: Model) -> Self {
+        Self::LQG(LQG::new(model))
+    }
+    /// Instantiate a new adaptive LQR controller for [`InvertedPendulum`]
+    ///
+    /// The masses of `model` are used as the initial guess for adaptation.
+    pub fn adaptive(model: Model) -> Self {
+        Self::Adaptive(AdaptiveLQR::new(model))
+    }
     /// Reset the states of the current [`Controller`]
     ///
     /// If there are parameters related to the controller (e.g. PID gains),
@@ -46,6 +66,8 @@ impl Controller {
         match self {
             Self::LQR(_) => (),
             Self::PID(pid) => pid.reset_state(),
+            Self::LQG(lqg) => lqg.reset_state(),
+            Self::Adaptive(adaptive) => adaptive.reset_state(),
         }
     }
     /// Reset the states and any parameters to it's default values
@@ -58,6 +80,12 @@ impl Controller {
         match self {
             Self::LQR(_) => *self = Self::lqr(Model::default()),
             Self::PID(_) => *self = Self::pid(),
+            Self::LQG(_) => *self = Self::lqg(Model::default()),
+            Self::Adaptive(adaptive) => {
+                // Keep the initial guess, which may differ from the plant
+                adaptive.reset_state();
+                *self = Self::adaptive(adaptive.model)
+            }
         }
     }
     /// Method to draw onto [`egui`] UI.
@@ -66,57 +94,54 @@ impl Controller {
             Self::LQR(model) => {
                 ui.vertical(|ui| {
                     ui.label("LQR Parameters:");
+                    lqr_options(ui, model);
+                });
+            }
+            Self::LQG(lqg) => {
+                ui.vertical(|ui| {
+                    ui.label("LQG Parameters:");
+                    lqr_options(ui, &mut lqg.model);
+                    ui.label("Measurement Noise Covariance");
                     ui.add(
-                        DragValue::new(&mut model.l_bar)
-                            .speed(0.01)
-                            .clamp_range(0.1_f32..=10.0)
-                            .prefix("Beam Length: ")
-                            .suffix(" m"),
+                        DragValue::new(lqg.kf.R.get_mut(0).unwrap())
+                            .speed(0.0001)
+                            .clamp_range(0.0001_f32..=1.0)
+                            .prefix("Lateral Position: "),
+                    );
+                    ui.add(
+                        DragValue::new(lqg.kf.R.get_mut(3).unwrap())
+                            .speed(0.0001)
+                            .clamp_range(0.0001_f32..=1.0)
+                            .prefix("Rod Angle: "),
                     );
+                });
+            }
+            Self::Adaptive(adaptive) => {
+                ui.vertical(|ui| {
+                    ui.label("Adaptive LQR Parameters:");
+                    let est = adaptive.estimates();
+                    ui.label(format!("Cart Mass Estimate: {:.2} kg", est[0]));
+                    ui.label(format!("Ball Mass Estimate: {:.2} kg", est[1]));
+                    ui.label("Initial Guess");
                     ui.add(
-                        DragValue::new(&mut model.m_cart)
+                        DragValue::new(adaptive.initial.get_mut(0).unwrap())
                             .speed(0.01)
                             .clamp_range(0.1_f32..=3.0)
                             .prefix("Cart Mass: ")
                             .suffix(" kg"),
                     );
                     ui.add(
-                        DragValue::new(&mut model.m_ball)
+                        DragValue::new(adaptive.initial.get_mut(1).unwrap())
                             .speed(0.01)
                             .clamp_range(0.1_f32..=10.0)
                             .prefix("Ball Mass: ")
                             .suffix(" kg"),
                     );
-                    ui.label("Weights");
-                    ui.add(
-                        DragValue::new(model.Q.get_mut(0).unwrap())
-                            .speed(0.01)
-                            .clamp_range(0.0_f32..=100.0)
-                            .prefix("Lateral Position: "),
-                    );
-                    ui.add(
-                        DragValue::new(model.Q.get_mut(5).unwrap())
-                            .speed(0.01)
-                            .clamp_range(0.0_f32..=100.0)
-                            .prefix("Lateral Velocity: "),
-                    );
-                    ui.add(
-                        DragValue::new(model.Q.get_mut(10).unwrap())
-                            .speed(0.01)
-                            .clamp_range(0.0_f32..=100.0)
-                            .prefix("Rod Angle: "),
-                    );
                     ui.add(
-                        DragValue::new(model.Q.get_mut(15).unwrap())
-                            .speed(0.01)
-                            .clamp_range(0.0_f32..=100.0)
-                            .prefix("Rod Angular Vel: "),
-                    );
-                    ui.add(
-                        DragValue::new(model.R.get_mut(0).unwrap())
-                            .speed(0.01)
-                            .clamp_range(0.0_f32..=100.0)
-                            .prefix("Control Input: "),
+                        DragValue::new(&mut adaptive.forgetting)
+                            .speed(0.001)
+                            .clamp_range(0.9_f32..=1.0)
+                            .prefix("Forgetting Factor: "),
                     );
                 });
             }
@@ -151,6 +176,8 @@ impl Controller {
         match self {
             Self::LQR(_) => "LQR".to_owned(),
             Self::PID(_) => "PID".to_owned(),
+            Self::LQG(_) => "LQG".to_owned(),
+            Self::Adaptive(_) => "Adaptive LQR".to_owned(),
         }
     }
 }
@@ -160,14 +187,25 @@ pub struct InvertedPendulum {
     state: State,
     controller: Controller,
     model: Model,
+    /// Plant model and controller the simulation was built with, restored by
+    /// [`reset_all`](Simulate::reset_all)
+    scenario: (Model, Controller),
+    /// Standard deviation of the noise on measured cart position and rod angle
+    sensor_noise: Output,
     id: usize,
     data: TimeTable,
+    /// True and estimated masses, logged while the controller is adaptive
+    estimates: TimeTable,
     time_init: f32,
+    /// Seed of `rng`, used again on every [`reset_state`](Simulate::reset_state)
+    seed: u64,
+    rng: StdRng,
 }
 
 impl Default for InvertedPendulum {
     fn default() -> Self {
-        let state = vector![0., 0., rand(0.4), 0.];
+        let mut rng = StdRng::seed_from_u64(DEFAULT_SEED);
+        let state = initial_state(&mut rng);
         let data = TimeTable::init_with_names(vec![
             "Lateral Position",
             "Lateral Velocity",
@@ -175,14 +213,25 @@ impl Default for InvertedPendulum {
             "Rod Angular Velocity",
             "Control Input",
         ]);
+        let estimates = TimeTable::init_with_names(vec![
+            "Cart Mass",
+            "Ball Mass",
+            "Cart Mass Estimate",
+            "Ball Mass Estimate",
+        ]);
 
         Self {
             state,
             controller: Controller::lqr(Model::default()),
             model: Model::default(),
+            scenario: (Model::default(), Controller::lqr(Model::default())),
+            sensor_noise: vector![0.05, 0.02],
             id: 1,
             time_init: 0.0,
             data,
+            estimates,
+            seed: DEFAULT_SEED,
+            rng,
         }
     }
 }
@@ -196,6 +245,30 @@ impl InvertedPendulum {
         }
     }
 
+    /// Scenario where the plant carries a heavier payload than the controller
+    /// assumes, stabilised by an [`AdaptiveLQR`] controller.
+    pub fn with_payload_mismatch(id: usize, time: f32) -> Self {
+        let model = Model {
+            m_ball: 3.0,
+            ..Default::default()
+        };
+        let controller = Controller::adaptive(Model::default());
+        Self {
+            model,
+            controller,
+            scenario: (model, controller),
+            ..Self::new(id, time)
+        }
+    }
+
+    /// Draw the initial state and measurement noise from `seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self.state = initial_state(&mut self.rng);
+        self
+    }
+
     pub fn x_position(&self) -> f32 {
         self.state[0]
     }
@@ -203,6 +276,14 @@ impl InvertedPendulum {
     pub fn rod_angle(&self) -> f32 {
         self.state[2]
     }
+
+    /// Noisy measurement of cart position and rod angle
+    pub fn measure(&mut self) -> Output {
+        vector![
+            self.x_position() + randn(&mut self.rng, self.sensor_noise[0]),
+            self.rod_angle() + randn(&mut self.rng, self.sensor_noise[1])
+        ]
+    }
 }
 
 impl Simulate for InvertedPendulum {
@@ -222,15 +303,17 @@ impl Simulate for InvertedPendulum {
         let (A, B) = self.model.model(dt);
 
         // Compute control command
-        let u = self.controller.control(x, dt);
+        let y = self.measure();
+        let u = self.controller.control(x, y, dt);
 
         // Update simulation based on control input
         x = A * x + B * u;
         self.state = x;
 
         // Log data
+        let time = self.data.time_last() + dt;
         self.data.add(
-            self.data.time_last() + dt,
+            time,
             vec![
                 self.state[0],
                 self.state[1],
@@ -239,34 +322,53 @@ impl Simulate for InvertedPendulum {
                 u,
             ],
         );
+        if let Controller::Adaptive(adaptive) = &self.controller {
+            let est = adaptive.estimates();
+            self.estimates.add(
+                time,
+                vec![self.model.m_cart, self.model.m_ball, est[0], est[1]],
+            );
+        }
     }
 
     fn reset_state(&mut self) {
-        self.state = vector![0., 0., rand(0.4), 0.];
+        self.rng = StdRng::seed_from_u64(self.seed);
+        self.state = initial_state(&mut self.rng);
         self.time_init = 0.0;
         self.controller.reset_state();
         self.data.clear();
+        self.estimates.clear();
     }
 
     fn reset_all(&mut self) {
-        *self = Self::default();
+        (self.model, self.controller) = self.scenario;
+        self.sensor_noise = Self::default().sensor_noise;
+        self.reset_state();
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
     }
 }
 
 impl Draw for InvertedPendulum {
     fn plot(&self, plot_ui: &mut PlotUi) {
-        let names: Vec<String> = self
-            .data
-            .names()
+        [&self.data, &self.estimates]
             .iter()
-            .map(|name| format!("{}_{}", name, self.id))
-            .collect();
+            .filter(|table| table.nrow() > 0)
+            .for_each(|table| {
+                let names: Vec<String> = table
+                    .names()
+                    .iter()
+                    .map(|name| format!("{}_{}", name, self.id))
+                    .collect();
 
-        (0..self.data.ncols()).for_each(|i| {
-            self.data
-                .values_shifted(i, self.time_init, 0.0)
-                .map(|values| plot_ui.line(Line::new(values).name(&names[i])));
-        });
+                (0..table.ncols()).for_each(|i| {
+                    table
+                        .values_shifted(i, self.time_init, 0.0)
+                        .map(|values| plot_ui.line(Line::new(values).name(&names[i])));
+                });
+            });
     }
 
     fn scene(&self, plot_ui: &mut PlotUi) {
@@ -318,9 +420,13 @@ impl Draw for InvertedPendulum {
                                     ComboBox::from_label("")
                                         .selected_text(self.controller.to_string())
                                         .show_ui(ui, |ui| {
-                                            for options in
-                                                [Controller::lqr(self.model), Controller::pid()]
-                                                    .iter()
+                                            for options in [
+                                                Controller::lqr(self.model),
+                                                Controller::pid(),
+                                                Controller::lqg(self.model),
+                                                Controller::adaptive(Model::default()),
+                                            ]
+                                            .iter()
                                             {
                                                 ui.selectable_value(
                                                     &mut self.controller,
@@ -333,6 +439,27 @@ impl Draw for InvertedPendulum {
                                 self.controller.options(ui);
                             });
                         });
+                        if let Controller::LQG(_) = self.controller {
+                            ui.group(|ui| {
+                                ui.vertical(|ui| {
+                                    ui.label("Sensor Noise (std):");
+                                    ui.add(
+                                        DragValue::new(self.sensor_noise.get_mut(0).unwrap())
+                                            .speed(0.001)
+                                            .clamp_range(0.0_f32..=1.0)
+                                            .prefix("Lateral Position: ")
+                                            .suffix(" m"),
+                                    );
+                                    ui.add(
+                                        DragValue::new(self.sensor_noise.get_mut(1).unwrap())
+                                            .speed(0.001)
+                                            .clamp_range(0.0_f32..=1.0)
+                                            .prefix("Rod Angle: ")
+                                            .suffix(" rad"),
+                                    );
+                                });
+                            });
+                        }
                     });
                 });
             });
@@ -340,6 +467,75 @@ impl Draw for InvertedPendulum {
     }
 }
 
-pub fn rand(max: f32) -> f32 {
-    rand::thread_rng().gen_range(-max..max)
+/// Draw [`egui`] widgets for tuning the model and weights of an LQR-based controller
+fn lqr_options(ui: &mut Ui, model: &mut Model) {
+    ui.add(
+        DragValue::new(&mut model.l_bar)
+            .speed(0.01)
+            .clamp_range(0.1_f32..=10.0)
+            .prefix("Beam Length: ")
+            .suffix(" m"),
+    );
+    ui.add(
+        DragValue::new(&mut model.m_cart)
+            .speed(0.01)
+            .clamp_range(0.1_f32..=3.0)
+            .prefix("Cart Mass: ")
+            .suffix(" kg"),
+    );
+    ui.add(
+        DragValue::new(&mut model.m_ball)
+            .speed(0.01)
+            .clamp_range(0.1_f32..=10.0)
+            .prefix("Ball Mass: ")
+            .suffix(" kg"),
+    );
+    ui.label("Weights");
+    ui.add(
+        DragValue::new(model.Q.get_mut(0).unwrap())
+            .speed(0.01)
+            .clamp_range(0.0_f32..=100.0)
+            .prefix("Lateral Position: "),
+    );
+    ui.add(
+        DragValue::new(model.Q.get_mut(5).unwrap())
+            .speed(0.01)
+            .clamp_range(0.0_f32..=100.0)
+            .prefix("Lateral Velocity: "),
+    );
+    ui.add(
+        DragValue::new(model.Q.get_mut(10).unwrap())
+            .speed(0.01)
+            .clamp_range(0.0_f32..=100.0)
+            .prefix("Rod Angle: "),
+    );
+    ui.add(
+        DragValue::new(model.Q.get_mut(15).unwrap())
+            .speed(0.01)
+            .clamp_range(0.0_f32..=100.0)
+            .prefix("Rod Angular Vel: "),
+    );
+    ui.add(
+        DragValue::new(model.R.get_mut(0).unwrap())
+            .speed(0.01)
+            .clamp_range(0.0_f32..=100.0)
+            .prefix("Control Input: "),
+    );
+}
+
+fn initial_state<R: Rng + ?Sized>(rng: &mut R) -> State {
+    vector![0., 0., rand(rng, 0.4), 0.]
+}
+
+/// Sample uniformly between [-max, max)
+pub fn rand<R: Rng + ?Sized>(rng: &mut R, max: f32) -> f32 {
+    rng.gen_range(-max..max)
+}
+
+/// Sample from a zero-mean normal distribution with standard deviation `std`
+pub fn randn<R: Rng + ?Sized>(rng: &mut R, std: f32) -> f32 {
+    // Box-Muller transform
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.gen();
+    std * (-2.0 * u1.ln()).sqrt() * (TAU * u2).cos()
 }
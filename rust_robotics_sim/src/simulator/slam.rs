@@ -0,0 +1,359 @@
+use super::localization::{
+    draw_covariance, marker_values, noise_options, values_from_marker_state, State, MARKERS,
+};
+use super::*;
+
+use crate::data::VehiclePlot;
+use crate::item::{draw_vehicle, Ellipse};
+
+use egui::plot::{Line, Points, Value, Values};
+use egui::{plot::PlotUi, ComboBox, DragValue, Slider, Ui};
+use rand::{rngs::StdRng, SeedableRng};
+use rb::localization::association::Association;
+use rb::localization::particle_filter::calc_input;
+use rb::localization::range_bearing::RangeBearingSensor;
+use rb::localization::StateVector;
+use rb::prelude::*;
+use rb::slam::{fast_slam::Version, EkfSlam, FastSlam, Landmark};
+use rust_robotics_algo as rb;
+
+const N: usize = 1000;
+
+/// Vehicle mapping the landmarks in [`MARKERS`] while localizing against them
+pub struct Slam {
+    x_true: State,
+    x_dr: State,
+    ekf: EkfSlam,
+    /// Whether to show the EKF-SLAM estimate and map
+    show_ekf: bool,
+    fast: FastSlam,
+    /// Whether to show the FastSLAM particles, and the map of the best one
+    show_fast: bool,
+    sensor: RangeBearingSensor,
+    h_x_est: Vec<State>,
+    /// FastSLAM particles and their ancestors at each step, to trace back the
+    /// trajectory of the best particle
+    h_particles: Vec<(Vec<VehicleState>, Vec<usize>)>,
+    h_x_true: Vec<State>,
+    h_x_dr: Vec<State>,
+    id: usize,
+    init_time: f32,
+    /// Seed of `rng`, used again on every [`reset_state`](Simulate::reset_state)
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Slam {
+    pub fn new(id: usize, time: f32) -> Self {
+        Self {
+            x_true: zeros!(4, 1),
+            x_dr: zeros!(4, 1),
+            ekf: EkfSlam::default(),
+            show_ekf: true,
+            fast: FastSlam::default(),
+            show_fast: true,
+            sensor: RangeBearingSensor::default(),
+            h_x_est: vec![zeros!(4, 1)],
+            h_particles: Vec::new(),
+            h_x_true: vec![zeros!(4, 1)],
+            h_x_dr: vec![zeros!(4, 1)],
+            id,
+            init_time: time,
+            seed: DEFAULT_SEED,
+            rng: StdRng::seed_from_u64(DEFAULT_SEED),
+        }
+    }
+
+    /// Draw sensor and particle noise from `seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn update_history(&mut self) {
+        self.h_x_est.push(self.ekf.state().into());
+        self.h_x_true.push(self.x_true);
+        self.h_x_dr.push(self.x_dr);
+        self.h_particles
+            .push((self.fast.px.clone(), self.fast.ancestors.clone()));
+
+        if self.h_x_est.len() > N {
+            self.h_x_est.remove(0);
+            self.h_particles.remove(0);
+            self.h_x_true.remove(0);
+            self.h_x_dr.remove(0);
+        }
+    }
+
+    /// Trajectory of the best FastSLAM particle, following its ancestors back
+    /// through resampling
+    fn best_trajectory(&self) -> Vec<State> {
+        let mut i = self.fast.best();
+        let mut trajectory: Vec<State> = self
+            .h_particles
+            .iter()
+            .rev()
+            .map_while(|(px, ancestors)| {
+                let x = px[i].into();
+                i = *ancestors.get(i)?;
+                Some(x)
+            })
+            .collect();
+        trajectory.reverse();
+        trajectory
+    }
+}
+
+impl Simulate for Slam {
+    fn get_state(&self) -> &dyn std::any::Any {
+        &self.x_true
+    }
+    fn match_state_with(&mut self, other: &dyn Simulate) {
+        if let Some(data) = other.get_state().downcast_ref::<State>() {
+            // Then set self's data from `other` if the type matches
+            self.x_true.clone_from(data);
+        }
+    }
+    fn step(&mut self, dt: f32) {
+        let u = calc_input();
+        let (z, ud) = self.sensor.observe(
+            &mut self.rng,
+            &mut self.x_true,
+            &mut self.x_dr,
+            u,
+            &MARKERS,
+            dt,
+        );
+        self.ekf.update(&z, ud, dt);
+        self.fast.update(&mut self.rng, &z, ud, dt);
+
+        self.update_history();
+    }
+    fn reset_state(&mut self) {
+        self.rng = StdRng::seed_from_u64(self.seed);
+        self.x_true = zeros!(4, 1);
+        self.x_dr = zeros!(4, 1);
+        self.ekf = EkfSlam {
+            Q: self.ekf.Q,
+            R: self.ekf.R,
+            association: self.ekf.association,
+            new_landmark_distance: self.ekf.new_landmark_distance,
+            ..EkfSlam::default()
+        };
+        self.fast.x_est = VehicleState::default();
+        self.fast.set_num_particles(self.fast.num_particles());
+        self.h_x_est = vec![zeros!(4, 1)];
+        self.h_particles.clear();
+        self.h_x_true = vec![zeros!(4, 1)];
+        self.h_x_dr = vec![zeros!(4, 1)];
+        self.init_time = 0.0;
+    }
+    fn reset_all(&mut self) {}
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+}
+
+impl Draw for Slam {
+    fn scene(&self, plot_ui: &mut PlotUi) {
+        plot_ui.points(
+            Points::new(marker_values())
+                .radius(2.0)
+                .name(format!("Landmarks {} (Actual)", self.id)),
+        );
+        MARKERS.iter().for_each(|marker| {
+            if range(&self.x_true, marker) <= self.sensor.max_range {
+                plot_ui.line(
+                    Line::new(values_from_marker_state(marker, &self.x_true))
+                        .style(plot::LineStyle::Dotted { spacing: 10.0 }),
+                );
+            }
+        });
+
+        plot_ui.line(Line::new(self.h_x_true.positions()));
+        draw_vehicle(
+            plot_ui,
+            self.x_true,
+            &format!("Vehicle {} (Actual)", self.id),
+        );
+        plot_ui.line(Line::new(self.h_x_dr.positions()));
+        draw_vehicle(plot_ui, self.x_dr, &format!("Vehicle {} (DR)", self.id));
+        if self.show_ekf {
+            let landmarks: Vec<Landmark> = (0..self.ekf.num_landmarks())
+                .map(|i| Landmark {
+                    x: self.ekf.landmark(i),
+                    P: self.ekf.landmark_covariance(i),
+                })
+                .collect();
+            draw_landmarks(
+                plot_ui,
+                &landmarks,
+                &format!("Landmarks {} (EKF-SLAM)", self.id),
+            );
+            plot_ui.line(Line::new(self.h_x_est.positions()));
+            draw_vehicle(
+                plot_ui,
+                self.ekf.state(),
+                &format!("Vehicle {} (EKF-SLAM)", self.id),
+            );
+            draw_covariance(
+                plot_ui,
+                &self.ekf.state(),
+                &self.ekf.pose_covariance(),
+                &format!("Vehicle {} (EKF-SLAM)", self.id),
+            );
+        }
+        if self.show_fast {
+            plot_ui.points(Points::new(Values::from_values(
+                self.fast.px.iter().map(|x| Value::new(x.x, x.y)).collect(),
+            )));
+            draw_landmarks(
+                plot_ui,
+                self.fast.best_map(),
+                &format!("Landmarks {} (FastSLAM)", self.id),
+            );
+            plot_ui.line(Line::new(self.best_trajectory().positions()));
+            draw_vehicle(
+                plot_ui,
+                self.fast.px[self.fast.best()],
+                &format!("Vehicle {} (FastSLAM)", self.id),
+            );
+        }
+    }
+    fn options(&mut self, ui: &mut Ui) {
+        ui.group(|ui| {
+            ui.vertical(|ui| {
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.label("Sensor:");
+                        ui.add(
+                            Slider::new(&mut self.sensor.max_range, 1.0..=50.0)
+                                .text("Max Range [m]"),
+                        );
+                        range_bearing_options(ui, &mut self.sensor.Q);
+                        noise_options(ui, &mut self.sensor.R);
+                    });
+                });
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.checkbox(&mut self.show_ekf, "EKF-SLAM:");
+                        association_options(ui, self.id, &mut self.ekf.association);
+                        ui.add(
+                            DragValue::new(&mut self.ekf.new_landmark_distance)
+                                .speed(1.0)
+                                .clamp_range(1.0_f32..=1000.0)
+                                .prefix("New Landmark Distance: "),
+                        )
+                        .on_hover_text("Squared Mahalanobis distance to the nearest landmark");
+                        range_bearing_options(ui, &mut self.ekf.Q);
+                        noise_options(ui, &mut self.ekf.R);
+                    });
+                });
+                ui.group(|ui| {
+                    ui.vertical(|ui| {
+                        ui.checkbox(&mut self.show_fast, "FastSLAM:");
+                        // ui.push_id is used here to create unique ID for each `ComboBox`
+                        ui.push_id(("fast_slam", self.id), |ui| {
+                            ComboBox::from_label("Version")
+                                .selected_text(self.fast.version.name())
+                                .show_ui(ui, |ui| {
+                                    for option in Version::ALL {
+                                        ui.selectable_value(
+                                            &mut self.fast.version,
+                                            option,
+                                            option.name(),
+                                        );
+                                    }
+                                });
+                        });
+                        let mut np = self.fast.num_particles();
+                        if ui
+                            .add(Slider::new(&mut np, 10..=500).text("Particles"))
+                            .changed()
+                        {
+                            self.fast.set_num_particles(np);
+                            self.h_particles.clear();
+                        }
+                        ui.add(
+                            DragValue::new(&mut self.fast.new_landmark_distance)
+                                .speed(1.0)
+                                .clamp_range(1.0_f32..=1000.0)
+                                .prefix("New Landmark Distance: "),
+                        )
+                        .on_hover_text("Squared Mahalanobis distance to the nearest landmark");
+                        range_bearing_options(ui, &mut self.fast.Q);
+                        noise_options(ui, &mut self.fast.R);
+                    });
+                });
+            });
+        });
+    }
+}
+
+/// Draw estimated `landmarks` with the 2-sigma ellipses of their covariance
+fn draw_landmarks(plot_ui: &mut PlotUi, landmarks: &[Landmark], name: &str) {
+    plot_ui.points(
+        Points::new(Values::from_values(
+            landmarks
+                .iter()
+                .map(|landmark| Value::new(landmark.x.x, landmark.x.y))
+                .collect(),
+        ))
+        .radius(3.0)
+        .shape(plot::MarkerShape::Cross)
+        .name(name),
+    );
+    landmarks.iter().for_each(|landmark| {
+        plot_ui.polygon(
+            Ellipse::from_covariance(&landmark.P, 2.0)
+                .at(landmark.x.x as f64, landmark.x.y as f64)
+                .into_polygon()
+                .name(name),
+        );
+    });
+}
+
+fn range(x: &State, marker: &rb::Vector2) -> f32 {
+    hypot(marker.x - x.x(), marker.y - x.y())
+}
+
+/// Draw [`egui`] widgets for the data association algorithm and its confidence
+pub(super) fn association_options(ui: &mut Ui, id: usize, association: &mut Association) {
+    // ui.push_id is used here to create unique ID for each `ComboBox`
+    ui.push_id(id, |ui| {
+        ComboBox::from_label("Association")
+            .selected_text(association.name())
+            .show_ui(ui, |ui| {
+                for option in [Association::NearestNeighbor(0.99), Association::JCBB(0.99)] {
+                    if ui
+                        .selectable_label(association.name() == option.name(), option.name())
+                        .clicked()
+                    {
+                        *association = option;
+                    }
+                }
+            });
+    });
+    match association {
+        Association::NearestNeighbor(confidence) | Association::JCBB(confidence) => {
+            ui.add(Slider::new(confidence, 0.5..=0.999).text("Gate Confidence"));
+        }
+    }
+}
+
+/// Draw [`egui`] widgets for the diagonal of range-bearing noise covariance
+pub(super) fn range_bearing_options(ui: &mut Ui, cov: &mut rb::Matrix2) {
+    ui.add(
+        DragValue::new(&mut cov[(0, 0)])
+            .speed(0.001)
+            .clamp_range(0.0001_f32..=10.0)
+            .prefix("Range Variance: "),
+    );
+    ui.add(
+        DragValue::new(&mut cov[(1, 1)])
+            .speed(0.0001)
+            .clamp_range(0.00001_f32..=1.0)
+            .prefix("Bearing Variance: "),
+    );
+}